@@ -0,0 +1,258 @@
+use crate::lexer::Span;
+use crate::number::{Number, NumberMode};
+use std::fmt;
+
+/// 式の抽象構文木
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span, // エラーの表示に使う位置（演算子、名前、リテラル）
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(String),                         // 数値リテラル（評価時のモードで読む）
+    Constant(Number),                       // 定数畳み込みで計算済みの値
+    Variable(String),                       // 変数または組み込みの定数
+    Memory(String),                         // memX
+    History(usize),                         // $n
+    Unary(UnaryOp, Box<Expr>),              // -x、~x
+    Binary(BinaryOp, Box<Expr>, Box<Expr>), // 二項演算
+    Call(String, Vec<Expr>),                // 関数呼び出し
+    Convert(Box<Expr>, Box<Expr>),          // 単位の変換（x to km）
+    List(Vec<Expr>),                        // ベクトル・行列のリテラル（[1, 2]、[[1, 2], [3, 4]]）
+    Equation(Box<Expr>, Box<Expr>),         // 方程式（solve の引数の x^2 = 2）
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Rem,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+/// 演算子の優先順位（大きいほど強く結合する）
+mod precedence {
+    pub const CONVERSION: u8 = 0;
+    pub const BIT_OR: u8 = 1;
+    pub const BIT_XOR: u8 = 2;
+    pub const BIT_AND: u8 = 3;
+    pub const SHIFT: u8 = 4;
+    pub const ADDITIVE: u8 = 5;
+    pub const MULTIPLICATIVE: u8 = 6;
+    pub const UNARY: u8 = 7;
+    pub const POWER: u8 = 8;
+    pub const PRIMARY: u8 = 9;
+}
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::BitOr => precedence::BIT_OR,
+            BinaryOp::BitXor => precedence::BIT_XOR,
+            BinaryOp::BitAnd => precedence::BIT_AND,
+            BinaryOp::Shl | BinaryOp::Shr => precedence::SHIFT,
+            BinaryOp::Add | BinaryOp::Sub => precedence::ADDITIVE,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem => {
+                precedence::MULTIPLICATIVE
+            }
+            BinaryOp::Pow => precedence::POWER,
+        }
+    }
+
+    /// 演算子の表記。整数モードでは ^ が排他的論理和のため、べき乗は ** にする
+    pub fn symbol(self, mode: NumberMode) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::FloorDiv => "//",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow if mode.is_integer() => "**",
+            BinaryOp::Pow => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExprKind::Unary(..) => precedence::UNARY,
+            ExprKind::Binary(op, ..) => op.precedence(),
+            ExprKind::Convert(..) | ExprKind::Equation(..) => precedence::CONVERSION,
+            // 計算済みの値は -5 や 1/3 のように表示されることがある
+            ExprKind::Constant(value) => {
+                let text = value.to_string();
+                if text.starts_with('-') {
+                    precedence::UNARY
+                } else if text.contains('/') {
+                    precedence::MULTIPLICATIVE
+                } else {
+                    precedence::PRIMARY
+                }
+            }
+            _ => precedence::PRIMARY,
+        }
+    }
+
+    /// 括弧を最小限にして式を表示する。modeは演算子の表記に使う
+    pub fn display(&self, mode: NumberMode) -> ExprDisplay<'_> {
+        ExprDisplay { expr: self, mode }
+    }
+}
+
+/// Expr::displayの結果
+pub struct ExprDisplay<'a> {
+    expr: &'a Expr,
+    mode: NumberMode,
+}
+
+impl ExprDisplay<'_> {
+    /// exprを表示する。優先順位がmin_precedenceより低ければ括弧で囲む
+    fn write(&self, f: &mut fmt::Formatter<'_>, expr: &Expr, min_precedence: u8) -> fmt::Result {
+        if expr.precedence() < min_precedence {
+            write!(f, "(")?;
+            self.write_expr(f, expr)?;
+            write!(f, ")")
+        } else {
+            self.write_expr(f, expr)
+        }
+    }
+
+    /// 引数や要素をカンマで区切って表示する
+    fn write_list(&self, f: &mut fmt::Formatter<'_>, exprs: &[Expr]) -> fmt::Result {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            self.write(f, expr, precedence::CONVERSION)?;
+        }
+        Ok(())
+    }
+
+    fn write_expr(&self, f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+        match &expr.kind {
+            ExprKind::Number(text) => write!(f, "{}", text),
+            ExprKind::Constant(value) => write!(f, "{}", value),
+            ExprKind::Variable(name) => write!(f, "{}", name),
+            ExprKind::Memory(name) => write!(f, "mem{}", name),
+            ExprKind::History(n) => write!(f, "${}", n),
+            ExprKind::Unary(op, operand) => {
+                let symbol = match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "~",
+                };
+                write!(f, "{}", symbol)?;
+                self.write(f, operand, precedence::UNARY)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let p = op.precedence();
+                // べき乗は右結合で、右辺に単項演算子を書ける（2 ^ -1）。それ以外は左結合
+                let (lhs_min, rhs_min) = if *op == BinaryOp::Pow {
+                    (p + 1, precedence::UNARY)
+                } else {
+                    (p, p + 1)
+                };
+                self.write(f, lhs, lhs_min)?;
+                write!(f, " {} ", op.symbol(self.mode))?;
+                self.write(f, rhs, rhs_min)
+            }
+            ExprKind::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                self.write_list(f, args)?;
+                write!(f, ")")
+            }
+            ExprKind::List(elements) => {
+                write!(f, "[")?;
+                self.write_list(f, elements)?;
+                write!(f, "]")
+            }
+            ExprKind::Convert(value, unit) => {
+                self.write(f, value, precedence::BIT_OR)?;
+                write!(f, " to ")?;
+                self.write(f, unit, precedence::BIT_OR)
+            }
+            // 両辺は x to m = 5 m のように変換も括弧なしで書ける
+            ExprKind::Equation(lhs, rhs) => {
+                self.write(f, lhs, precedence::CONVERSION)?;
+                write!(f, " = ")?;
+                self.write(f, rhs, precedence::CONVERSION)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExprDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, self.expr, precedence::CONVERSION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Token;
+    use crate::number::{IntType, NumberMode};
+    use crate::parser::parse;
+
+    fn reprint(line: &str, mode: NumberMode) -> String {
+        let tokens = Token::tokenize(line).unwrap();
+        parse(&tokens, mode).unwrap().display(mode).to_string()
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let mode = NumberMode::Float;
+        assert_eq!("1 + 2 * 3", reprint("(1 + (2 * 3))", mode));
+        assert_eq!("(1 + 2) * 3", reprint("((1 + 2)) * 3", mode));
+        assert_eq!("1 - (2 - 3)", reprint("1 - (2 - 3)", mode));
+        assert_eq!("1 - 2 - 3", reprint("(1 - 2) - 3", mode));
+        assert_eq!("2 ^ 3 ^ 2", reprint("2 ^ (3 ^ 2)", mode));
+        assert_eq!("(2 ^ 3) ^ 2", reprint("(2 ^ 3) ^ 2", mode));
+        assert_eq!("-2 ^ 2", reprint("-(2 ^ 2)", mode));
+        assert_eq!("(-2) ^ 2", reprint("(-2) ^ 2", mode));
+        assert_eq!("2 ^ -1", reprint("2 ^ (-1)", mode));
+        assert_eq!("--x", reprint("-(-x)", mode));
+        assert_eq!("max(1 + 2, memA)", reprint("max((1 + 2), memA)", mode));
+        assert_eq!("5 * km + 3 * m to ft", reprint("(5 km + 3 m) in ft", mode));
+        assert_eq!("(x to m) * 2", reprint("(x to m) * 2", mode));
+        assert_eq!(
+            "[[1, 2], [x, 3 * m]]",
+            reprint("[[(1), 2], [x, (3 m)]]", mode)
+        );
+    }
+
+    #[test]
+    fn test_integer_operators() {
+        let mode = NumberMode::Integer(IntType::parse("u8", false).unwrap());
+        assert_eq!("1 | 2 ^ 3 & 4", reprint("1 | (2 ^ (3 & 4))", mode));
+        assert_eq!("(1 | 2) & 0xf", reprint("(1 | 2) & 0xf", mode));
+        assert_eq!("1 << 2 + 3", reprint("1 << (2 + 3)", mode));
+        assert_eq!("2 ** 3", reprint("2 ** 3", mode));
+        assert_eq!("~(1 + 2)", reprint("~(1 + 2)", mode));
+    }
+}
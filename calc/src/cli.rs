@@ -0,0 +1,226 @@
+use crate::repl::{Outcome, State};
+use std::fmt;
+use std::path::PathBuf;
+
+/// コマンドラインの使い方
+pub const USAGE: &str = "\
+usage: calc [--json] [-e expression]... | [--json] [file]
+    -e, --eval expression  evaluate the expression and exit (may be repeated)
+    --json                 print one {\"input\", \"value\", \"error\"} record per line
+    -h, --help             show this help
+    file                   run the lines of a script ('#' starts a comment)
+with no expression or file, lines are read from standard input";
+
+/// 読む行の出所
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Stdin,                    // 対話的な入力、またはパイプ
+    Expressions(Vec<String>), // -e で与えた式
+    Script(PathBuf),          // スクリプトファイル
+}
+
+/// 結果の表示形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Repl,  // $n => 値
+    Plain, // 値だけ（-e）
+    Json,  // 1行に1つのJSONの記録
+}
+
+/// コマンドラインの指定
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub input: Input,
+    pub format: Format,
+    pub help: bool,
+}
+
+/// コマンドラインの誤り
+#[derive(Debug, PartialEq)]
+pub enum OptionError {
+    MissingValue(String), // 値のないオプション（-e）
+    Unknown(String),      // 知らないオプション
+    Conflict,             // -e とファイル、または複数のファイル
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::MissingValue(option) => write!(f, "option {} needs a value", option),
+            OptionError::Unknown(option) => write!(f, "unknown option {}", option),
+            OptionError::Conflict => write!(f, "give either -e expressions or one script file"),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+impl Options {
+    /// プログラム名を除いた引数を読む。- で始まる値は -e の後か -- の後に書く
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, OptionError> {
+        let mut expressions = Vec::new();
+        let mut scripts = Vec::new();
+        let mut json = false;
+        let mut help = false;
+        let mut args = args.into_iter();
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                _ if options_done => scripts.push(PathBuf::from(arg)),
+                "-e" | "--eval" => match args.next() {
+                    Some(expression) => expressions.push(expression),
+                    None => return Err(OptionError::MissingValue(arg)),
+                },
+                "--json" => json = true,
+                "-h" | "--help" => help = true,
+                "--" => options_done = true,
+                _ if arg.starts_with('-') && arg != "-" => return Err(OptionError::Unknown(arg)),
+                _ => scripts.push(PathBuf::from(arg)),
+            }
+        }
+
+        // - は標準入力
+        scripts.retain(|path| path.as_os_str() != "-");
+        let input = match (expressions.is_empty(), scripts.len()) {
+            (true, 0) => Input::Stdin,
+            (true, 1) => Input::Script(scripts.remove(0)),
+            (false, 0) => Input::Expressions(expressions),
+            _ => return Err(OptionError::Conflict),
+        };
+        let format = match (&input, json) {
+            (_, true) => Format::Json,
+            (Input::Expressions(_), false) => Format::Plain,
+            _ => Format::Repl,
+        };
+        Ok(Self {
+            input,
+            format,
+            help,
+        })
+    }
+}
+
+/// 1行の実行結果のJSONの記録。空行ではNone
+///
+/// valueには値と導関数の式だけを入れ、定義やコマンドの通知、一覧、グラフはnullにする。
+pub fn json_outcome(line: &str, outcome: &Outcome, state: &State) -> Option<String> {
+    let (value, error) = match outcome {
+        Outcome::Empty => return None,
+        Outcome::Result(_, value) | Outcome::Value(value) => {
+            (Some(state.format_value(value)), None)
+        }
+        Outcome::Derivative(expr) => (Some(expr.clone()), None),
+        Outcome::Defined(_) | Outcome::Message(_) | Outcome::Text(_) => (None, None),
+        Outcome::Error(_, e) => (None, Some(e.to_string())),
+        Outcome::Failure(message) => (None, Some(message.clone())),
+    };
+    Some(json_record(line.trim(), value.as_deref(), error.as_deref()))
+}
+
+/// 1行の入力の結果を表す1行のJSON
+pub fn json_record(input: &str, value: Option<&str>, error: Option<&str>) -> String {
+    let field = |text: Option<&str>| text.map_or("null".to_string(), json_string);
+    format!(
+        "{{\"input\": {}, \"value\": {}, \"error\": {}}}",
+        json_string(input),
+        field(value),
+        field(error)
+    )
+}
+
+/// JSONの文字列リテラル
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_outcome, json_record, Format, Input, OptionError, Options};
+    use crate::repl::{run_line, State};
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> Result<Options, OptionError> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(
+            (Input::Stdin, Format::Repl),
+            (options.input, options.format)
+        );
+        let options = parse(&["-e", "1+2", "--eval", "-3"]).unwrap();
+        let expressions = vec!["1+2".to_string(), "-3".to_string()];
+        assert_eq!(Input::Expressions(expressions), options.input);
+        assert_eq!(Format::Plain, options.format);
+        let options = parse(&["--json", "run.calc"]).unwrap();
+        let script = Input::Script(PathBuf::from("run.calc"));
+        assert_eq!((script, Format::Json), (options.input, options.format));
+        assert_eq!(Input::Stdin, parse(&["-", "--json"]).unwrap().input);
+        assert!(parse(&["-h"]).unwrap().help);
+        assert_eq!(
+            Input::Script(PathBuf::from("-x.calc")),
+            parse(&["--", "-x.calc"]).unwrap().input
+        );
+
+        assert_eq!(
+            Err(OptionError::MissingValue("-e".to_string())),
+            parse(&["-e"])
+        );
+        assert_eq!(Err(OptionError::Unknown("-x".to_string())), parse(&["-x"]));
+        assert_eq!(Err(OptionError::Conflict), parse(&["-e", "1", "a.calc"]));
+        assert_eq!(Err(OptionError::Conflict), parse(&["a.calc", "b.calc"]));
+    }
+
+    #[test]
+    fn test_json_record() {
+        assert_eq!(
+            r#"{"input": "1+2", "value": "3", "error": null}"#,
+            json_record("1+2", Some("3"), None)
+        );
+        assert_eq!(
+            r#"{"input": "\"a\\b\"", "value": null, "error": "line\n\u0001"}"#,
+            json_record("\"a\\b\"", None, Some("line\n\u{1}"))
+        );
+    }
+
+    #[test]
+    fn test_json_outcome() {
+        let mut state = State::new();
+        let mut json = |line: &str| {
+            let outcome = run_line(line, &mut state);
+            json_outcome(line, &outcome, &state)
+        };
+        assert_eq!(None, json("# comment"));
+        assert_eq!(
+            Some(r#"{"input": "f(x) = x^2", "value": null, "error": null}"#.to_string()),
+            json("f(x) = x^2")
+        );
+        assert_eq!(
+            Some(r#"{"input": "diff(f(x), x)", "value": "2 * x", "error": null}"#.to_string()),
+            json("diff(f(x), x)")
+        );
+        assert_eq!(
+            Some(r#"{"input": "f(3)", "value": "9", "error": null}"#.to_string()),
+            json("f(3)")
+        );
+        assert_eq!(
+            Some(r#"{"input": ":deg", "value": null, "error": null}"#.to_string()),
+            json(":deg")
+        );
+    }
+}
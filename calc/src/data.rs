@@ -0,0 +1,106 @@
+use crate::number::{Number, NumberMode};
+use crate::value::Quantity;
+use std::{fmt, io};
+
+/// データの読み込みの失敗
+#[derive(Debug)]
+pub enum DataError {
+    Io(io::Error),
+    NoColumn(String), // 指定の列がない
+    NotNumber(usize), // 数でない値のある行（1始まり）
+    Empty,            // 数が1つもない
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Io(e) => write!(f, "{}", e),
+            DataError::NoColumn(column) => write!(f, "no column '{}'", column),
+            DataError::NotNumber(line) => write!(f, "not a number at line {}", line),
+            DataError::Empty => write!(f, "no data"),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl From<io::Error> for DataError {
+    fn from(e: io::Error) -> Self {
+        DataError::Io(e)
+    }
+}
+
+/// CSVなどの表のcolumnの列の数を読む
+///
+/// 区切りはカンマ、タブ、セミコロン、空白の順に最初の行にあるものを使う。columnは1始まりの列番号か
+/// 見出しの名前。最初の行の値が数でなければ見出しの行とみなす。空の値と # で始まる行は読み飛ばす。
+pub fn column(text: &str, column: &str, mode: NumberMode) -> Result<Vec<Quantity>, DataError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .peekable();
+    let Some(&(_, first)) = lines.peek() else {
+        return Err(DataError::Empty);
+    };
+    let delimiter = [',', '\t', ';'].into_iter().find(|&c| first.contains(c));
+    let split = |line: &str| -> Vec<String> {
+        let cells: Vec<&str> = match delimiter {
+            Some(c) => line.split(c).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        cells
+            .into_iter()
+            .map(|cell| cell.trim().trim_matches('"').to_string())
+            .collect()
+    };
+
+    let header = split(first);
+    let index = match column.parse::<usize>() {
+        Ok(n) if (1..=header.len()).contains(&n) => n - 1,
+        _ => header
+            .iter()
+            .position(|name| name == column)
+            .ok_or_else(|| DataError::NoColumn(column.to_string()))?,
+    };
+    if Number::parse(&header[index], mode).is_err() {
+        lines.next();
+    }
+
+    let mut values = Vec::new();
+    for (i, line) in lines {
+        let cells = split(line);
+        let Some(cell) = cells.get(index).filter(|cell| !cell.is_empty()) else {
+            continue;
+        };
+        let number = Number::parse(cell, mode).map_err(|_| DataError::NotNumber(i + 1))?;
+        values.push(Quantity::new(number));
+    }
+    if values.is_empty() {
+        return Err(DataError::Empty);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{column, DataError};
+    use crate::number::NumberMode;
+
+    fn read(text: &str, name: &str) -> Result<Vec<String>, DataError> {
+        column(text, name, NumberMode::Float)
+            .map(|values| values.iter().map(|q| q.to_string()).collect())
+    }
+
+    #[test]
+    fn test_column() {
+        let csv = "name,temp\n\"a\",20.5\n# comment\nb,\n\nc,19\n";
+        assert_eq!(vec!["20.5", "19"], read(csv, "temp").unwrap());
+        assert_eq!(vec!["20.5", "19"], read(csv, "2").unwrap());
+        assert_eq!(vec!["1", "4"], read("1\t2\n4\t5\n", "1").unwrap());
+        assert_eq!(vec!["2", "5"], read("1 2\n4  5\n", "2").unwrap());
+        assert!(matches!(read(csv, "name"), Err(DataError::NotNumber(2))));
+        assert!(matches!(read(csv, "3"), Err(DataError::NoColumn(_))));
+        assert!(matches!(read("x\n\n", "x"), Err(DataError::Empty)));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::repl;
+use calc::Context;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Editor, Helper};
+use std::io::{stdin, IsTerminal};
+use std::path::PathBuf;
+
+/// 端末での入力の促し
+const PROMPT: &str = "> ";
+
+/// 履歴ファイルに残す行数
+const HISTORY_SIZE: usize = 1000;
+
+/// 入力行の読み込み
+///
+/// 端末では矢印キーでの編集、Ctrl-R での履歴の検索、Tab での名前の補完ができ、
+/// 履歴は設定ディレクトリのファイルに残る。端末でなければ標準入力から1行ずつ読む。
+pub enum LineReader {
+    Terminal {
+        editor: Box<Editor<Completion, FileHistory>>,
+        history: Option<PathBuf>,
+    },
+    Pipe,
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        if !stdin().is_terminal() {
+            return LineReader::Pipe;
+        }
+        let config = Config::builder()
+            .max_history_size(HISTORY_SIZE)
+            .and_then(|config| config.history_ignore_dups(true))
+            .map(|config| config.completion_type(CompletionType::List).build());
+        let Ok(mut editor) = config.and_then(Editor::with_config) else {
+            return LineReader::Pipe;
+        };
+        editor.set_helper(Some(Completion::default()));
+        // 初回は履歴ファイルがない
+        let history = repl::history_path();
+        if let Some(path) = history.as_ref().filter(|path| path.exists()) {
+            if let Err(e) = editor.load_history(path) {
+                println!("error: {}: {}", path.display(), e);
+            }
+        }
+        LineReader::Terminal {
+            editor: Box::new(editor),
+            history,
+        }
+    }
+
+    /// 1行読む（改行は除く）。入力の終わり（Ctrl-D）ではNone
+    ///
+    /// Ctrl-C は入力中の行を捨てて次の行を読む。補完の候補はctxの名前から作る。
+    pub fn read_line(&mut self, ctx: &Context) -> Option<String> {
+        let LineReader::Terminal { editor, .. } = self else {
+            return repl::read_line();
+        };
+        if let Some(completion) = editor.helper_mut() {
+            completion.names = ctx.names();
+        }
+        loop {
+            match editor.readline(PROMPT) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    return Some(line);
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return None,
+                Err(e) => {
+                    println!("error: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// 端末から読んでいるか
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, LineReader::Terminal { .. })
+    }
+
+    /// 履歴をファイルに保存する
+    pub fn save_history(&mut self) {
+        let LineReader::Terminal {
+            editor,
+            history: Some(path),
+        } = self
+        else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = editor.save_history(path) {
+            println!("error: {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Tab で入力中の名前を補完する
+#[derive(Default)]
+pub struct Completion {
+    names: Vec<String>,
+}
+
+impl Completion {
+    /// カーソルの前の名前の開始位置と、それで始まる候補
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let is_name = |c: char| c.is_alphanumeric() || c == '_';
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_name(c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        // 数値リテラルの途中（2e、0x1f）は補完しない
+        if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+            return (pos, Vec::new());
+        }
+        let candidates = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        (start, candidates)
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod tests {
+    use super::Completion;
+    use calc::Context;
+
+    #[test]
+    fn test_completion() {
+        let mut ctx = Context::new();
+        ctx.define("speed = 1").unwrap();
+        ctx.eval_line("2").unwrap();
+        ctx.eval_line("memA+").unwrap();
+        let completion = Completion { names: ctx.names() };
+
+        assert_eq!(
+            (4, vec!["sqrt(".to_string()]),
+            completion.candidates("1 + sq", 6)
+        );
+        assert_eq!(
+            (0, vec!["speed".to_string()]),
+            completion.candidates("sp * 2", 2)
+        );
+        assert_eq!(
+            (4, vec!["memA".to_string()]),
+            completion.candidates("2 * mem", 7)
+        );
+        assert_eq!(
+            (0, vec!["tan(".to_string(), "tau".to_string()]),
+            completion.candidates("ta", 2)
+        );
+        assert_eq!((2, Vec::<String>::new()), completion.candidates("2e", 2));
+        assert_eq!((3, Vec::<String>::new()), completion.candidates("1 +", 3));
+    }
+}
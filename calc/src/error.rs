@@ -0,0 +1,178 @@
+use crate::lexer::{LexError, Span};
+use crate::number::ArithmeticError;
+use crate::unit::Unit;
+use crate::value::ValueError;
+use std::fmt;
+
+/// 式の評価で発生するエラー。いずれも入力中の位置を持つ
+#[derive(Debug, PartialEq)]
+pub enum CalcError {
+    Lex(LexError),                            // 不正な文字
+    UnexpectedToken(Span),                    // 式の途中に現れた想定外のトークン
+    UnexpectedEnd(Span),                      // 式の途中で入力が終わった
+    UnbalancedParen(Span),                    // 対応する括弧（角括弧も）がない
+    TrailingInput(Span),                      // 式の後に余分な入力がある
+    DivisionByZero(Span),                     // 0での除算
+    Overflow(Span),                           // 整数型の範囲外、範囲外のシフト量、大きすぎる数
+    NotInteger(Span),                         // 整数が必要な所に整数でない値
+    NotReal(Span),                            // 実数が必要な所に複素数
+    IncompatibleUnits(String, String, Span),  // 次元の違う単位（左辺、右辺または変換先の単位）
+    UnitNotAllowed(Span),                     // 単位なしの数が必要な所に単位のある値
+    UnitPower(Span),                          // 単位のある値の整数でない指数
+    NotScalar(Span),                          // 数が必要な所にベクトルや行列
+    ShapeMismatch(String, Span),              // ベクトル・行列の形が合わない（説明）
+    SingularMatrix(Span),                     // 逆行列のない行列
+    InvalidConversion(Span),                  // to、in の右辺が単位でない
+    UnknownIdentifier(String, Span),          // 未定義の名前
+    NoResult(String, Span),                   // まだない結果（$n、ans）の参照
+    UnknownFunction(String, Span),            // 未定義の関数
+    NotVariable(Span),                        // 変数の名前が必要な所にそれ以外の式
+    NotDifferentiable(String, Span),          // 微分できない関数・演算子
+    MisplacedEquation(Span),                  // solve の引数以外にある方程式
+    NoConvergence(Span),                      // 方程式の解が求まらない
+    MissingPoint(Span),                       // 評価する点のない式の中の diff(f, x)
+    ArgumentCount(String, String, Span),      // 引数の個数の誤り（関数名、受け付ける個数）
+    Domain(String, Span),                     // 定義域外の引数
+    OutOfRange(String, Span),                 // 有限の引数で結果が大きすぎる（関数名）
+    InvalidAssignment(Span),                  // 代入できない左辺
+    ReservedName(String, Span),               // 組み込みの名前への代入
+    DuplicateParameter(String, Span),         // 同じ名前の仮引数
+    RecursionLimit(Span),                     // 関数呼び出しが深すぎる
+    InFunction(String, Box<CalcError>, Span), // 関数の本体でのエラー（関数名、エラー、呼び出し位置）
+    Native(String, String, Span),             // 登録した関数のエラー（関数名、メッセージ）
+}
+
+impl CalcError {
+    /// 演算の失敗を、演算子などの位置spanのエラーにする
+    pub fn arithmetic(e: ArithmeticError, span: &Span) -> Self {
+        match e {
+            ArithmeticError::DivisionByZero => CalcError::DivisionByZero(span.clone()),
+            ArithmeticError::Overflow => CalcError::Overflow(span.clone()),
+            ArithmeticError::NotInteger => CalcError::NotInteger(span.clone()),
+            ArithmeticError::NotReal => CalcError::NotReal(span.clone()),
+        }
+    }
+
+    /// 単位つきの演算の失敗を、演算子などの位置spanのエラーにする
+    pub fn value(e: ValueError, span: &Span) -> Self {
+        let name = |unit: Unit| {
+            if unit.is_none() {
+                "no unit".to_string()
+            } else {
+                unit.to_string()
+            }
+        };
+        match e {
+            ValueError::Arithmetic(e) => CalcError::arithmetic(e, span),
+            ValueError::IncompatibleUnits(lhs, rhs) => {
+                CalcError::IncompatibleUnits(name(lhs), name(rhs), span.clone())
+            }
+            ValueError::UnitNotAllowed => CalcError::UnitNotAllowed(span.clone()),
+            ValueError::UnitPower => CalcError::UnitPower(span.clone()),
+            ValueError::NotScalar => CalcError::NotScalar(span.clone()),
+            ValueError::Shape(shapes) => CalcError::ShapeMismatch(shapes, span.clone()),
+            ValueError::Singular => CalcError::SingularMatrix(span.clone()),
+        }
+    }
+
+    /// エラーの原因となった入力中の範囲
+    pub fn span(&self) -> Span {
+        match self {
+            CalcError::Lex(e) => e.column..e.column + 1,
+            CalcError::UnexpectedToken(span)
+            | CalcError::UnexpectedEnd(span)
+            | CalcError::UnbalancedParen(span)
+            | CalcError::TrailingInput(span)
+            | CalcError::DivisionByZero(span)
+            | CalcError::Overflow(span)
+            | CalcError::NotInteger(span)
+            | CalcError::NotReal(span)
+            | CalcError::IncompatibleUnits(_, _, span)
+            | CalcError::UnitNotAllowed(span)
+            | CalcError::UnitPower(span)
+            | CalcError::NotScalar(span)
+            | CalcError::ShapeMismatch(_, span)
+            | CalcError::SingularMatrix(span)
+            | CalcError::InvalidConversion(span)
+            | CalcError::UnknownIdentifier(_, span)
+            | CalcError::NoResult(_, span)
+            | CalcError::UnknownFunction(_, span)
+            | CalcError::NotVariable(span)
+            | CalcError::NotDifferentiable(_, span)
+            | CalcError::MisplacedEquation(span)
+            | CalcError::NoConvergence(span)
+            | CalcError::MissingPoint(span)
+            | CalcError::ArgumentCount(_, _, span)
+            | CalcError::Domain(_, span)
+            | CalcError::OutOfRange(_, span)
+            | CalcError::InvalidAssignment(span)
+            | CalcError::ReservedName(_, span)
+            | CalcError::DuplicateParameter(_, span)
+            | CalcError::RecursionLimit(span)
+            | CalcError::InFunction(_, _, span)
+            | CalcError::Native(_, _, span) => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::Lex(e) => write!(f, "{}", e),
+            CalcError::UnexpectedToken(_) => write!(f, "unexpected token"),
+            CalcError::UnexpectedEnd(_) => write!(f, "unexpected end of input"),
+            CalcError::UnbalancedParen(_) => write!(f, "unbalanced parenthesis or bracket"),
+            CalcError::TrailingInput(_) => write!(f, "unexpected input after expression"),
+            CalcError::DivisionByZero(_) => write!(f, "division by zero"),
+            CalcError::Overflow(_) => write!(f, "number out of range"),
+            CalcError::NotInteger(_) => write!(f, "operand must be an integer"),
+            CalcError::NotReal(_) => write!(f, "operand must be a real number"),
+            CalcError::IncompatibleUnits(lhs, rhs, _) => {
+                write!(f, "incompatible units: {} and {}", lhs, rhs)
+            }
+            CalcError::UnitNotAllowed(_) => write!(f, "operand must not have a unit"),
+            CalcError::UnitPower(_) => write!(f, "a unit can only be raised to an integer power"),
+            CalcError::NotScalar(_) => write!(f, "operand must be a scalar"),
+            CalcError::ShapeMismatch(shapes, _) => write!(f, "shape mismatch: {}", shapes),
+            CalcError::SingularMatrix(_) => write!(f, "matrix is singular"),
+            CalcError::InvalidConversion(_) => write!(f, "conversion target must be a unit"),
+            CalcError::UnknownIdentifier(name, _) => write!(f, "unknown identifier '{}'", name),
+            CalcError::NoResult(name, _) => write!(f, "no result {} yet", name),
+            CalcError::UnknownFunction(name, _) => write!(f, "unknown function '{}'", name),
+            CalcError::NotVariable(_) => write!(f, "expected a variable name"),
+            CalcError::NotDifferentiable(name, _) => write!(f, "cannot differentiate '{}'", name),
+            CalcError::MisplacedEquation(_) => write!(f, "an equation is only allowed in solve"),
+            CalcError::NoConvergence(_) => write!(f, "root did not converge"),
+            CalcError::MissingPoint(_) => {
+                write!(f, "give the point to evaluate at as diff(f, x, a)")
+            }
+            CalcError::ArgumentCount(name, expected, _) => {
+                write!(f, "'{}' takes {} argument(s)", name, expected)
+            }
+            CalcError::Domain(name, _) => write!(f, "argument out of domain of '{}'", name),
+            CalcError::OutOfRange(name, _) => write!(f, "result of '{}' is too large", name),
+            CalcError::InvalidAssignment(_) => write!(f, "cannot assign to this expression"),
+            CalcError::ReservedName(name, _) => write!(f, "'{}' is a built-in name", name),
+            CalcError::DuplicateParameter(name, _) => {
+                write!(f, "duplicate parameter '{}'", name)
+            }
+            CalcError::RecursionLimit(_) => write!(
+                f,
+                "function calls nested more than {} deep",
+                crate::MAX_CALL_DEPTH
+            ),
+            CalcError::InFunction(name, e, _) => write!(f, "in function '{}': {}", name, e),
+            CalcError::Native(name, message, _) => {
+                write!(f, "in function '{}': {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+impl From<LexError> for CalcError {
+    fn from(e: LexError) -> Self {
+        CalcError::Lex(e)
+    }
+}
@@ -0,0 +1,545 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, UnaryOp};
+use crate::error::CalcError;
+use crate::functions;
+use crate::lexer::Span;
+use crate::matrix::{self, Matrix};
+use crate::number::{ArithmeticError, Number};
+use crate::numeric;
+use crate::stats;
+use crate::symbolic;
+use crate::unit::Unit;
+use crate::value::{Quantity, Value, ValueError};
+use crate::{Context, MAX_CALL_DEPTH};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::slice;
+
+/// 直前の結果を表す名前
+pub const LAST_RESULT: &str = "ans";
+
+/// 変数を探す範囲。関数の本体は仮引数、グローバル変数、定数、単位の順に探す
+pub struct Scope<'a> {
+    ctx: &'a Context,
+    locals: HashMap<String, Value>,
+    depth: usize, // ユーザー定義関数の呼び出しの深さ
+}
+
+impl<'a> Scope<'a> {
+    pub fn global(ctx: &'a Context) -> Self {
+        Self {
+            ctx,
+            locals: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn ctx(&self) -> &'a Context {
+        self.ctx
+    }
+
+    /// 変数nameにvalueを加えたスコープ
+    pub fn with_local(&self, name: &str, value: Value) -> Self {
+        let mut locals = self.locals.clone();
+        locals.insert(name.to_string(), value);
+        Self {
+            ctx: self.ctx,
+            locals,
+            depth: self.depth,
+        }
+    }
+
+    /// 変数の値。仮引数、ans、グローバル変数、定数、単位（km は 1 km）の順に探す
+    ///
+    /// 仮引数以外は現在のモードの表現に変換する。
+    fn lookup(&self, name: &str) -> Option<Value> {
+        let mode = self.ctx.number_mode;
+        if let Some(value) = self.locals.get(name) {
+            return Some(value.clone());
+        }
+        if name == LAST_RESULT {
+            return self.ctx.last_result().map(|value| value.to_mode(mode));
+        }
+        self.ctx
+            .variables
+            .get(name)
+            .map(|value| value.to_mode(mode))
+            .or_else(|| functions::constant(name).map(|c| Value::new(Number::from_f64(c, mode))))
+            .or_else(|| {
+                Some(Value::Scalar(Quantity {
+                    number: Number::parse("1", mode).ok()?,
+                    unit: Unit::named(name)?,
+                }))
+            })
+    }
+}
+
+/// 式をscopeの中で評価する
+pub fn eval(expr: &Expr, scope: &Scope) -> Result<Value, CalcError> {
+    let mode = scope.ctx.number_mode;
+    let error = |e: ValueError| CalcError::value(e, &expr.span);
+    match &expr.kind {
+        ExprKind::Number(text) => Number::parse(text, mode)
+            .map(Value::new)
+            .map_err(|e| CalcError::arithmetic(e, &expr.span)),
+        ExprKind::Constant(value) => Ok(Value::new(value.clone())),
+        ExprKind::Variable(name) => match scope.lookup(name) {
+            Some(value) => Ok(value),
+            None if name == LAST_RESULT => {
+                Err(CalcError::NoResult(name.clone(), expr.span.clone()))
+            }
+            None => Err(CalcError::UnknownIdentifier(
+                name.clone(),
+                expr.span.clone(),
+            )),
+        },
+        ExprKind::Memory(name) => Ok(scope.ctx.memory.get(name).to_mode(mode)),
+        ExprKind::History(n) => match n.checked_sub(1).and_then(|i| scope.ctx.history.get(i)) {
+            Some(entry) => Ok(entry.value.to_mode(mode)),
+            None => Err(CalcError::NoResult(format!("${}", n), expr.span.clone())),
+        },
+        ExprKind::Unary(op, operand) => {
+            let value = eval(operand, scope)?;
+            match op {
+                UnaryOp::Neg => value.neg(),
+                UnaryOp::Not => plain(value.plain(), |x| x.bit_not(mode)),
+            }
+            .map_err(error)
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, scope)?;
+            let rhs = eval(rhs, scope)?;
+            match op {
+                BinaryOp::Add => lhs.add(&rhs),
+                BinaryOp::Sub => lhs.sub(&rhs),
+                BinaryOp::Mul => lhs.mul(&rhs),
+                BinaryOp::Div => lhs.div(&rhs),
+                // 切り捨て除算と、その余り（結果の符号は除数と同じ）
+                BinaryOp::FloorDiv => lhs.floor_div(&rhs),
+                BinaryOp::Rem => lhs.rem(&rhs),
+                BinaryOp::Pow => lhs.pow(&rhs, mode),
+                // ビット演算は単位なしの数だけ
+                op => {
+                    let operands = lhs.plain().and_then(|x| Ok((x, rhs.plain()?)));
+                    plain(operands, |(x, y)| match op {
+                        BinaryOp::BitAnd => x.bit_and(y, mode),
+                        BinaryOp::BitOr => x.bit_or(y, mode),
+                        BinaryOp::BitXor => x.bit_xor(y, mode),
+                        BinaryOp::Shl => x.shift(y, true, mode),
+                        _ => x.shift(y, false, mode),
+                    })
+                }
+            }
+            .map_err(error)
+        }
+        ExprKind::Convert(value, unit) => {
+            let value = eval(value, scope)?;
+            // 変換先は km/h のように数の部分が1の単位であること
+            let target = eval(unit, scope)?;
+            let one = Number::Float(1.0);
+            match target {
+                Value::Scalar(target)
+                    if !target.unit.is_none()
+                        && target.number.compare(&one) == Some(Ordering::Equal) =>
+                {
+                    value.convert(&target.unit).map_err(error)
+                }
+                _ => Err(CalcError::InvalidConversion(expr.span.clone())),
+            }
+        }
+        ExprKind::List(elements) => {
+            let elements = elements
+                .iter()
+                .map(|element| eval(element, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            list(&elements).map_err(error)
+        }
+        ExprKind::Equation(..) => Err(CalcError::MisplacedEquation(expr.span.clone())),
+        // 方程式か3つの引数の solve は数値解。solve(A, b) は連立一次方程式
+        ExprKind::Call(name, args)
+            if name == "solve"
+                && (args.len() == 3
+                    || matches!(args.first(), Some(e) if matches!(e.kind, ExprKind::Equation(..)))) =>
+        {
+            numeric::solve(args, scope, &expr.span)
+        }
+        ExprKind::Call(name, args) if name == "integrate" => {
+            numeric::integrate(args, scope, &expr.span)
+        }
+        // 導関数を求め、diff(f, x, a) であれば x = a で評価する。diff(f, x) は
+        // 仮引数や plot、solve、integrate の変数のように x が束縛されている時だけ評価する
+        ExprKind::Call(name, args) if name == "diff" => {
+            let (derivative, var) = symbolic::derivative_of(args, scope.ctx, &expr.span)?;
+            match args.get(2) {
+                Some(point) => {
+                    let point = eval(point, scope)?;
+                    eval(&derivative, &scope.with_local(var, point))
+                }
+                None if scope.locals.contains_key(var) => eval(&derivative, scope),
+                None => Err(CalcError::MissingPoint(expr.span.clone())),
+            }
+        }
+        ExprKind::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            call_function(name, &args, scope, &expr.span)
+        }
+    }
+}
+
+/// 要素の値からベクトルを作る。要素がすべてベクトルであれば、それを行とする行列を作る
+fn list(elements: &[Value]) -> Result<Value, ValueError> {
+    let scalars: Option<Vec<_>> = elements.iter().map(|e| e.scalar().ok().cloned()).collect();
+    if let Some(scalars) = scalars {
+        return Ok(Value::Vector(scalars));
+    }
+    let rows = elements
+        .iter()
+        .map(|e| match e {
+            Value::Vector(row) => Ok(row.clone()),
+            e => Err(ValueError::Shape(format!("{} in a matrix", e.shape()))),
+        })
+        .collect::<Result<_, _>>()?;
+    Matrix::from_rows(rows).map(Value::Matrix)
+}
+
+/// 単位なしの数numberの演算の結果を単位なしの値にする
+fn plain<T>(
+    number: Result<T, ValueError>,
+    op: impl FnOnce(T) -> Result<Number, ArithmeticError>,
+) -> Result<Value, ValueError> {
+    Ok(Value::new(op(number?)?))
+}
+
+/// ユーザー定義関数、組み込み関数の順に探して呼び出す
+fn call_function(
+    name: &str,
+    args: &[Value],
+    scope: &Scope,
+    span: &Span,
+) -> Result<Value, CalcError> {
+    let Some(function) = scope.ctx.functions.get(name) else {
+        // 埋め込む側が登録した関数
+        if let Some(native) = scope.ctx.natives.get(name) {
+            return native(args)
+                .map_err(|message| CalcError::Native(name.to_string(), message, span.clone()));
+        }
+        return call_builtin(name, args, scope.ctx, span);
+    };
+    if function.params.len() != args.len() {
+        let expected = function.params.len().to_string();
+        return Err(CalcError::ArgumentCount(
+            name.to_string(),
+            expected,
+            span.clone(),
+        ));
+    }
+    if scope.depth >= MAX_CALL_DEPTH {
+        return Err(CalcError::RecursionLimit(span.clone()));
+    }
+
+    // 仮引数だけを持つ新しいスコープで本体を評価する
+    let inner = Scope {
+        ctx: scope.ctx,
+        locals: function
+            .params
+            .iter()
+            .cloned()
+            .zip(args.iter().cloned())
+            .collect(),
+        depth: scope.depth + 1,
+    };
+    eval(&function.body, &inner).map_err(|e| match e {
+        // 再帰の上限は最も外側の呼び出し位置で報告する
+        CalcError::RecursionLimit(_) => CalcError::RecursionLimit(span.clone()),
+        e => CalcError::InFunction(name.to_string(), Box::new(e), span.clone()),
+    })
+}
+
+/// 組み込み関数を呼び出す
+///
+/// ベクトル・行列や統計の関数でなければ、引数1つの関数はベクトル・行列の要素ごとに計算する。
+fn call_builtin(
+    name: &str,
+    args: &[Value],
+    ctx: &Context,
+    span: &Span,
+) -> Result<Value, CalcError> {
+    let mode = ctx.number_mode;
+    if let Some(result) = matrix::call(name, args, mode, span) {
+        return result;
+    }
+    if let Some(result) = stats::call(name, args, mode, span) {
+        return result;
+    }
+    if let [arg @ (Value::Vector(_) | Value::Matrix(_))] = args {
+        return arg.map(|q| call_scalar(name, slice::from_ref(q), ctx, span));
+    }
+    let args = args
+        .iter()
+        .map(|arg| arg.scalar().cloned())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CalcError::value(e, span))?;
+    call_scalar(name, &args, ctx, span).map(Value::Scalar)
+}
+
+/// 数を引数とする組み込み関数を呼び出す
+///
+/// abs、floor、ceil、round、min、maxは単位のある値にも使え、結果は最初の引数の単位になる
+/// （min、maxは残りの引数もその単位に換算する）。それ以外の引数は単位なしの数であること。
+fn call_scalar(
+    name: &str,
+    args: &[Quantity],
+    ctx: &Context,
+    span: &Span,
+) -> Result<Quantity, CalcError> {
+    let unit = match (name, args.first()) {
+        ("abs" | "floor" | "ceil" | "round" | "min" | "max", Some(first)) => first.unit.clone(),
+        _ => Unit::default(),
+    };
+    let numbers = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let unit = if i == 0 || name == "min" || name == "max" {
+                &unit
+            } else {
+                &Unit::default()
+            };
+            arg.convert(unit).map(|arg| arg.number)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CalcError::value(e, span))?;
+    let number = functions::call(name, &numbers, ctx.number_mode, ctx.angle_mode, span)?;
+    Ok(Quantity { number, unit })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CalcError;
+    use crate::number::NumberMode;
+    use crate::{Context, LineResult};
+
+    fn eval(line: &str) -> Result<f64, CalcError> {
+        Context::new()
+            .eval(line)
+            .map(|value| value.scalar().unwrap().number.to_f64())
+    }
+
+    /// 評価して表示用の文字列にする
+    fn eval_str(line: &str) -> Result<String, CalcError> {
+        Context::new().eval(line).map(|value| value.to_string())
+    }
+
+    /// 行を順に実行するctxで、値をf64にして返す関数
+    fn runner(ctx: &mut Context) -> impl FnMut(&str) -> Result<Option<f64>, CalcError> + '_ {
+        |line| {
+            ctx.eval_line(line).map(|r| match r {
+                LineResult::Value(value) => Some(value.scalar().unwrap().number.to_f64()),
+                LineResult::Memory(_) | LineResult::Defined(_) | LineResult::Derivative(_) => None,
+            })
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(Ok(-1.0), eval("1+2*(3-4)"));
+        assert_eq!(Ok(2.5), eval("10 / 4"));
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(Ok(-6.0), eval("-3 * 2"));
+        assert_eq!(Ok(1024.0), eval("2 ^ 10"));
+        assert_eq!(Ok(1024.0), eval("2 ** 10"));
+        assert_eq!(Ok(0.5), eval("2 ^ -1"));
+        assert_eq!(Ok(5.0), eval("--5"));
+        assert_eq!(Ok(3.0), eval("+3"));
+    }
+
+    #[test]
+    fn test_precedence() {
+        // 右結合
+        assert_eq!(Ok(2f64.powf(9.0)), eval("2 ^ 3 ^ 2"));
+        // 単項マイナスはべき乗より優先順位が低い
+        assert_eq!(Ok(-4.0), eval("-2 ^ 2"));
+        assert_eq!(Ok(7.0), eval("1 + 2 * 3 ^ 2 // 3"));
+    }
+
+    #[test]
+    fn test_floor_division() {
+        assert_eq!(Ok(1.0), eval("7 % 3"));
+        assert_eq!(Ok(2.0), eval("-7 % 3"));
+        assert_eq!(Ok(2.0), eval("7 // 3"));
+        assert_eq!(Ok(-3.0), eval("-7 // 3"));
+        assert_eq!(Err(CalcError::DivisionByZero(2..3)), eval("1 % 0"));
+        assert_eq!(Err(CalcError::DivisionByZero(2..4)), eval("1 // 0"));
+    }
+
+    #[test]
+    fn test_eval_error() {
+        assert_eq!(Err(CalcError::DivisionByZero(2..3)), eval("1 / (2 - 2)"));
+        assert_eq!(
+            Err(CalcError::UnknownIdentifier("x".to_string(), 0..1)),
+            eval("x + 1")
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(Ok("5.3 km".to_string()), eval_str("5 km + 300 m"));
+        assert_eq!(Ok("120 km".to_string()), eval_str("60 km/h * 2 h"));
+        assert_eq!(Ok("3145.728 KB".to_string()), eval_str("3 MiB to KB"));
+        assert_eq!(Ok("5280 ft".to_string()), eval_str("1 mi in ft"));
+        assert_eq!(Ok("100 degC".to_string()), eval_str("212 degF to degC"));
+        assert_eq!(Ok("2 m".to_string()), eval_str("sqrt(4) m"));
+        assert_eq!(Ok("3 km".to_string()), eval_str("max(3 km, 2500 m)"));
+        assert_eq!(Ok("0.5".to_string()), eval_str("500 m / km"));
+        assert_eq!(Ok("16.8 m^2".to_string()), eval_str("(4 m * 4.2 m)"));
+        let mut ctx = Context::new();
+        ctx.number_mode = NumberMode::Decimal(3);
+        let value = ctx.eval("(5 km + 300 m) / km");
+        assert_eq!("5.300", value.unwrap().to_string());
+    }
+
+    #[test]
+    fn test_unit_errors() {
+        assert_eq!(
+            Err(CalcError::IncompatibleUnits(
+                "km".to_string(),
+                "h".to_string(),
+                5..6
+            )),
+            eval_str("5 km + 2 h")
+        );
+        assert_eq!(
+            Err(CalcError::IncompatibleUnits(
+                "km".to_string(),
+                "kg".to_string(),
+                5..7
+            )),
+            eval_str("5 km to kg")
+        );
+        assert_eq!(
+            Err(CalcError::InvalidConversion(5..7)),
+            eval_str("5 km to 2 m")
+        );
+        assert_eq!(
+            Err(CalcError::IncompatibleUnits(
+                "m".to_string(),
+                "no unit".to_string(),
+                0..3
+            )),
+            eval_str("sin(2 m)")
+        );
+        assert_eq!(Err(CalcError::UnitNotAllowed(4..5)), eval_str("3 m & 1"));
+        assert_eq!(Err(CalcError::UnitPower(4..5)), eval_str("4 m ^ 0.5"));
+    }
+
+    #[test]
+    fn test_variable_shadows_unit() {
+        // 同じ名前の変数は単位より優先する
+        let mut ctx = Context::new();
+        ctx.eval_line("m = 2").unwrap();
+        assert_eq!("6", ctx.eval("3 m").unwrap().to_string());
+    }
+
+    #[test]
+    fn test_complex() {
+        assert_eq!(Ok("3 + 4i".to_string()), eval_str("3 + 4i"));
+        assert_eq!(Ok("-3 + 4i".to_string()), eval_str("(1 + 2i) ^ 2"));
+        assert_eq!(
+            Ok("11 - 2i".to_string()),
+            eval_str("(3 + 4i) * conj(1 + 2i)")
+        );
+        assert_eq!(Ok("1.5i m".to_string()), eval_str("sqrt(-2.25) m"));
+        assert_eq!(Err(CalcError::NotReal(9..10)), eval_str("(1 + 2i) % 2"));
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let mut ctx = Context::new();
+        let mut run = runner(&mut ctx);
+        assert_eq!(Ok(Some(0.08)), run("rate = 0.08"));
+        assert_eq!(Ok(Some(1.08)), run("1 + rate"));
+        run("tax(x) = x * (1 + rate)").unwrap();
+        assert_eq!(Ok(Some(216.0)), run("tax(200)"));
+        run("area(w, h) = w * h").unwrap();
+        assert_eq!(Ok(Some(12.0)), run("area(3, 2 * 2)"));
+        // 仮引数は呼び出し元の変数を隠し、呼び出し後には残らない
+        run("x = 10").unwrap();
+        assert_eq!(Ok(Some(12.0)), run("tax(100) - 96 + x - 10 + area(0, x)"));
+        assert_eq!(Ok(Some(10.0)), run("x"));
+        assert_eq!(
+            Err(CalcError::ArgumentCount(
+                "area".to_string(),
+                "2".to_string(),
+                0..4
+            )),
+            run("area(1)")
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        // 終わらない再帰は上限で止める
+        let mut ctx = Context::new();
+        let mut run = runner(&mut ctx);
+        run("loop(n) = loop(n + 1)").unwrap();
+        assert_eq!(Err(CalcError::RecursionLimit(0..4)), run("loop(0)"));
+    }
+
+    #[test]
+    fn test_error_in_function() {
+        // 本体のエラーは呼び出し位置で報告する
+        let mut ctx = Context::new();
+        let mut run = runner(&mut ctx);
+        run("rcp(x) = 1 / x").unwrap();
+        assert_eq!(
+            Err(CalcError::InFunction(
+                "rcp".to_string(),
+                Box::new(CalcError::DivisionByZero(11..12)),
+                2..5
+            )),
+            run("1+rcp(0)")
+        );
+    }
+
+    #[test]
+    fn test_history_references() {
+        let mut ctx = Context::new();
+        let mut run = runner(&mut ctx);
+        assert_eq!(
+            Err(CalcError::NoResult("ans".to_string(), 0..3)),
+            run("ans")
+        );
+        assert_eq!(Ok(Some(3.0)), run("1 + 2"));
+        assert_eq!(Ok(Some(30.0)), run("ans * 10"));
+        assert_eq!(Ok(Some(5.0)), run("x = $1 + 2"));
+        assert_eq!(Ok(Some(38.0)), run("$2 + $3 + $1"));
+        // 関数の本体の ans は呼び出した時の直前の結果
+        run("f(a) = a + ans").unwrap();
+        assert_eq!(Ok(Some(39.0)), run("f(1)"));
+        assert_eq!(
+            Err(CalcError::NoResult("$9".to_string(), 4..6)),
+            run("1 + $9")
+        );
+        assert_eq!(Err(CalcError::NoResult("$0".to_string(), 0..2)), run("$0"));
+    }
+
+    #[test]
+    fn test_diff_at_point() {
+        // diff(f, x, a) は x = a での値。仮引数に束縛された x では点を省ける
+        let mut ctx = Context::new();
+        assert_eq!(Ok(12.0), eval("diff(x^3, x, 2)"));
+        ctx.eval_line("g(x) = diff(x^4, x)").unwrap();
+        assert_eq!("32", ctx.eval("g(2)").unwrap().to_string());
+        ctx.eval_line("x = 3").unwrap();
+        assert_eq!("28", ctx.eval("diff(x^3, x, x) + 1").unwrap().to_string());
+        assert_eq!(
+            Err(CalcError::MissingPoint(0..4)),
+            ctx.eval("diff(x^3, x) + 1")
+        );
+    }
+}
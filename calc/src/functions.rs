@@ -0,0 +1,329 @@
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::matrix;
+use crate::number::{ArithmeticError, Number, NumberMode};
+use crate::numeric;
+use crate::stats;
+use crate::symbolic;
+use num_traits::Signed;
+use std::cmp::Ordering;
+use std::f64::consts;
+
+/// 三角関数の角度の単位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    /// 三角関数の引数をラジアンに変換する
+    fn input_to_radians(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_radians(),
+        }
+    }
+
+    /// 逆三角関数の結果をラジアンから変換する
+    fn output_from_radians(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_degrees(),
+        }
+    }
+}
+
+/// 組み込みの定数
+pub fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(consts::PI),
+        "e" => Some(consts::E),
+        "tau" => Some(consts::TAU),
+        _ => None,
+    }
+}
+
+/// 組み込み関数の名前の一覧
+pub const FUNCTION_NAMES: &[&str] = &[
+    "sqrt", "abs", "sin", "cos", "tan", "asin", "acos", "atan", "ln", "log10", "log", "exp",
+    "floor", "ceil", "round", "min", "max", "hypot", "re", "im", "arg", "conj",
+];
+
+/// 組み込みの定数の名前の一覧
+pub const CONSTANT_NAMES: &[&str] = &["pi", "e", "tau"];
+
+/// 組み込み関数の名前（ベクトル・行列、統計、式を引数にとる関数も含む）
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    [
+        FUNCTION_NAMES,
+        matrix::MATRIX_FUNCTION_NAMES,
+        stats::STATS_FUNCTION_NAMES,
+        symbolic::SYMBOLIC_FUNCTION_NAMES,
+        numeric::NUMERIC_FUNCTION_NAMES,
+    ]
+    .into_iter()
+    .flatten()
+    .copied()
+}
+
+/// 組み込み関数の名前か
+pub fn is_builtin(name: &str) -> bool {
+    builtin_names().any(|builtin| builtin == name)
+}
+
+/// 組み込み関数を呼び出す。spanはエラー表示に使う関数名の位置
+///
+/// 複素数が必要であれば複素数で計算する。abs、floor、ceil、round、min、maxは有理数・10進数のまま計算し、
+/// それ以外はf64で計算した結果をmodeの表現にする。
+pub fn call(
+    name: &str,
+    args: &[Number],
+    mode: NumberMode,
+    angle_mode: AngleMode,
+    span: &Span,
+) -> Result<Number, CalcError> {
+    if let Some(result) = call_complex(name, args, mode, angle_mode, span) {
+        return result;
+    }
+    if let Some(result) = call_exact(name, args) {
+        return result.map_err(|e| CalcError::arithmetic(e, span));
+    }
+    let args: Vec<f64> = args.iter().map(Number::to_f64).collect();
+    call_float(name, &args, angle_mode, span).map(|result| Number::from_f64(result, mode))
+}
+
+/// 複素数で計算する関数。re、im、arg、conjのほか、引数に複素数があるか、負の数の平方根・対数であれば使う
+fn call_complex(
+    name: &str,
+    args: &[Number],
+    mode: NumberMode,
+    angle_mode: AngleMode,
+    span: &Span,
+) -> Option<Result<Number, CalcError>> {
+    let is_complex = |x: &Number| matches!(x, Number::Complex(_));
+    let has_complex = args.iter().any(is_complex);
+    let negative = args.first().is_some_and(|x| x.to_f64() < 0.0);
+    let needed = matches!(name, "re" | "im" | "arg" | "conj")
+        || has_complex
+        || (negative && matches!(name, "sqrt" | "ln" | "log10" | "log"));
+    if !needed {
+        return None;
+    }
+    // 三角関数の引数をラジアンにする係数
+    let radians = angle_mode.input_to_radians(1.0);
+    let z = Number::to_complex;
+    let result = match (name, args) {
+        // 実数の実部・共役はモードの表現のまま
+        ("re" | "conj", [x]) if !is_complex(x) => x.clone(),
+        ("re", [x]) => Number::Float(z(x).re),
+        ("im", [x]) => Number::from_f64(z(x).im, mode),
+        ("conj", [x]) => Number::Complex(z(x).conj()),
+        ("arg", [x]) => Number::from_f64(angle_mode.output_from_radians(z(x).arg()), mode),
+        ("abs", [x]) => Number::Float(z(x).norm()),
+        ("sqrt", [x]) => Number::from_complex_approx(z(x).sqrt()),
+        ("exp", [x]) => Number::from_complex_approx(z(x).exp()),
+        ("ln", [x]) => Number::from_complex_approx(z(x).ln()),
+        ("log10", [x]) => Number::from_complex_approx(z(x).log10()),
+        ("log", [x, base]) => Number::from_complex_approx(z(x).ln() / z(base).ln()),
+        ("sin", [x]) => Number::from_complex_approx((z(x) * radians).sin()),
+        ("cos", [x]) => Number::from_complex_approx((z(x) * radians).cos()),
+        ("tan", [x]) => Number::from_complex_approx((z(x) * radians).tan()),
+        // 大小の比較や逆三角関数は実数だけ
+        ("asin" | "acos" | "atan" | "hypot" | "min" | "max", _) if has_complex => {
+            return Some(Err(CalcError::NotReal(span.clone())));
+        }
+        // 引数の個数の誤りや、floorなど実数だけの関数のエラーは実数の場合と同じ
+        ("re" | "im" | "arg" | "conj", _) => {
+            let expected = "1".to_string();
+            return Some(Err(CalcError::ArgumentCount(
+                name.to_string(),
+                expected,
+                span.clone(),
+            )));
+        }
+        _ => return None,
+    };
+    if !result.to_complex().is_finite() {
+        return Some(Err(divergence(name, span)));
+    }
+    Some(Ok(result))
+}
+
+/// 誤差なく計算できる関数
+fn call_exact(name: &str, args: &[Number]) -> Option<Result<Number, ArithmeticError>> {
+    // 比較した結果orderであれば新しい方を選ぶ
+    let select = |order: Ordering| {
+        move |acc: Number, x: &Number| match x.compare(&acc) {
+            Some(o) if o == order => x.clone(),
+            _ => acc,
+        }
+    };
+    let result = match (name, args) {
+        ("abs", [x]) => x.map_exact(f64::abs, |r| r.abs()),
+        ("floor", [x]) => x.map_exact(f64::floor, |r| r.floor()),
+        ("ceil", [x]) => x.map_exact(f64::ceil, |r| r.ceil()),
+        ("round", [x]) => x.round_to(0),
+        ("round", [x, digits]) => digits.to_exponent().and_then(|digits| x.round_to(digits)),
+        ("min", [first, rest @ ..]) => Ok(rest.iter().fold(first.clone(), select(Ordering::Less))),
+        ("max", [first, rest @ ..]) => {
+            Ok(rest.iter().fold(first.clone(), select(Ordering::Greater)))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// f64で計算する
+fn call_float(
+    name: &str,
+    args: &[f64],
+    angle_mode: AngleMode,
+    span: &Span,
+) -> Result<f64, CalcError> {
+    let arity_error = |expected: &str| {
+        CalcError::ArgumentCount(name.to_string(), expected.to_string(), span.clone())
+    };
+    let result = match (name, args) {
+        ("sqrt", [x]) => x.sqrt(),
+        ("sin", [x]) => angle_mode.input_to_radians(*x).sin(),
+        ("cos", [x]) => angle_mode.input_to_radians(*x).cos(),
+        ("tan", [x]) => angle_mode.input_to_radians(*x).tan(),
+        ("asin", [x]) => angle_mode.output_from_radians(x.asin()),
+        ("acos", [x]) => angle_mode.output_from_radians(x.acos()),
+        ("atan", [x]) => angle_mode.output_from_radians(x.atan()),
+        ("ln", [x]) => x.ln(),
+        ("log10", [x]) => x.log10(),
+        ("log", [x, base]) => x.log(*base),
+        ("exp", [x]) => x.exp(),
+        ("hypot", [x, y]) => x.hypot(*y),
+        ("log" | "hypot", _) => return Err(arity_error("2")),
+        ("round", _) => return Err(arity_error("1 or 2")),
+        ("min" | "max", _) => return Err(arity_error("1 or more")),
+        _ if FUNCTION_NAMES.contains(&name) => return Err(arity_error("1")),
+        _ => return Err(CalcError::UnknownFunction(name.to_string(), span.clone())),
+    };
+
+    // 引数が数なのに結果が数でなければ定義域外（ln(-1) など）
+    if result.is_nan() && !args.iter().any(|x| x.is_nan()) {
+        return Err(CalcError::Domain(name.to_string(), span.clone()));
+    }
+    // ln(0) などの発散や exp(1000) などの桁あふれ
+    if result.is_infinite() && args.iter().all(|x| x.is_finite()) && name != "tan" {
+        return Err(divergence(name, span));
+    }
+    Ok(result)
+}
+
+/// 有限の引数で結果が有限でない時のエラー
+///
+/// 対数の0での発散は定義域外、それ以外は結果がf64で表せないほど大きい。
+fn divergence(name: &str, span: &Span) -> CalcError {
+    match name {
+        "ln" | "log10" | "log" => CalcError::Domain(name.to_string(), span.clone()),
+        _ => CalcError::OutOfRange(name.to_string(), span.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AngleMode;
+    use crate::error::CalcError;
+    use crate::Context;
+
+    fn eval(line: &str) -> Result<f64, CalcError> {
+        Context::new()
+            .eval(line)
+            .map(|value| value.scalar().unwrap().number.to_f64())
+    }
+
+    /// 評価して表示用の文字列にする
+    fn eval_str(line: &str) -> Result<String, CalcError> {
+        Context::new().eval(line).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(Ok(3.0), eval("sqrt(9)"));
+        assert_eq!(Ok(3.0), eval("log(8, 2)"));
+        assert_eq!(Ok(5.0), eval("hypot(3, 4)"));
+        assert_eq!(Ok(1.0), eval("min(3, 1, 2)"));
+        assert_eq!(Ok(2.72), eval("round(e, 2)"));
+        assert_eq!(Ok(-2.0), eval("-abs(floor(-1.5))"));
+        assert_eq!(Ok(std::f64::consts::TAU), eval("2*pi"));
+        assert_eq!(Ok(1.0), eval("ln(e)"));
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        assert_eq!(
+            Err(CalcError::ArgumentCount(
+                "sqrt".to_string(),
+                "1".to_string(),
+                0..4
+            )),
+            eval("sqrt(1, 2)")
+        );
+        assert_eq!(
+            Err(CalcError::UnknownFunction("foo".to_string(), 0..3)),
+            eval("foo(1)")
+        );
+        assert_eq!(Err(CalcError::Overflow(0..5)), eval("round(2, 1e9)"));
+        assert_eq!(Err(CalcError::NotInteger(0..5)), eval("round(2, 0.5)"));
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(
+            Err(CalcError::Domain("ln".to_string(), 0..2)),
+            eval("ln(0)")
+        );
+        assert_eq!(
+            Err(CalcError::Domain("asin".to_string(), 0..4)),
+            eval("asin(2)")
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        // 有限の引数で結果が発散すれば定義域外ではなく範囲外
+        assert_eq!(
+            Err(CalcError::OutOfRange("exp".to_string(), 0..3)),
+            eval("exp(1000)")
+        );
+        assert_eq!(
+            Err(CalcError::OutOfRange("hypot".to_string(), 0..5)),
+            eval("hypot(1.5e308, 1.5e308)")
+        );
+        assert_eq!(
+            Err(CalcError::OutOfRange("exp".to_string(), 0..3)),
+            eval("exp(1000 + 1i)")
+        );
+    }
+
+    #[test]
+    fn test_degrees() {
+        let mut ctx = Context::new();
+        ctx.set_angle_mode(AngleMode::Degrees);
+        let eval = |line| ctx.eval(line).unwrap().scalar().unwrap().number.to_f64();
+        assert!((eval("sin(30)") - 0.5).abs() < 1e-12);
+        assert!((eval("atan(1)") - 45.0).abs() < 1e-12);
+        assert_eq!(90.0, eval("arg(1i)"));
+        let z = ctx.eval("-2i").unwrap();
+        assert_eq!("2∠-90°", z.to_string_radix(10, Some(AngleMode::Degrees)));
+    }
+
+    #[test]
+    fn test_complex_functions() {
+        assert_eq!(Ok("1i".to_string()), eval_str("sqrt(-1)"));
+        assert_eq!(Ok("-1".to_string()), eval_str("sqrt(-1) ^ 2"));
+        assert_eq!(Ok("5".to_string()), eval_str("abs(3 + 4i)"));
+        assert_eq!(Ok("3".to_string()), eval_str("re(3 - 4i)"));
+        assert_eq!(Ok("-4".to_string()), eval_str("im(3 - 4i)"));
+        assert_eq!(Ok("0".to_string()), eval_str("im(3)"));
+        assert_eq!(Ok("3.141592653589793i".to_string()), eval_str("ln(-1)"));
+        assert_eq!(Ok("-1".to_string()), eval_str("exp(pi * 1i)"));
+        assert_eq!(Err(CalcError::NotReal(0..5)), eval_str("floor(1i)"));
+        assert_eq!(Err(CalcError::NotReal(0..3)), eval_str("max(1i, 2)"));
+    }
+}
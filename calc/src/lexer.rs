@@ -0,0 +1,491 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+
+/// 入力中の範囲（0始まりの文字位置）
+pub type Span = Range<usize>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Number(String), // 数値リテラルの文字列（数の表現はモードに応じて評価時に決める）
+    Ident(String),
+    MemoryRef(String),
+    MemoryPlus(String),
+    MemoryMinus(String),
+    HistoryRef(usize), // $n（n番目の結果）
+    Plus,
+    Minus,
+    Asterisk,
+    DoubleAsterisk,
+    Slash,
+    DoubleSlash,
+    Percent,
+    Caret,
+    Ampersand,
+    Pipe,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Equals,
+}
+
+/// 入力中の範囲を伴うトークン
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// 字句解析のエラー（不正な文字とその桁）
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub column: usize, // 0始まりの文字位置
+    pub ch: char,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unexpected character '{}' at column {}",
+            self.ch,
+            self.column + 1
+        )
+    }
+}
+
+impl Token {
+    /// 1文字ずつ読み進めてトークン列に分割する（トークン間の空白は不要）
+    pub fn tokenize(text: &str) -> Result<Vec<SpannedToken>, LexError> {
+        let mut lexer = Lexer {
+            text,
+            chars: text.char_indices().peekable(),
+            column: 0,
+        };
+        let mut tokens = Vec::new();
+        loop {
+            while lexer.peek().is_some_and(char::is_whitespace) {
+                lexer.bump();
+            }
+            let start = lexer.column;
+            let Some(token) = lexer.next_token()? else {
+                break;
+            };
+            tokens.push(SpannedToken {
+                token,
+                span: start..lexer.column,
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    column: usize, // 次に読む文字の位置（文字単位）
+}
+
+impl Lexer<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        self.column += 1;
+        Some(c)
+    }
+
+    /// 次の文字がcであれば読み進めてtrueを返す
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 現在のバイト位置
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |(i, _)| *i)
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        let token = match c {
+            '0'..='9' | '.' => self.number()?,
+            c if c.is_alphabetic() || c == '_' => self.ident(),
+            '$' => self.history_ref()?,
+            _ => {
+                let column = self.column;
+                self.bump();
+                match c {
+                    '+' => Token::Plus,
+                    '-' => Token::Minus,
+                    '*' if self.eat('*') => Token::DoubleAsterisk,
+                    '*' => Token::Asterisk,
+                    '/' if self.eat('/') => Token::DoubleSlash,
+                    '/' => Token::Slash,
+                    '%' => Token::Percent,
+                    '^' => Token::Caret,
+                    '&' => Token::Ampersand,
+                    '|' => Token::Pipe,
+                    '~' => Token::Tilde,
+                    '<' if self.eat('<') => Token::ShiftLeft,
+                    '>' if self.eat('>') => Token::ShiftRight,
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    '=' => Token::Equals,
+                    _ => return Err(LexError { column, ch: c }),
+                }
+            }
+        };
+        Ok(Some(token))
+    }
+
+    fn digits(&mut self) -> usize {
+        let mut count = 0;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+            count += 1;
+        }
+        count
+    }
+
+    /// 123、1.5、.5、1e10、1.5e-3 の形式の数値
+    fn number(&mut self) -> Result<Token, LexError> {
+        let start_column = self.column;
+        let start = self.offset();
+        if let Some(token) = self.radix_number()? {
+            return Ok(token);
+        }
+        let mut count = self.digits();
+        if self.peek() == Some('.') {
+            self.bump();
+            count += self.digits();
+        }
+        if count == 0 {
+            // 数字を含まない "."
+            return Err(LexError {
+                column: start_column,
+                ch: '.',
+            });
+        }
+
+        // 指数部は直後に数字が続く場合のみ読む（"2e" は数値2と識別子eになる）
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let rest = &self.text[self.offset() + 1..];
+            let sign_len = usize::from(rest.starts_with(['+', '-']));
+            if rest[sign_len..].starts_with(|c: char| c.is_ascii_digit()) {
+                self.bump();
+                if sign_len == 1 {
+                    self.bump();
+                }
+                self.digits();
+            }
+        }
+
+        // 直後の i は虚数単位（4i）。"2in" は数値2と識別子inになる
+        let rest = &self.text[self.offset()..];
+        if rest.starts_with('i')
+            && !rest[1..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        {
+            self.bump();
+        }
+
+        let end = self.offset();
+        Ok(Token::Number(self.text[start..end].to_string()))
+    }
+
+    /// 0xff、0b1010、0o17 の形式の整数。数字の間には _ を書ける（0b1111_0000）
+    ///
+    /// 接頭辞の直後が数字でなければ読まない（"0x" は数値0と識別子xになる）。
+    fn radix_number(&mut self) -> Result<Option<Token>, LexError> {
+        let start = self.offset();
+        let rest = &self.text[start..];
+        let radix = match rest.get(..2) {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            Some("0o") => 8,
+            _ => return Ok(None),
+        };
+        if !rest[2..].starts_with(|c: char| c.is_digit(radix)) {
+            return Ok(None);
+        }
+        self.bump();
+        self.bump();
+        while self.peek().is_some_and(|c| c.is_digit(radix) || c == '_') {
+            self.bump();
+        }
+        // 0b102 のように基数に合わない数字や文字が続く
+        if let Some(c) = self.peek().filter(|c| c.is_alphanumeric()) {
+            return Err(LexError {
+                column: self.column,
+                ch: c,
+            });
+        }
+        let end = self.offset();
+        Ok(Some(Token::Number(self.text[start..end].to_string())))
+    }
+
+    /// $1、$2 の形式の結果の参照
+    fn history_ref(&mut self) -> Result<Token, LexError> {
+        let column = self.column;
+        self.bump();
+        let start = self.offset();
+        self.digits();
+        match self.text[start..self.offset()].parse() {
+            Ok(n) => Ok(Token::HistoryRef(n)),
+            Err(_) => Err(LexError { column, ch: '$' }),
+        }
+    }
+
+    /// 識別子。mem に数字の列か英大文字で始まる名前が続くもの（mem1、mem12、memA、memTotal）は
+    /// メモリの参照、行末の memX+ と memX- はメモリへの加算・減算になる（memo や memory は識別子）
+    fn ident(&mut self) -> Token {
+        let start = self.offset();
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.bump();
+        }
+        let name = &self.text[start..self.offset()];
+        let memory_name = match name.strip_prefix("mem") {
+            Some(slot) if is_memory_slot(slot) => slot,
+            _ => return Token::Ident(name.to_string()),
+        };
+
+        let rest = self.text[self.offset()..].trim();
+        let memory_name = memory_name.to_string();
+        match rest {
+            "+" | "-" => {
+                while self.bump().is_some() {}
+                if rest == "+" {
+                    Token::MemoryPlus(memory_name)
+                } else {
+                    Token::MemoryMinus(memory_name)
+                }
+            }
+            _ => Token::MemoryRef(memory_name),
+        }
+    }
+}
+
+/// メモリの名前（数字の列、または英大文字で始まる英数字の列）か
+fn is_memory_slot(slot: &str) -> bool {
+    match slot.chars().next() {
+        Some(c) if c.is_ascii_digit() => slot.chars().all(|c| c.is_ascii_digit()),
+        Some(c) if c.is_ascii_uppercase() => slot.chars().all(|c| c.is_ascii_alphanumeric()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LexError, Token};
+
+    fn number(text: &str) -> Token {
+        Token::Number(text.to_string())
+    }
+
+    fn tokenize(text: &str) -> Result<Vec<Token>, LexError> {
+        Token::tokenize(text).map(|tokens| tokens.into_iter().map(|t| t.token).collect())
+    }
+
+    #[test]
+    fn test_tokenize_without_spaces() {
+        assert_eq!(
+            Ok(vec![
+                number("1"),
+                Token::Plus,
+                number("2"),
+                Token::Asterisk,
+                Token::LParen,
+                number("3"),
+                Token::Minus,
+                number("4"),
+                Token::RParen,
+            ]),
+            tokenize("1+2*(3-4)")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        assert_eq!(
+            Ok(vec![
+                number("2"),
+                Token::DoubleAsterisk,
+                number("3"),
+                Token::Caret,
+                number("4"),
+                Token::DoubleSlash,
+                number("5"),
+                Token::Percent,
+                number("6"),
+                Token::Slash,
+                Token::Asterisk,
+            ]),
+            tokenize("2**3^4//5%6/*")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_numbers() {
+        assert_eq!(
+            Ok(vec![
+                number("1.5e-3"),
+                number(".5"),
+                number("2E+10"),
+                number("3."),
+            ]),
+            tokenize("1.5e-3 .5 2E+10 3.")
+        );
+        assert_eq!(
+            Ok(vec![number("2"), Token::Ident("e".to_string())]),
+            tokenize("2e")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_radix() {
+        assert_eq!(
+            Ok(vec![
+                number("0xFF"),
+                Token::Ampersand,
+                number("0b1111_0000"),
+                Token::Pipe,
+                Token::Tilde,
+                number("0o17"),
+                Token::ShiftLeft,
+                number("2"),
+                Token::ShiftRight,
+                number("1"),
+            ]),
+            tokenize("0xFF&0b1111_0000|~0o17<<2>>1")
+        );
+        assert_eq!(
+            Ok(vec![number("0"), Token::Ident("x".to_string())]),
+            tokenize("0x")
+        );
+        assert_eq!(Err(LexError { column: 4, ch: '2' }), tokenize("0b102"));
+        assert_eq!(Err(LexError { column: 2, ch: '<' }), tokenize("1 < 2"));
+    }
+
+    #[test]
+    fn test_tokenize_imaginary() {
+        assert_eq!(
+            Ok(vec![
+                number("3"),
+                Token::Plus,
+                number("4.5i"),
+                Token::Asterisk,
+                number("1e3i")
+            ]),
+            tokenize("3+4.5i*1e3i")
+        );
+        assert_eq!(
+            Ok(vec![
+                number("2"),
+                Token::Ident("in".to_string()),
+                Token::Ident("m".to_string())
+            ]),
+            tokenize("2in m")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_memory() {
+        assert_eq!(
+            Ok(vec![Token::MemoryPlus("1".to_string())]),
+            tokenize("mem1+")
+        );
+        assert_eq!(
+            Ok(vec![Token::MemoryMinus("A".to_string())]),
+            tokenize(" memA - ")
+        );
+        assert_eq!(
+            Ok(vec![
+                Token::MemoryRef("1".to_string()),
+                Token::Plus,
+                number("2"),
+            ]),
+            tokenize("mem1+2")
+        );
+        // 複数文字の名前もメモリ
+        assert_eq!(
+            Ok(vec![Token::MemoryPlus("12".to_string())]),
+            tokenize("mem12+")
+        );
+        assert_eq!(
+            Ok(vec![Token::MemoryPlus("Total".to_string())]),
+            tokenize("memTotal+")
+        );
+        assert_eq!(
+            Ok(vec![Token::MemoryRef("Total2".to_string())]),
+            tokenize("memTotal2")
+        );
+        // mem で始まる他の名前は識別子
+        assert_eq!(
+            Ok(vec![
+                Token::Ident("memo".to_string()),
+                Token::Equals,
+                number("3"),
+            ]),
+            tokenize("memo = 3")
+        );
+        assert_eq!(
+            Ok(vec![Token::Ident("member".to_string()), Token::Minus,]),
+            tokenize("member -")
+        );
+        assert_eq!(Ok(vec![Token::Ident("mem".to_string())]), tokenize("mem"));
+        assert_eq!(
+            Ok(vec![Token::Ident("memory".to_string())]),
+            tokenize("memory")
+        );
+        assert_eq!(
+            Ok(vec![Token::Ident("mem1a".to_string())]),
+            tokenize("mem1a")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_history() {
+        assert_eq!(
+            Ok(vec![
+                Token::HistoryRef(1),
+                Token::Plus,
+                Token::HistoryRef(12)
+            ]),
+            tokenize("$1+$12")
+        );
+        assert_eq!(Err(LexError { column: 2, ch: '$' }), tokenize("1+$x"));
+    }
+
+    #[test]
+    fn test_tokenize_error() {
+        assert_eq!(Err(LexError { column: 4, ch: '#' }), tokenize("1 + # 2"));
+        assert_eq!(Err(LexError { column: 2, ch: '.' }), tokenize("1+."));
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let spans: Vec<_> = Token::tokenize(" 12.5 +x1")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.span)
+            .collect();
+        assert_eq!(vec![1..5, 6..7, 7..9], spans);
+    }
+}
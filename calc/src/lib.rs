@@ -0,0 +1,504 @@
+mod ast;
+mod data;
+mod error;
+mod eval;
+mod functions;
+mod lexer;
+mod matrix;
+mod number;
+mod numeric;
+mod parser;
+mod plot;
+mod session;
+mod simplify;
+mod stats;
+mod symbolic;
+mod unit;
+mod value;
+
+pub use data::DataError;
+pub use error::CalcError;
+pub use functions::AngleMode;
+pub use number::NumberMode;
+pub use plot::{Plot, PlotError, Style};
+pub use session::{Session, SessionError};
+pub use value::Value;
+
+use ast::{Expr, ExprKind};
+use eval::{Scope, LAST_RESULT};
+use lexer::{SpannedToken, Token};
+use number::Number;
+use parser::Definition;
+use std::collections::{hash_map::Entry, HashMap};
+use value::ValueError;
+
+/// ユーザー定義関数の呼び出しの深さの上限
+const MAX_CALL_DEPTH: usize = 64;
+
+/// 埋め込む側が登録した関数。引数の値を受け取り、失敗はメッセージで返す
+type NativeFunction = Box<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// 式の評価に必要な状態
+///
+/// 他のクレートからは new で作り、変数・関数を定義・登録して eval で式を評価する。
+pub struct Context {
+    memory: Memory,
+    angle_mode: AngleMode,
+    number_mode: NumberMode,
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, UserFunction>,
+    natives: HashMap<String, NativeFunction>,
+    history: Vec<HistoryEntry>, // 結果の一覧（$1 が先頭）
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self {
+            memory: Memory::new(),
+            angle_mode: AngleMode::Radians,
+            number_mode: NumberMode::Float,
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            natives: HashMap::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// 式を評価する。変数・関数の定義や履歴は変えない
+    pub fn eval(&self, expr: &str) -> Result<Value, CalcError> {
+        let tokens = Token::tokenize(expr)?;
+        let expr = parser::parse(&tokens, self.number_mode)?;
+        eval::eval(&expr, &Scope::global(self))
+    }
+
+    /// 電卓の1行を実行する
+    ///
+    /// 代入文であれば変数・関数を定義し、式であれば評価して値を履歴に追加する。
+    /// memX+、memX- は直前の結果をメモリに加える・から引く。
+    pub fn eval_line(&mut self, line: &str) -> Result<LineResult, CalcError> {
+        let tokens = Token::tokenize(line)?;
+        // メモリへの加算・減算には直前の結果を使う
+        let prev_result = self
+            .last_result()
+            .cloned()
+            .unwrap_or(Value::new(Number::Float(0.0)));
+        let memory_result = match tokens.first().map(|t| &t.token) {
+            Some(Token::MemoryPlus(memory_name)) => {
+                let memory_name = memory_name.to_string();
+                Some(self.memory.add(memory_name, prev_result.clone()))
+            }
+            Some(Token::MemoryMinus(memory_name)) => {
+                let memory_name = memory_name.to_string();
+                Some(
+                    prev_result
+                        .neg()
+                        .and_then(|value| self.memory.add(memory_name, value)),
+                )
+            }
+            _ => None,
+        };
+        match memory_result {
+            Some(Ok(result)) => Ok(LineResult::Memory(result)),
+            Some(Err(e)) => Err(CalcError::value(e, &tokens[0].span)),
+            None => eval_line(line, &tokens, self),
+        }
+    }
+
+    /// rate = 0.08、tax(x) = x * 1.1 の形の定義を読んで変数・関数を定義し、その名前を返す
+    ///
+    /// eval_line での定義と違い、変数の値は履歴に残さない。
+    pub fn define(&mut self, source: &str) -> Result<String, CalcError> {
+        let tokens = Token::tokenize(source)?;
+        let Some((definition, body)) = parser::parse_definition(&tokens)? else {
+            let end = tokens.last().map_or(0, |t| t.span.end);
+            return Err(CalcError::InvalidAssignment(0..end));
+        };
+        define(source, &tokens, definition, body, self).map(|(name, _)| name)
+    }
+
+    /// 変数の値
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// 変数に値を入れる。組み込みの名前や、名前として読めない文字列はエラー
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<(), CalcError> {
+        check_name(name)?;
+        self.variables.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Rustの関数を式から呼べるようにする。同じ名前のユーザー定義関数は置き換える
+    ///
+    /// 引数の個数や種類の確認は関数に任せ、失敗のメッセージは呼び出し位置のエラーになる。
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Result<(), CalcError> {
+        check_name(name)?;
+        self.functions.remove(name);
+        self.natives.insert(name.to_string(), Box::new(function));
+        Ok(())
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.number_mode
+    }
+
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.number_mode = mode;
+    }
+
+    /// 変数の名前と値（順不同）
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// ユーザー定義関数の名前と、定義した時の入力（順不同）
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.functions
+            .iter()
+            .map(|(name, function)| (name.as_str(), function.source.as_str()))
+    }
+
+    /// メモリの名前と値（順不同）
+    pub fn memory(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.memory
+            .slots
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn clear_memory(&mut self) {
+        self.memory.slots.clear();
+    }
+
+    /// 結果の履歴の入力と値（$1 が先頭）
+    pub fn history(&self) -> impl ExactSizeIterator<Item = (&str, &Value)> {
+        self.history
+            .iter()
+            .map(|entry| (entry.input.as_str(), &entry.value))
+    }
+
+    /// 補完の候補。組み込み関数・定数、定義・登録した関数、変数、メモリの名前（関数は括弧をつける）
+    pub fn names(&self) -> Vec<String> {
+        let functions = functions::builtin_names()
+            .map(str::to_string)
+            .chain(self.functions.keys().cloned())
+            .chain(self.natives.keys().cloned())
+            .map(|name| format!("{}(", name));
+        let mut names: Vec<String> = functions
+            .chain(
+                functions::CONSTANT_NAMES
+                    .iter()
+                    .map(|name| name.to_string()),
+            )
+            .chain(self.variables.keys().cloned())
+            .chain(self.memory.slots.keys().map(|name| format!("mem{}", name)))
+            .chain([LAST_RESULT.to_string()])
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// 式のパース結果と、定数を畳み込んだ結果
+    pub fn show(&self, expr: &str) -> Result<(String, String), CalcError> {
+        let tokens = Token::tokenize(expr)?;
+        let expr = parser::parse(&tokens, self.number_mode)?;
+        let simplified = simplify::simplify(&expr, self);
+        Ok((
+            expr.display(self.number_mode).to_string(),
+            simplified.display(self.number_mode).to_string(),
+        ))
+    }
+
+    /// 式functions（, で区切る）の変数xをlowerからupperまで変えたグラフ
+    pub fn plot(&self, functions: &str, lower: &str, upper: &str) -> Result<Plot, PlotError> {
+        plot::plot(functions, lower, upper, self)
+    }
+
+    /// CSVなどのテキストtextの列columnの数をベクトルにして変数nameに入れ、その個数を返す
+    pub fn load_data(&mut self, name: &str, text: &str, column: &str) -> Result<usize, DataError> {
+        let values = data::column(text, column, self.number_mode)?;
+        let count = values.len();
+        self.variables
+            .insert(name.to_string(), Value::Vector(values));
+        Ok(count)
+    }
+
+    /// 直前の結果（ans）
+    fn last_result(&self) -> Option<&Value> {
+        self.history.last().map(|entry| &entry.value)
+    }
+
+    fn push_history(&mut self, line: &str, value: Value) {
+        self.history.push(HistoryEntry {
+            input: line.trim().to_string(),
+            value,
+        });
+    }
+}
+
+/// nameが1つの識別子で、組み込みの名前でないことを確かめる
+fn check_name(name: &str) -> Result<(), CalcError> {
+    let span = 0..name.len().max(1);
+    match Token::tokenize(name)?.as_slice() {
+        [SpannedToken {
+            token: Token::Ident(ident),
+            ..
+        }] if ident == name => (),
+        _ => return Err(CalcError::InvalidAssignment(span)),
+    }
+    if parser::is_reserved(name) {
+        return Err(CalcError::ReservedName(name.to_string(), span));
+    }
+    Ok(())
+}
+
+/// 1行の評価結果
+#[derive(Debug, PartialEq)]
+pub enum LineResult {
+    Value(Value),       // 式または変数への代入の値（履歴に追加済み）
+    Memory(Value),      // memX+、memX- の後のメモリの値（履歴には追加しない）
+    Defined(String),    // 定義した関数の名前
+    Derivative(String), // diff(f, x) の導関数の式
+}
+
+/// 代入文であれば変数・関数を定義し、式であれば評価する。値は履歴に追加する
+fn eval_line(
+    line: &str,
+    tokens: &[SpannedToken],
+    ctx: &mut Context,
+) -> Result<LineResult, CalcError> {
+    let Some((definition, body)) = parser::parse_definition(tokens)? else {
+        let expr = parser::parse(tokens, ctx.number_mode)?;
+        // diff(f, x) は変数の値によらず導関数の式にする
+        if let ExprKind::Call(name, args) = &expr.kind {
+            if name == "diff" && args.len() == 2 {
+                let (derivative, _) = symbolic::derivative_of(args, ctx, &expr.span)?;
+                let derivative = derivative.display(ctx.number_mode).to_string();
+                return Ok(LineResult::Derivative(derivative));
+            }
+        }
+        let value = eval::eval(&expr, &Scope::global(ctx))?;
+        ctx.push_history(line, value.clone());
+        return Ok(LineResult::Value(value));
+    };
+    match define(line, tokens, definition, body, ctx)? {
+        (name, None) => Ok(LineResult::Defined(name)),
+        (_, Some(value)) => {
+            ctx.push_history(line, value.clone());
+            Ok(LineResult::Value(value))
+        }
+    }
+}
+
+/// 代入文の右辺（tokensのbody以降）で変数・関数を定義し、名前と、変数であればその値を返す
+fn define(
+    line: &str,
+    tokens: &[SpannedToken],
+    definition: Definition,
+    body: usize,
+    ctx: &mut Context,
+) -> Result<(String, Option<Value>), CalcError> {
+    // 右辺が空であれば = の直後を指す
+    if body == tokens.len() {
+        let end = tokens[body - 1].span.end;
+        return Err(CalcError::UnexpectedEnd(end..end + 1));
+    }
+    let expr = parser::parse(&tokens[body..], ctx.number_mode)?;
+    match definition {
+        Definition::Variable(name) => {
+            let value = eval::eval(&expr, &Scope::global(ctx))?;
+            ctx.variables.insert(name.clone(), value.clone());
+            Ok((name, Some(value)))
+        }
+        Definition::Function(name, params) => {
+            let function = UserFunction {
+                params,
+                body: expr,
+                source: line.trim().to_string(),
+            };
+            ctx.functions.insert(name.clone(), function);
+            Ok((name, None))
+        }
+    }
+}
+
+/// 結果の履歴の1件
+struct HistoryEntry {
+    input: String,
+    value: Value,
+}
+
+/// tax(x) = x * 1.1 のように定義した関数
+struct UserFunction {
+    params: Vec<String>,
+    body: Expr,
+    source: String, // 定義した時の入力（funcsでの表示用）
+}
+
+struct Memory {
+    slots: HashMap<String, Value>,
+}
+
+impl Memory {
+    fn new() -> Self {
+        Self {
+            slots: HashMap::new(),
+        }
+    }
+    fn add(&mut self, slot_name: String, prev_result: Value) -> Result<Value, ValueError> {
+        match self.slots.entry(slot_name) {
+            Entry::Occupied(mut entry) => {
+                // メモリが見つかった
+                let sum = entry.get().add(&prev_result)?;
+                entry.insert(sum.clone());
+                Ok(sum)
+            }
+            Entry::Vacant(entry) => {
+                // メモリ見つからなかった
+                entry.insert(prev_result.clone());
+                Ok(prev_result)
+            }
+        }
+    }
+
+    fn get(&self, slot_name: &str) -> Value {
+        self.slots
+            .get(slot_name)
+            .cloned()
+            .unwrap_or(Value::new(Number::Float(0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CalcError, Context, LineResult, Value};
+
+    #[test]
+    fn test_eval_line() {
+        let mut ctx = Context::new();
+        let value = |x: f64| Ok(LineResult::Value(Value::from(x)));
+        assert_eq!(value(3.0), ctx.eval_line("1 + 2"));
+        assert_eq!(value(0.08), ctx.eval_line("rate = 0.08"));
+        assert_eq!(
+            Ok(LineResult::Defined("tax".to_string())),
+            ctx.eval_line("tax(x) = x * (1 + rate)")
+        );
+        assert_eq!(
+            Ok(LineResult::Memory(Value::from(0.08))),
+            ctx.eval_line("memA+")
+        );
+    }
+
+    #[test]
+    fn test_definition_errors() {
+        let mut ctx = Context::new();
+        let mut run = |line: &str| ctx.eval_line(line).map(|_| ());
+        assert_eq!(
+            Err(CalcError::ReservedName("pi".to_string(), 0..2)),
+            run("pi = 3")
+        );
+        assert_eq!(
+            Err(CalcError::ReservedName("ans".to_string(), 0..3)),
+            run("ans = 1")
+        );
+        assert_eq!(
+            Err(CalcError::DuplicateParameter("a".to_string(), 5..6)),
+            run("f(a, a) = a")
+        );
+        assert_eq!(Err(CalcError::InvalidAssignment(0..7)), run("1 + 2 = 3"));
+        // 本体の構文エラーは定義した時に報告する
+        assert_eq!(Err(CalcError::UnexpectedEnd(10..11)), run("f(x) = x +"));
+        assert_eq!(Err(CalcError::UnexpectedEnd(3..4)), run("y ="));
+    }
+
+    #[test]
+    fn test_derivative_line() {
+        // diff(f, x) の行は、変数に値があっても導関数の式にする
+        let mut ctx = Context::new();
+        let derivative = Ok(LineResult::Derivative("3 * x ^ 2".to_string()));
+        assert_eq!(derivative, ctx.eval_line("diff(x^3, x)"));
+        ctx.eval_line("x = 3").unwrap();
+        assert_eq!(derivative, ctx.eval_line("diff(x^3, x)"));
+        assert_eq!(
+            Err(CalcError::ReservedName("diff".to_string(), 0..4)),
+            ctx.eval_line("diff = 1")
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        // 式と代入の値は履歴に残り、定義とエラーは残らない
+        let mut ctx = Context::new();
+        for line in ["1 + 2", "x = $1 + 2", "f(a) = a + ans", "1 / 0", "f(1)"] {
+            let _ = ctx.eval_line(line);
+        }
+        let inputs: Vec<_> = ctx.history().map(|(input, _)| input).collect();
+        assert_eq!(vec!["1 + 2", "x = $1 + 2", "f(1)"], inputs);
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut ctx = Context::new();
+        ctx.set_variable("rate", Value::from(0.5)).unwrap();
+        assert_eq!(Ok("tax".to_string()), ctx.define("tax(x) = x * (1 + rate)"));
+        ctx.register_function("half", |args| match args {
+            [value] => value.div(&Value::from(2.0)).map_err(|e| format!("{:?}", e)),
+            _ => Err("expected one argument".to_string()),
+        })
+        .unwrap();
+        assert_eq!(Some(1500.0), ctx.eval("tax(1000)").unwrap().to_f64());
+        assert_eq!("750", ctx.eval("half(tax(1000))").unwrap().to_string());
+        assert_eq!(
+            Some("0.5"),
+            ctx.variable("rate").map(|v| v.to_string()).as_deref()
+        );
+        // 評価は履歴に残らない
+        assert_eq!(
+            Err(CalcError::NoResult("ans".to_string(), 0..3)),
+            ctx.eval("ans")
+        );
+        assert_eq!(
+            Err(CalcError::Native(
+                "half".to_string(),
+                "expected one argument".to_string(),
+                4..8
+            )),
+            ctx.eval("1 + half(1, 2)")
+        );
+    }
+
+    #[test]
+    fn test_embedding_errors() {
+        let mut ctx = Context::new();
+        assert_eq!(
+            Err(CalcError::ReservedName("pi".to_string(), 0..2)),
+            ctx.set_variable("pi", Value::from(3.0))
+        );
+        assert_eq!(
+            Err(CalcError::InvalidAssignment(0..5)),
+            ctx.register_function("a + b", |_| Err(String::new()))
+        );
+        assert_eq!(Err(CalcError::InvalidAssignment(0..5)), ctx.define("1 + 2"));
+    }
+}
@@ -1,174 +1,164 @@
-use std::collections::{hash_map::Entry, HashMap};
-use std::io::stdin;
+mod cli;
+mod editor;
+mod repl;
+
+use calc::{CalcError, Session};
+use cli::{Format, Input, Options};
+use editor::LineReader;
+use repl::{run_line, Outcome, State};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let mut memory = Memory::new();
-    let mut prev_result: f64 = 0.0;
-    for line in stdin().lines() {
-        // 行毎読み込み
-        let line = line.unwrap();
-        if line.is_empty() {
-            break;
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", cli::USAGE);
+            process::exit(2);
         }
-        // トークン列に分割
-        let tokens = Token::split(&line);
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-        // 式の評価
-        match &tokens[0] {
-            Token::MemoryPlus(memory_name) => {
-                let memory_name = memory_name.to_string();
-                let result = memory.add(memory_name, prev_result);
-                print_output(result);
-            }
-            Token::MemoryMinus(memory_name) => {
-                let memory_name = memory_name.to_string();
-                let result = memory.add(memory_name, -prev_result);
-                print_output(result);
-            }
-            _ => {
-                let result = eval_expression(&tokens, &memory);
-                print_output(result);
-                prev_result = result;
+    let mut state = State::new();
+    let format = options.format;
+    let succeeded = match options.input {
+        // エラーがあっても残りの式を評価する
+        Input::Expressions(expressions) => {
+            let mut succeeded = true;
+            for line in &expressions {
+                let outcome = run_line(line, &mut state);
+                succeeded &= report(line, outcome, &state, format, None);
             }
+            succeeded
         }
+        Input::Script(path) => run_script(&path, &mut state, format),
+        Input::Stdin => repl(&mut state, format),
+    };
+    // スクリプトやパイプラインのためにエラーがあれば失敗で終わる
+    if !succeeded {
+        process::exit(1);
     }
 }
-fn print_output(value: f64) {
-    println!(" => {}", value);
-}
-
-struct Memory {
-    slots: HashMap<String, f64>,
-}
 
-impl Memory {
-    fn new() -> Self {
-        Self {
-            slots: HashMap::new(),
-        }
-    }
-    fn add(&mut self, slot_name: String, prev_result: f64) -> f64 {
-        match self.slots.entry(slot_name) {
-            Entry::Occupied(mut entry) => {
-                // メモリが見つかった
-                *entry.get_mut() += prev_result;
-                *entry.get()
-            }
-            Entry::Vacant(entry) => {
-                // メモリ見つからなかった
-                entry.insert(prev_result);
-                prev_result
+/// 標準入力の行を順に実行する。端末では行編集を使う。端末でなくエラーがあった場合はfalse
+///
+/// 自動保存が有効であれば前回の状態から始め、終了時に保存する。
+fn repl(state: &mut State, format: Format) -> bool {
+    if let Some(path) = repl::session_path().filter(|path| path.exists()) {
+        match Session::load(&path) {
+            Ok(saved) if saved.autosave => {
+                state.autosave = true;
+                saved.restore(&mut state.ctx);
+                if format == Format::Repl {
+                    println!(" => restored session from {}", path.display());
+                }
             }
+            Ok(_) => (),
+            Err(e) => eprintln!("error: {}: {}", path.display(), e),
         }
     }
 
-    fn get(&self, slot_name: &str) -> f64 {
-        self.slots.get(slot_name).copied().unwrap_or(0.0)
+    // 行毎読み込み。空行は読み飛ばし、入力の終わり（Ctrl-D）で終了する
+    let mut reader = LineReader::new();
+    let mut succeeded = true;
+    while let Some(line) = reader.read_line(&state.ctx) {
+        let outcome = run_line(&line, state);
+        // エラーの場合も次の行の読み込みを続ける
+        succeeded &= report(&line, outcome, state, format, None);
     }
-}
 
-#[derive(Debug, PartialEq)]
-enum Token {
-    Number(f64),
-    MemoryRef(String),
-    MemoryPlus(String),
-    MemoryMinus(String),
-    Plus,
-    Minus,
-    Asterisk,
-    Slash,
-    LParen,
-    RParen,
-}
-impl Token {
-    fn parse(value: &str) -> Self {
-        match value {
-            "+" => Self::Plus,
-            "-" => Self::Minus,
-            "*" => Self::Asterisk,
-            "/" => Self::Slash,
-            "(" => Self::LParen,
-            ")" => Self::RParen,
-            _ if value.starts_with("mem") => {
-                let mut memory_name = value[3..].to_string();
-                if value.ends_with('+') {
-                    memory_name.pop();
-                    Self::MemoryPlus(memory_name)
-                } else if value.ends_with('-') {
-                    memory_name.pop();
-                    Self::MemoryMinus(memory_name)
-                } else {
-                    Self::MemoryRef(memory_name)
-                }
+    reader.save_history();
+    if state.autosave {
+        if let Some(path) = repl::session_path() {
+            if let Err(e) = Session::save(&state.ctx, &path, true) {
+                eprintln!("error: {}: {}", path.display(), e);
             }
-            _ => Self::Number(value.parse().unwrap()),
         }
     }
-
-    fn split(text: &str) -> Vec<Self> {
-        text.split(char::is_whitespace).map(Self::parse).collect()
-    }
+    succeeded || reader.is_terminal()
 }
 
-fn eval_expression(tokens: &[Token], memory: &Memory) -> f64 {
-    let (result, index) = eval_additive_expression(tokens, 0, memory);
-    assert_eq!(tokens.len(), index);
-    result
-}
-fn eval_additive_expression(tokens: &[Token], index: usize, memory: &Memory) -> (f64, usize) {
-    let mut index = index;
-    let mut result;
-    (result, index) = eval_multiplicative_expression(tokens, index, memory);
-    while index < tokens.len() {
-        match &tokens[index] {
-            Token::Plus => {
-                let (value, next) = eval_multiplicative_expression(tokens, index + 1, memory);
-                result += value;
-                index = next;
-            }
-            Token::Minus => {
-                let (value, next) = eval_multiplicative_expression(tokens, index + 1, memory);
-                result -= value;
-                index = next;
-            }
-            _ => break,
+/// スクリプトファイルの行を順に実行する。エラーがあればfalse
+fn run_script(path: &Path, state: &mut State, format: Format) -> bool {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            return false;
         }
+    };
+    let mut succeeded = true;
+    for (i, line) in text.lines().enumerate() {
+        let outcome = run_line(line, state);
+        succeeded &= report(line, outcome, state, format, Some((path, i + 1)));
     }
-    (result, index)
+    succeeded
 }
-fn eval_multiplicative_expression(tokens: &[Token], index: usize, memory: &Memory) -> (f64, usize) {
-    let mut index = index;
-    let mut result;
-    (result, index) = eval_primary_expression(tokens, index, memory);
-    while index < tokens.len() {
-        match &tokens[index] {
-            Token::Asterisk => {
-                let (value, next) = eval_primary_expression(tokens, index + 1, memory);
-                result *= value;
-                index = next;
-            }
-            Token::Slash => {
-                let (value, next) = eval_primary_expression(tokens, index + 1, memory);
-                result /= value;
-                index = next;
-            }
-            _ => break,
+
+/// 1行の実行結果をformatで表示する。エラーであればfalse
+///
+/// locationはスクリプトのファイルと行番号（1始まり）で、エラーの前に表示する。
+fn report(
+    line: &str,
+    outcome: Outcome,
+    state: &State,
+    format: Format,
+    location: Option<(&Path, usize)>,
+) -> bool {
+    let succeeded = !matches!(outcome, Outcome::Error(..) | Outcome::Failure(_));
+    if format == Format::Json {
+        if let Some(record) = cli::json_outcome(line, &outcome, state) {
+            println!("{}", record);
         }
+        return succeeded;
     }
-    (result, index)
-}
-fn eval_primary_expression(tokens: &[Token], index: usize, memory: &Memory) -> (f64, usize) {
-    let first_token = &tokens[index];
-    match first_token {
-        Token::LParen => {
-            let (result, next) = eval_additive_expression(tokens, index + 1, memory);
-            assert_eq!(Token::RParen, tokens[next]);
-            (result, next + 1)
+
+    let plain = format == Format::Plain;
+    let error = |text: String| {
+        let text = match location {
+            Some((path, n)) => format!("{}:{}:\n{}", path.display(), n, text),
+            None => text,
+        };
+        // -e の結果はパイプで使うため、エラーは標準エラー出力に出す
+        if plain {
+            eprintln!("{}", text);
+        } else {
+            println!("{}", text);
         }
-        Token::Number(value) => (*value, index + 1),
-        Token::MemoryRef(memory_name) => (memory.get(memory_name), index + 1),
-        _ => {
-            unreachable!()
+    };
+    match outcome {
+        Outcome::Empty => (),
+        Outcome::Result(_, value) | Outcome::Value(value) if plain => {
+            println!("{}", state.format_value(&value))
         }
+        Outcome::Result(n, value) => println!(" ${} => {}", n, state.format_value(&value)),
+        Outcome::Value(value) => println!(" => {}", state.format_value(&value)),
+        Outcome::Derivative(expr) if plain => println!("{}", expr),
+        Outcome::Derivative(expr) => println!(" => derivative: {}", expr),
+        Outcome::Defined(name) => println!(" => defined {}", name),
+        Outcome::Message(text) => println!(" => {}", text),
+        Outcome::Text(text) if text.is_empty() => (),
+        Outcome::Text(text) => println!("{}", text),
+        Outcome::Error(text, e) => error(format_error(&text, &e)),
+        Outcome::Failure(message) => error(format!("error: {}", message)),
     }
+    succeeded
+}
+
+/// 入力行と、その下にエラーの位置を示す^を並べたエラーの表示
+fn format_error(line: &str, error: &CalcError) -> String {
+    let span = error.span();
+    format!(
+        "    {}\n    {}{}\nerror: {}",
+        line,
+        " ".repeat(span.start),
+        "^".repeat((span.end - span.start).max(1)),
+        error
+    )
 }
@@ -0,0 +1,499 @@
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::number::{Number, NumberMode};
+use crate::value::{Quantity, Value, ValueError};
+
+/// 行列。要素は行ごとに並べて持つ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    elements: Vec<Quantity>,
+}
+
+impl Matrix {
+    /// 行の並びから作る。行の長さがそろっていないか、要素がなければエラー
+    pub fn from_rows(rows: Vec<Vec<Quantity>>) -> Result<Self, ValueError> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if cols == 0 {
+            return Err(ValueError::Shape("empty matrix".to_string()));
+        }
+        if let Some(row) = rows.iter().find(|row| row.len() != cols) {
+            return Err(ValueError::Shape(format!(
+                "rows of length {} and {}",
+                cols,
+                row.len()
+            )));
+        }
+        Ok(Self {
+            rows: rows.len(),
+            cols,
+            elements: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    /// エラー表示に使う形の説明（2x3 matrix）
+    pub fn shape(&self) -> String {
+        format!("{}x{} matrix", self.rows, self.cols)
+    }
+
+    pub fn elements(&self) -> &[Quantity] {
+        &self.elements
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Quantity]> {
+        self.elements.chunks(self.cols)
+    }
+
+    fn get(&self, i: usize, j: usize) -> &Quantity {
+        &self.elements[i * self.cols + j]
+    }
+
+    pub fn map<E>(&self, op: impl FnMut(&Quantity) -> Result<Quantity, E>) -> Result<Self, E> {
+        Ok(Self {
+            elements: self.elements.iter().map(op).collect::<Result<_, _>>()?,
+            ..*self
+        })
+    }
+
+    /// 同じ形の行列の要素どうしにopを適用する
+    pub fn zip(
+        &self,
+        other: &Self,
+        op: impl Fn(&Quantity, &Quantity) -> Result<Quantity, ValueError>,
+    ) -> Result<Self, ValueError> {
+        Ok(Self {
+            elements: self
+                .elements
+                .iter()
+                .zip(&other.elements)
+                .map(|(a, b)| op(a, b))
+                .collect::<Result<_, _>>()?,
+            ..*self
+        })
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self {
+            rows: self.cols,
+            cols: self.rows,
+            elements: (0..self.cols)
+                .flat_map(|j| (0..self.rows).map(move |i| self.get(i, j).clone()))
+                .collect(),
+        }
+    }
+
+    /// 行列の積
+    pub fn mul(&self, other: &Self) -> Result<Self, ValueError> {
+        if self.cols != other.rows {
+            return Err(ValueError::Shape(format!(
+                "{} and {}",
+                self.shape(),
+                other.shape()
+            )));
+        }
+        let columns = other.transpose();
+        let elements = self
+            .rows()
+            .flat_map(|row| columns.rows().map(move |column| dot_product(row, column)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            rows: self.rows,
+            cols: other.cols,
+            elements,
+        })
+    }
+
+    /// 行列と列ベクトルの積
+    pub fn mul_vector(&self, vector: &[Quantity]) -> Result<Vec<Quantity>, ValueError> {
+        if self.cols != vector.len() {
+            return Err(ValueError::Shape(format!(
+                "{} and vector of {}",
+                self.shape(),
+                vector.len()
+            )));
+        }
+        self.rows().map(|row| dot_product(row, vector)).collect()
+    }
+
+    /// 正方行列の単位なしの要素を行ごとに取り出す
+    fn square_numbers(&self) -> Result<Vec<Vec<Number>>, ValueError> {
+        if self.rows != self.cols {
+            return Err(ValueError::Shape(format!("{} is not square", self.shape())));
+        }
+        self.rows()
+            .map(|row| row.iter().map(|q| q.plain().cloned()).collect())
+            .collect()
+    }
+
+    /// 行列式。分数を作らない消去法（Bareiss法）で計算するため、整数の行列は整数のまま誤差なく求まる
+    pub fn determinant(&self, mode: NumberMode) -> Result<Number, ValueError> {
+        let mut m = self.square_numbers()?;
+        let float = to_exact(&mut m);
+        let n = m.len();
+        let mut negate = false;
+        let mut previous: Option<Number> = None;
+        for k in 0..n {
+            let Some(pivot) = pivot_row(&m, k) else {
+                return Ok(Number::parse("0", mode)?);
+            };
+            if pivot != k {
+                m.swap(pivot, k);
+                negate = !negate;
+            }
+            for i in k + 1..n {
+                for j in k + 1..n {
+                    let x = m[i][j].mul(&m[k][k])?.sub(&m[i][k].mul(&m[k][j])?)?;
+                    m[i][j] = match &previous {
+                        Some(p) => x.div(p)?,
+                        None => x,
+                    };
+                }
+            }
+            previous = Some(m[k][k].clone());
+        }
+        let det = from_exact(m[n - 1][n - 1].clone(), float);
+        Ok(if negate { det.neg()? } else { det })
+    }
+
+    /// 逆行列
+    pub fn inverse(&self, mode: NumberMode) -> Result<Self, ValueError> {
+        let n = self.rows;
+        let identity = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| Number::parse(if i == j { "1" } else { "0" }, mode))
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        self.solve_numbers(identity)
+    }
+
+    /// 連立一次方程式 self x = b の解。bがベクトルであればベクトル、行列であれば列ごとの解の行列
+    pub fn solve(&self, b: &Value) -> Result<Value, ValueError> {
+        match b {
+            Value::Vector(elements) if elements.len() == self.rows => {
+                let column = elements
+                    .iter()
+                    .map(|q| Ok(vec![q.plain()?.clone()]))
+                    .collect::<Result<_, ValueError>>()?;
+                let solution = self.solve_numbers(column)?;
+                Ok(Value::Vector(solution.elements))
+            }
+            Value::Matrix(matrix) if matrix.rows == self.rows => {
+                let rows = matrix
+                    .rows()
+                    .map(|row| row.iter().map(|q| q.plain().cloned()).collect())
+                    .collect::<Result<_, _>>()?;
+                self.solve_numbers(rows).map(Value::Matrix)
+            }
+            _ => Err(ValueError::Shape(format!(
+                "{} and {}",
+                self.shape(),
+                b.shape()
+            ))),
+        }
+    }
+
+    /// 拡大行列 [self | b] を掃き出して [I | x] にし、xを返す
+    fn solve_numbers(&self, b: Vec<Vec<Number>>) -> Result<Self, ValueError> {
+        let mut m = self.square_numbers()?;
+        let n = m.len();
+        for (row, rhs) in m.iter_mut().zip(b) {
+            row.extend(rhs);
+        }
+        let float = to_exact(&mut m);
+        for k in 0..n {
+            let pivot = pivot_row(&m, k).ok_or(ValueError::Singular)?;
+            m.swap(pivot, k);
+            let p = m[k][k].clone();
+            for x in &mut m[k][k..] {
+                *x = x.div(&p)?;
+            }
+            let pivot = m[k].clone();
+            for (_, row) in m.iter_mut().enumerate().filter(|&(i, _)| i != k) {
+                let factor = row[k].clone();
+                if factor.is_zero() {
+                    continue;
+                }
+                for (x, y) in row[k..].iter_mut().zip(&pivot[k..]) {
+                    *x = x.sub(&factor.mul(y)?)?;
+                }
+            }
+        }
+        let rows = m
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .skip(n)
+                    .map(|x| Quantity::new(from_exact(x, float)))
+                    .collect()
+            })
+            .collect();
+        Self::from_rows(rows)
+    }
+}
+
+/// 要素がすべて浮動小数点数であれば、その値ちょうどの有理数にしてtrueを返す
+///
+/// 消去の途中で誤差がたまらないようにするため（inv([[1, 2], [3, 4]]) の結果が -2 などちょうどになる）。
+fn to_exact(m: &mut [Vec<Number>]) -> bool {
+    if !m.iter().flatten().all(|x| matches!(x, Number::Float(_))) {
+        return false;
+    }
+    for x in m.iter_mut().flatten() {
+        *x = x.to_mode(NumberMode::Rational);
+    }
+    true
+}
+
+/// to_exactで有理数にした結果を浮動小数点数に戻す
+fn from_exact(x: Number, float: bool) -> Number {
+    if float {
+        x.to_mode(NumberMode::Float)
+    } else {
+        x
+    }
+}
+
+/// k列目のk行目以降で絶対値が最大の0でない要素の行（部分ピボット選択）
+fn pivot_row(m: &[Vec<Number>], k: usize) -> Option<usize> {
+    (k..m.len())
+        .filter(|&i| !m[i][k].is_zero())
+        .max_by(|&i, &j| {
+            let norm = |i: usize| m[i][k].to_complex().norm();
+            norm(i).total_cmp(&norm(j))
+        })
+}
+
+/// 同じ長さの要素の並びの内積。空であればエラー
+fn dot_product(a: &[Quantity], b: &[Quantity]) -> Result<Quantity, ValueError> {
+    let mut products = a.iter().zip(b).map(|(x, y)| x.mul(y));
+    let Some(first) = products.next() else {
+        return Err(ValueError::Shape("empty vectors".to_string()));
+    };
+    let first = first?;
+    products.try_fold(first, |sum, product| sum.add(&product?))
+}
+
+/// ベクトル・行列の組み込み関数の名前の一覧
+pub const MATRIX_FUNCTION_NAMES: &[&str] = &["dot", "cross", "transpose", "det", "inv", "solve"];
+
+/// ベクトル・行列の組み込み関数を呼び出す。nameがそれでなければNone
+pub fn call(
+    name: &str,
+    args: &[Value],
+    mode: NumberMode,
+    span: &Span,
+) -> Option<Result<Value, CalcError>> {
+    let arity = match name {
+        "transpose" | "det" | "inv" => 1,
+        "dot" | "cross" | "solve" => 2,
+        _ => return None,
+    };
+    if args.len() != arity {
+        let expected = arity.to_string();
+        return Some(Err(CalcError::ArgumentCount(
+            name.to_string(),
+            expected,
+            span.clone(),
+        )));
+    }
+    let result = match (name, &args[0]) {
+        ("transpose", Value::Matrix(m)) => Ok(Value::Matrix(m.transpose())),
+        // ベクトルは列ベクトルの行列にする
+        ("transpose", Value::Vector(v)) => {
+            Matrix::from_rows(v.iter().map(|q| vec![q.clone()]).collect()).map(Value::Matrix)
+        }
+        ("transpose", scalar) => Ok(scalar.clone()),
+        ("dot", a) => match (a, &args[1]) {
+            (Value::Vector(x), Value::Vector(y)) if x.len() == y.len() => {
+                dot_product(x, y).map(Value::Scalar)
+            }
+            (a, b) => Err(a.mismatch(b)),
+        },
+        ("cross", a) => match (a, &args[1]) {
+            (Value::Vector(x), Value::Vector(y)) if x.len() == 3 && y.len() == 3 => {
+                cross_product(x, y).map(Value::Vector)
+            }
+            (a, b) => Err(ValueError::Shape(format!(
+                "{} and {} (cross needs vectors of 3)",
+                a.shape(),
+                b.shape()
+            ))),
+        },
+        ("det", Value::Matrix(m)) => m.determinant(mode).map(Value::new),
+        ("inv", Value::Matrix(m)) => m.inverse(mode).map(Value::Matrix),
+        ("solve", Value::Matrix(m)) => m.solve(&args[1]),
+        (_, a) => Err(ValueError::Shape(format!("{} is not a matrix", a.shape()))),
+    };
+    Some(result.map_err(|e| CalcError::value(e, span)))
+}
+
+/// 3次元ベクトルの外積
+fn cross_product(a: &[Quantity], b: &[Quantity]) -> Result<Vec<Quantity>, ValueError> {
+    let term = |i: usize, j: usize| a[i].mul(&b[j])?.sub(&a[j].mul(&b[i])?);
+    Ok(vec![term(1, 2)?, term(2, 0)?, term(0, 1)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Matrix;
+    use crate::error::CalcError;
+    use crate::number::{Number, NumberMode};
+    use crate::value::{Quantity, Value, ValueError};
+    use crate::Context;
+
+    /// 評価して表示用の文字列にする
+    fn eval(line: &str) -> Result<String, CalcError> {
+        Context::new().eval(line).map(|value| value.to_string())
+    }
+
+    fn matrix(rows: &[&[i64]], mode: NumberMode) -> Matrix {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|x| Quantity::new(Number::parse(&x.to_string(), mode).unwrap()))
+                    .collect()
+            })
+            .collect();
+        Matrix::from_rows(rows).unwrap()
+    }
+
+    #[test]
+    fn test_product() {
+        let mode = NumberMode::Float;
+        let a = matrix(&[&[1, 2], &[3, 4]], mode);
+        let b = matrix(&[&[5, 6], &[7, 8]], mode);
+        assert_eq!(
+            "[[19, 22], [43, 50]]",
+            Value::Matrix(a.mul(&b).unwrap()).to_string()
+        );
+        assert_eq!("[[1, 3], [2, 4]]", Value::Matrix(a.transpose()).to_string());
+        let c = matrix(&[&[1, 2, 3]], mode);
+        assert_eq!(
+            Err(ValueError::Shape("2x2 matrix and 1x3 matrix".to_string())),
+            a.mul(&c)
+        );
+    }
+
+    #[test]
+    fn test_linear_algebra() {
+        // 有理数のモードでは誤差なく求まる
+        let mode = NumberMode::Rational;
+        let a = matrix(&[&[2, 1], &[1, 3]], mode);
+        assert_eq!("5", a.determinant(mode).unwrap().to_string());
+        assert_eq!(
+            "[[3/5, -1/5], [-1/5, 2/5]]",
+            Value::Matrix(a.inverse(mode).unwrap()).to_string()
+        );
+        let b = Value::Vector(vec![
+            Quantity::new(Number::parse("3", mode).unwrap()),
+            Quantity::new(Number::parse("5", mode).unwrap()),
+        ]);
+        assert_eq!("[4/5, 7/5]", a.solve(&b).unwrap().to_string());
+
+        let permutation = matrix(&[&[0, 1, 0], &[0, 0, 1], &[1, 0, 0]], mode);
+        assert_eq!("1", permutation.determinant(mode).unwrap().to_string());
+        let singular = matrix(&[&[1, 2], &[2, 4]], mode);
+        assert_eq!("0", singular.determinant(mode).unwrap().to_string());
+        assert_eq!(Err(ValueError::Singular), singular.inverse(mode));
+        assert_eq!(
+            Err(ValueError::Shape("1x2 matrix is not square".to_string())),
+            matrix(&[&[1, 2]], mode).determinant(mode)
+        );
+    }
+
+    #[test]
+    fn test_vector_expressions() {
+        assert_eq!(Ok("[5, 7, 9]".to_string()), eval("[1, 2, 3] + [4, 5, 6]"));
+        assert_eq!(Ok("[2, 4, 6]".to_string()), eval("2 * [1, 2, 3]"));
+        assert_eq!(Ok("[1000 m, 2000 m]".to_string()), eval("[1, 2] km to m"));
+        assert_eq!(Ok("[2, 3]".to_string()), eval("sqrt([4, 9])"));
+        assert_eq!(Ok("32".to_string()), eval("dot([1, 2, 3], [4, 5, 6])"));
+        assert_eq!(
+            Ok("[0, 0, 1]".to_string()),
+            eval("cross([1, 0, 0], [0, 1, 0])")
+        );
+    }
+
+    #[test]
+    fn test_matrix_expressions() {
+        assert_eq!(
+            Ok("[[19, 22], [43, 50]]".to_string()),
+            eval("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]")
+        );
+        assert_eq!(Ok("[3, 7]".to_string()), eval("[[1, 2], [3, 4]] * [1, 1]"));
+        assert_eq!(
+            Ok("[[1, 3], [2, 4]]".to_string()),
+            eval("transpose([[1, 2], [3, 4]])")
+        );
+        assert_eq!(Ok("-2".to_string()), eval("det([[1, 2], [3, 4]])"));
+        assert_eq!(
+            Ok("[[-2, 1], [1.5, -0.5]]".to_string()),
+            eval("inv([[1, 2], [3, 4]])")
+        );
+        assert_eq!(
+            Ok("[0.8, 1.4]".to_string()),
+            eval("solve([[2, 1], [1, 3]], [3, 5])")
+        );
+    }
+
+    #[test]
+    fn test_shape_errors() {
+        // 形が合わなければ評価のエラー
+        assert_eq!(
+            Err(CalcError::ShapeMismatch(
+                "vector of 2 and vector of 3".to_string(),
+                7..8
+            )),
+            eval("[1, 2] + [1, 2, 3]")
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch(
+                "2x2 matrix and 1x2 matrix".to_string(),
+                17..18
+            )),
+            eval("[[1, 2], [3, 4]] * [[1, 2]]")
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch(
+                "rows of length 2 and 1".to_string(),
+                0..1
+            )),
+            eval("[[1, 2], [3]]")
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch(
+                "2x3 matrix is not square".to_string(),
+                0..3
+            )),
+            eval("det([[1, 2, 3], [4, 5, 6]])")
+        );
+        assert_eq!(
+            Err(CalcError::SingularMatrix(0..3)),
+            eval("inv([[1, 2], [2, 4]])")
+        );
+        assert_eq!(Err(CalcError::NotScalar(7..8)), eval("[1, 2] & 1"));
+    }
+
+    #[test]
+    fn test_empty() {
+        // [] は空のベクトル。要素の要る関数や空の行列は形のエラー
+        assert_eq!(Ok("[]".to_string()), eval("[]"));
+        assert_eq!(Ok("[]".to_string()), eval("2 * [] + []"));
+        assert_eq!(
+            Err(CalcError::ShapeMismatch("empty vectors".to_string(), 0..3)),
+            eval("dot([], [])")
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch("empty matrix".to_string(), 0..9)),
+            eval("transpose([])")
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch("empty matrix".to_string(), 0..1)),
+            eval("[[], []]")
+        );
+    }
+}
@@ -0,0 +1,1243 @@
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// 計算に使う数の表現
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberMode {
+    Float,            // f64（既定）
+    Rational,         // 多倍長の有理数（誤差なし）
+    Decimal(u32),     // 小数点以下の桁数を固定した多倍長の10進数
+    Integer(IntType), // 幅と符号を指定した整数（プログラマモード）
+}
+
+impl NumberMode {
+    /// プログラマモードか（^ はべき乗ではなく排他的論理和になる）
+    pub fn is_integer(self) -> bool {
+        matches!(self, NumberMode::Integer(_))
+    }
+
+    /// `float`、`rational`、`decimal [scale]`、`i8..u128 [checked]` を読む
+    ///
+    /// decimalの桁数の既定は2で上限はMAX_DECIMAL_SCALE、整数型のオーバーフローの既定はwrapping。
+    pub fn parse(text: &str) -> Option<Self> {
+        let args: Vec<&str> = text.split_whitespace().collect();
+        match args[..] {
+            ["float"] => Some(NumberMode::Float),
+            ["rational"] => Some(NumberMode::Rational),
+            ["decimal"] => Some(NumberMode::Decimal(2)),
+            ["decimal", scale] => scale
+                .parse()
+                .ok()
+                .filter(|&scale| scale <= MAX_DECIMAL_SCALE)
+                .map(NumberMode::Decimal),
+            [ty] | [ty, "wrapping"] => IntType::parse(ty, false).map(NumberMode::Integer),
+            [ty, "checked"] => IntType::parse(ty, true).map(NumberMode::Integer),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberMode::Float => write!(f, "float"),
+            NumberMode::Rational => write!(f, "rational"),
+            NumberMode::Decimal(scale) => write!(f, "decimal {}", scale),
+            NumberMode::Integer(ty) => write!(f, "{}", ty),
+        }
+    }
+}
+
+/// プログラマモードの整数型（i8〜u128）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntType {
+    pub bits: u32,
+    pub signed: bool,
+    pub checked: bool, // trueであれば範囲外をエラーにし、falseであれば折り返す
+}
+
+impl IntType {
+    /// i8、u16 などの型名を読む
+    pub fn parse(name: &str, checked: bool) -> Option<Self> {
+        let signed = match name.chars().next()? {
+            'i' => true,
+            'u' => false,
+            _ => return None,
+        };
+        let bits = match &name[1..] {
+            "8" => 8,
+            "16" => 16,
+            "32" => 32,
+            "64" => 64,
+            "128" => 128,
+            _ => return None,
+        };
+        Some(Self {
+            bits,
+            signed,
+            checked,
+        })
+    }
+
+    fn modulus(&self) -> BigInt {
+        BigInt::one() << self.bits
+    }
+
+    fn min(&self) -> BigInt {
+        if self.signed {
+            -(BigInt::one() << (self.bits - 1))
+        } else {
+            BigInt::zero()
+        }
+    }
+
+    fn max(&self) -> BigInt {
+        if self.signed {
+            (BigInt::one() << (self.bits - 1)) - 1
+        } else {
+            self.modulus() - 1
+        }
+    }
+
+    /// 2の補数として下位bitsビットに切り詰める
+    fn wrap(&self, value: BigInt) -> BigInt {
+        let value = value.mod_floor(&self.modulus());
+        if value > self.max() {
+            value - self.modulus()
+        } else {
+            value
+        }
+    }
+
+    /// 型の範囲に収める。範囲外であれば折り返すか、エラーにする
+    fn fit(&self, value: BigInt) -> Result<BigInt, ArithmeticError> {
+        if value >= self.min() && value <= self.max() {
+            Ok(value)
+        } else if self.checked {
+            Err(ArithmeticError::Overflow)
+        } else {
+            Ok(self.wrap(value))
+        }
+    }
+
+    /// 値のビット列（2の補数）を符号なしの整数として返す
+    fn bit_pattern(&self, value: &BigInt) -> BigInt {
+        value.mod_floor(&self.modulus())
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        let overflow = if self.checked { "checked" } else { "wrapping" };
+        write!(f, "{}{} {}", sign, self.bits, overflow)
+    }
+}
+
+/// 固定小数点の10進数。値は mantissa / 10^scale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    /// 有理数を小数点以下scale桁に丸める（四捨五入）
+    fn from_rational(value: &BigRational, scale: u32) -> Self {
+        let scaled = value * BigRational::from_integer(pow10(scale));
+        Self {
+            mantissa: scaled.round().to_integer(),
+            scale,
+        }
+    }
+
+    fn to_rational(&self) -> BigRational {
+        BigRational::new(self.mantissa.clone(), pow10(self.scale))
+    }
+}
+
+/// 10進数のリテラルをf64で読む。f64で表せないほど大きければOverflow
+fn parse_f64(text: &str) -> Result<f64, ArithmeticError> {
+    text.parse()
+        .ok()
+        .filter(|x: &f64| x.is_finite())
+        .ok_or(ArithmeticError::Overflow)
+}
+
+fn pow10(exp: u32) -> BigInt {
+    num_traits::pow(BigInt::from(10), exp as usize)
+}
+
+/// 10進数・有理数で読む数値リテラルの指数の絶対値の上限（1e10000 まで）
+const MAX_LITERAL_EXPONENT: i32 = 10_000;
+
+/// 10進数モードの小数点以下の桁数の上限
+const MAX_DECIMAL_SCALE: u32 = 1000;
+
+/// 整数モード以外で誤差なく計算するべき乗の結果のビット数の上限
+const MAX_POW_BITS: u64 = 1 << 20;
+
+/// 整数モード以外でのシフト量の上限（巨大な整数を作らないため）
+const MAX_SHIFT: u32 = 4096;
+
+/// 数。演算は両辺の表現をそろえてから行う
+///
+/// f64が混ざると結果はf64になる。有理数・10進数・整数のうち異なるものが混ざると誤差のない有理数になる。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Float(f64),
+    Rational(BigRational),
+    Decimal(Decimal),
+    Integer(BigInt, IntType),
+    Complex(Complex64), // 虚部が0でない複素数（f64）
+}
+
+/// 演算の失敗
+#[derive(Debug, PartialEq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow, // 整数型の範囲外（checked）、範囲外のシフト量、またはf64で表せないほど大きい値
+    NotInteger, // ビット演算や整数モードのリテラルに整数でない値を使った
+    NotReal,  // 切り捨てや剰余などに複素数を使った
+}
+
+impl Number {
+    /// 数値リテラル（123、1.5、1.5e-3、0xff、0b1010、0o17、4i）をmodeの表現で読む
+    ///
+    /// 10進数の文字列のまま変換するため誤差がない。
+    /// 整数モードでは、基数つきのリテラルは型の幅に収まるビット列として読む（i8で0xffは-1）。
+    /// 虚数のリテラルはモードによらずf64の複素数になる。
+    pub fn parse(text: &str, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        if let Some(imaginary) = text.strip_suffix('i') {
+            let im = parse_f64(imaginary)?;
+            return Ok(Number::from_complex(Complex64::new(0.0, im)));
+        }
+        let radix = match text.get(..2) {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            Some("0o") => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            let digits = text[2..].replace('_', "");
+            let value =
+                BigInt::parse_bytes(digits.as_bytes(), radix).ok_or(ArithmeticError::NotInteger)?;
+            return match mode {
+                NumberMode::Integer(ty) if value < ty.modulus() => {
+                    Ok(Number::Integer(ty.wrap(value), ty))
+                }
+                NumberMode::Integer(ty) => Ok(Number::Integer(ty.fit(value)?, ty)),
+                _ => Ok(Number::Rational(BigRational::from_integer(value)).to_mode(mode)),
+            };
+        }
+
+        if mode == NumberMode::Float {
+            return parse_f64(text).map(Number::Float);
+        }
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (
+                &text[..i],
+                text[i + 1..]
+                    .parse::<i32>()
+                    .map_err(|_| ArithmeticError::Overflow)?,
+            ),
+            None => (text, 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: BigInt = format!("{}{}", int_part, frac_part)
+            .parse()
+            .map_err(|_| ArithmeticError::NotInteger)?;
+        if exponent.unsigned_abs() > MAX_LITERAL_EXPONENT as u32 {
+            return Err(ArithmeticError::Overflow);
+        }
+        let exponent = i32::try_from(frac_part.len())
+            .ok()
+            .and_then(|len| exponent.checked_sub(len))
+            .ok_or(ArithmeticError::Overflow)?;
+        let value = if exponent >= 0 {
+            BigRational::from_integer(digits * pow10(exponent as u32))
+        } else {
+            BigRational::new(digits, pow10(exponent.unsigned_abs()))
+        };
+        match mode {
+            NumberMode::Integer(ty) if value.is_integer() => {
+                Ok(Number::Integer(ty.fit(value.to_integer())?, ty))
+            }
+            NumberMode::Integer(_) => Err(ArithmeticError::NotInteger),
+            _ => Ok(Number::Rational(value).to_mode(mode)),
+        }
+    }
+
+    /// f64で計算した値（関数や定数）をmodeの表現にする
+    ///
+    /// 有理数のモードでは近似値であることがわかるよう、f64のまま残す。
+    /// 整数モードでは0の方向に切り捨てる。
+    pub fn from_f64(value: f64, mode: NumberMode) -> Self {
+        match mode {
+            NumberMode::Decimal(_) | NumberMode::Integer(_) => match BigRational::from_f64(value) {
+                Some(r) => Number::Rational(r).to_mode(mode),
+                None => Number::Float(value),
+            },
+            _ => Number::Float(value),
+        }
+    }
+
+    /// 複素数の計算結果。虚部が0であればf64にする
+    pub fn from_complex(z: Complex64) -> Self {
+        if z.im == 0.0 {
+            Number::Float(z.re)
+        } else {
+            Number::Complex(z)
+        }
+    }
+
+    /// 偏角を使って計算した複素数の結果
+    ///
+    /// 円周率の誤差で残る、絶対値に比べてごく小さい実部・虚部は0にする（(-1)^0.5 は 1i）。
+    pub fn from_complex_approx(z: Complex64) -> Self {
+        let epsilon = z.norm() * 4.0 * f64::EPSILON;
+        let clean = |x: f64| if x.abs() < epsilon { 0.0 } else { x };
+        Number::from_complex(Complex64::new(clean(z.re), clean(z.im)))
+    }
+
+    /// 複素数として見た値
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Number::Complex(z) => *z,
+            _ => Complex64::new(self.to_f64(), 0.0),
+        }
+    }
+
+    /// 保存しておいた値（変数やメモリ）をmodeの表現に変換する
+    ///
+    /// 整数モードでは小数部を切り捨て、範囲外の値は折り返す。複素数はそのまま残す。
+    pub fn to_mode(&self, mode: NumberMode) -> Self {
+        match (self, mode) {
+            (Number::Complex(_), _) => self.clone(),
+            (Number::Float(_), NumberMode::Float) => self.clone(),
+            (_, NumberMode::Float) => Number::Float(self.to_f64()),
+            (_, NumberMode::Rational) => match self.to_rational() {
+                Some(r) => Number::Rational(r),
+                None => self.clone(),
+            },
+            (_, NumberMode::Decimal(scale)) => match self.to_rational() {
+                Some(r) => Number::Decimal(Decimal::from_rational(&r, scale)),
+                None => self.clone(),
+            },
+            (_, NumberMode::Integer(ty)) => match self.to_rational() {
+                Some(r) => Number::Integer(ty.wrap(r.trunc().to_integer()), ty),
+                None => self.clone(),
+            },
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Float(x) => *x,
+            Number::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Decimal(d) => d.to_rational().to_f64().unwrap_or(f64::NAN),
+            Number::Integer(n, _) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Complex(_) => f64::NAN,
+        }
+    }
+
+    /// 誤差のない有理数に変換する。f64は表示される10進数の値として扱う（0.1は1/10）
+    fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Number::Float(x) if x.is_finite() => {
+                match Number::parse(&format!("{:e}", x), NumberMode::Rational).ok()? {
+                    Number::Rational(r) => Some(r),
+                    _ => None,
+                }
+            }
+            Number::Float(_) | Number::Complex(_) => None,
+            Number::Rational(r) => Some(r.clone()),
+            Number::Decimal(d) => Some(d.to_rational()),
+            Number::Integer(n, _) => Some(BigRational::from_integer(n.clone())),
+        }
+    }
+
+    /// 値が整数であれば、その値
+    fn to_integer(&self) -> Option<BigInt> {
+        self.to_rational()
+            .filter(|r| r.is_integer())
+            .map(|r| r.to_integer())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Float(x) => *x == 0.0,
+            Number::Rational(r) => r.is_zero(),
+            Number::Decimal(d) => d.mantissa.is_zero(),
+            Number::Integer(n, _) => n.is_zero(),
+            Number::Complex(z) => z.is_zero(),
+        }
+    }
+
+    /// 有理数・10進数・整数同士の演算をopで行い、f64が混ざる場合はfloat_opで行う
+    ///
+    /// 整数同士の結果は0の方向に切り捨てる。複素数が混ざる場合はcomplex_opで行い、
+    /// complex_opがなければエラーにする。
+    fn binary(
+        &self,
+        other: &Self,
+        complex_op: Option<fn(Complex64, Complex64) -> Complex64>,
+        float_op: impl Fn(f64, f64) -> f64,
+        op: impl Fn(&BigRational, &BigRational) -> BigRational,
+    ) -> Result<Self, ArithmeticError> {
+        let exact = || op(&self.to_rational().unwrap(), &other.to_rational().unwrap());
+        match (self, other) {
+            (Number::Complex(_), _) | (_, Number::Complex(_)) => {
+                let complex_op = complex_op.ok_or(ArithmeticError::NotReal)?;
+                Ok(Number::from_complex(complex_op(
+                    self.to_complex(),
+                    other.to_complex(),
+                )))
+            }
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                Ok(Number::Float(float_op(self.to_f64(), other.to_f64())))
+            }
+            (Number::Decimal(a), Number::Decimal(b)) => Ok(Number::Decimal(
+                Decimal::from_rational(&exact(), a.scale.max(b.scale)),
+            )),
+            (Number::Integer(_, ty), Number::Integer(..)) => {
+                Ok(Number::Integer(ty.fit(exact().trunc().to_integer())?, *ty))
+            }
+            _ => Ok(Number::Rational(exact())),
+        }
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.binary(other, Some(|a, b| a + b), |a, b| a + b, |a, b| a + b)
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.binary(other, Some(|a, b| a - b), |a, b| a - b, |a, b| a - b)
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        self.binary(other, Some(|a, b| a * b), |a, b| a * b, |a, b| a * b)
+    }
+
+    /// 除算。整数同士であれば0の方向に切り捨てる
+    pub fn div(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if other.is_zero() {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        self.binary(other, Some(|a, b| a / b), |a, b| a / b, |a, b| a / b)
+    }
+
+    /// 切り捨て除算
+    pub fn floor_div(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if other.is_zero() {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        self.binary(other, None, |a, b| (a / b).floor(), |a, b| (a / b).floor())
+    }
+
+    /// 切り捨て除算の余り（結果の符号は除数と同じ）
+    pub fn rem(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if other.is_zero() {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        self.binary(
+            other,
+            None,
+            |a, b| a - b * (a / b).floor(),
+            |a, b| a - b * (a / b).floor(),
+        )
+    }
+
+    pub fn neg(&self) -> Result<Self, ArithmeticError> {
+        match self {
+            Number::Float(x) => Ok(Number::Float(-x)),
+            Number::Rational(r) => Ok(Number::Rational(-r)),
+            Number::Decimal(d) => Ok(Number::Decimal(Decimal {
+                mantissa: -&d.mantissa,
+                scale: d.scale,
+            })),
+            Number::Integer(n, ty) => Ok(Number::Integer(ty.fit(-n)?, *ty)),
+            Number::Complex(z) => Ok(Number::Complex(-z)),
+        }
+    }
+
+    /// べき乗。指数が整数であれば有理数・10進数・整数のまま計算する
+    ///
+    /// 複素数や、負の数の整数でない指数（(-1)^0.5）は複素数で計算する。
+    pub fn pow(&self, exponent: &Self, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        let complex = matches!(
+            (self, exponent),
+            (Number::Complex(_), _) | (_, Number::Complex(_))
+        ) || (self.to_f64() < 0.0 && exponent.to_integer().is_none());
+        if complex {
+            if self.is_zero() {
+                return Err(ArithmeticError::DivisionByZero);
+            }
+            // 整数の指数は掛け算で計算する（(1 + 2i)^2 は -3 + 4i）
+            let z = self.to_complex();
+            return Ok(match exponent.to_integer().and_then(|e| e.to_i32()) {
+                Some(e) => Number::from_complex(z.powi(e)),
+                None => Number::from_complex_approx(z.powc(exponent.to_complex())),
+            });
+        }
+        let exact = match (self, exponent.to_integer()) {
+            (Number::Float(_), _) | (_, None) => None,
+            (_, Some(e)) => e.to_i32(),
+        };
+        match (self, exact) {
+            (_, Some(e)) if e < 0 && self.is_zero() => Err(ArithmeticError::DivisionByZero),
+            (Number::Integer(n, ty), Some(e)) => Ok(Number::Integer(integer_pow(n, e, ty)?, *ty)),
+            (_, Some(e)) => {
+                let base = self.to_rational().unwrap();
+                // 結果の分子・分母のビット数はおよそ底のビット数の|e|倍になる
+                let bits = base.numer().bits().max(base.denom().bits());
+                if bits > 1 && bits * u64::from(e.unsigned_abs()) > MAX_POW_BITS {
+                    return Err(ArithmeticError::Overflow);
+                }
+                let value = num_traits::Pow::pow(base, e);
+                Ok(Number::Rational(value).to_mode(self.exact_mode(mode)))
+            }
+            (_, None) => {
+                let (base, exponent) = (self.to_f64(), exponent.to_f64());
+                let value = base.powf(exponent);
+                // 有限の数のべき乗が発散すれば、0の負のべき乗か桁あふれ
+                if value.is_infinite() && base.is_finite() && exponent.is_finite() {
+                    return Err(if base == 0.0 {
+                        ArithmeticError::DivisionByZero
+                    } else {
+                        ArithmeticError::Overflow
+                    });
+                }
+                Ok(Number::from_f64(value, mode))
+            }
+        }
+    }
+
+    /// 値の表現に対応するモード（有理数・10進数の演算結果を戻すために使う）
+    fn exact_mode(&self, mode: NumberMode) -> NumberMode {
+        match self {
+            Number::Decimal(d) => NumberMode::Decimal(d.scale),
+            Number::Rational(_) => NumberMode::Rational,
+            Number::Integer(_, ty) => NumberMode::Integer(*ty),
+            Number::Float(_) | Number::Complex(_) => mode,
+        }
+    }
+
+    /// 有理数・10進数・整数のまま計算できる関数（abs、floor、ceil、round）
+    pub fn map_exact(
+        &self,
+        float_op: impl Fn(f64) -> f64,
+        op: impl Fn(&BigRational) -> BigRational,
+    ) -> Result<Self, ArithmeticError> {
+        match self {
+            Number::Float(x) => Ok(Number::Float(float_op(*x))),
+            Number::Rational(r) => Ok(Number::Rational(op(r))),
+            Number::Decimal(d) => Ok(Number::Decimal(Decimal::from_rational(
+                &op(&d.to_rational()),
+                d.scale,
+            ))),
+            Number::Integer(n, ty) => {
+                let value = op(&BigRational::from_integer(n.clone()));
+                Ok(Number::Integer(ty.fit(value.trunc().to_integer())?, *ty))
+            }
+            Number::Complex(_) => Err(ArithmeticError::NotReal),
+        }
+    }
+
+    /// 小数点以下digits桁に四捨五入する
+    pub fn round_to(&self, digits: i32) -> Result<Self, ArithmeticError> {
+        self.map_exact(
+            |x| {
+                let scale = 10f64.powi(digits);
+                let scaled = x * scale;
+                // f64の精度より細かい桁は丸めても変わらず、大きすぎる桁で丸めれば0
+                if !scaled.is_finite() {
+                    x
+                } else if scale == 0.0 {
+                    0.0
+                } else {
+                    scaled.round() / scale
+                }
+            },
+            |r| {
+                let scale = BigRational::from_integer(BigInt::from(10)).pow(digits);
+                (r * &scale).round() / &scale
+            },
+        )
+    }
+
+    /// 桁数や10の指数として使う整数。整数でなければNotInteger、絶対値が
+    /// MAX_LITERAL_EXPONENTを超えればOverflow
+    pub fn to_exponent(&self) -> Result<i32, ArithmeticError> {
+        let n = self.to_integer().ok_or(ArithmeticError::NotInteger)?;
+        n.to_i32()
+            .filter(|n| n.unsigned_abs() <= MAX_LITERAL_EXPONENT as u32)
+            .ok_or(ArithmeticError::Overflow)
+    }
+
+    /// 大小の比較。両方ともf64の場合はf64で比較する
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self.to_rational(), other.to_rational()) {
+            (Some(a), Some(b))
+                if !matches!((self, other), (Number::Float(_), Number::Float(_))) =>
+            {
+                Some(a.cmp(&b))
+            }
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    /// 整数同士のビット演算。負の数は無限に続く2の補数として扱う
+    fn bitwise(
+        &self,
+        other: &Self,
+        mode: NumberMode,
+        op: impl Fn(&BigInt, &BigInt) -> BigInt,
+    ) -> Result<Self, ArithmeticError> {
+        let (Some(a), Some(b)) = (self.to_integer(), other.to_integer()) else {
+            return Err(ArithmeticError::NotInteger);
+        };
+        Ok(self.with_integer_value(op(&a, &b), mode))
+    }
+
+    /// ビット演算の結果をselfと同じ表現にする
+    fn with_integer_value(&self, value: BigInt, mode: NumberMode) -> Self {
+        match self {
+            Number::Integer(_, ty) => Number::Integer(ty.wrap(value), *ty),
+            _ => Number::Rational(BigRational::from_integer(value)).to_mode(self.exact_mode(mode)),
+        }
+    }
+
+    pub fn bit_and(&self, other: &Self, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        self.bitwise(other, mode, |a, b| a & b)
+    }
+
+    pub fn bit_or(&self, other: &Self, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        self.bitwise(other, mode, |a, b| a | b)
+    }
+
+    pub fn bit_xor(&self, other: &Self, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        self.bitwise(other, mode, |a, b| a ^ b)
+    }
+
+    pub fn bit_not(&self, mode: NumberMode) -> Result<Self, ArithmeticError> {
+        let value = self.to_integer().ok_or(ArithmeticError::NotInteger)?;
+        Ok(self.with_integer_value(!value, mode))
+    }
+
+    /// 左シフト（leftがtrue）または右シフト（算術シフト）
+    ///
+    /// 整数型ではシフト量が型の幅以上であれば、checkedではエラー、wrappingでは幅で割った余りを使う。
+    pub fn shift(
+        &self,
+        amount: &Self,
+        left: bool,
+        mode: NumberMode,
+    ) -> Result<Self, ArithmeticError> {
+        let value = self.to_integer().ok_or(ArithmeticError::NotInteger)?;
+        let amount = amount.to_integer().ok_or(ArithmeticError::NotInteger)?;
+        let amount = match self {
+            Number::Integer(_, ty) if !ty.checked => amount.mod_floor(&BigInt::from(ty.bits)),
+            _ => amount,
+        };
+        let limit = match self {
+            Number::Integer(_, ty) => ty.bits,
+            _ => MAX_SHIFT,
+        };
+        let amount = amount
+            .to_u32()
+            .filter(|amount| *amount < limit)
+            .ok_or(ArithmeticError::Overflow)?;
+        let value = if left {
+            value << amount
+        } else {
+            value >> amount
+        };
+        Ok(self.with_integer_value(value, mode))
+    }
+
+    /// x * ratio + offset を元と同じ表現（f64、小数点以下の桁数、整数型）で計算する。単位の換算に使う
+    pub fn affine(
+        &self,
+        ratio: &BigRational,
+        offset: &BigRational,
+    ) -> Result<Self, ArithmeticError> {
+        let linear = |r: BigRational| r * ratio + offset;
+        match self {
+            Number::Float(x) => {
+                let float = |n: &BigInt| n.to_f64().unwrap_or(f64::NAN);
+                let offset = offset.to_f64().unwrap_or(f64::NAN);
+                Ok(Number::Float(
+                    x * float(ratio.numer()) / float(ratio.denom()) + offset,
+                ))
+            }
+            Number::Rational(r) => Ok(Number::Rational(linear(r.clone()))),
+            Number::Decimal(d) => Ok(Number::Decimal(Decimal::from_rational(
+                &linear(d.to_rational()),
+                d.scale,
+            ))),
+            Number::Integer(n, ty) => {
+                let value = linear(BigRational::from_integer(n.clone()));
+                Ok(Number::Integer(ty.fit(value.trunc().to_integer())?, *ty))
+            }
+            Number::Complex(z) => {
+                let float = |r: &BigRational| r.to_f64().unwrap_or(f64::NAN);
+                Ok(Number::Complex(z * float(ratio) + float(offset)))
+            }
+        }
+    }
+
+    /// 表現ごと保存するための文字列（float:0.5、rational:1/3、decimal:2:150、int:u8:wrapping:255）
+    pub fn encode(&self) -> String {
+        match self {
+            // Debugの表記は読み戻すと同じ値になる
+            Number::Float(x) => format!("float:{:?}", x),
+            Number::Rational(r) => format!("rational:{}/{}", r.numer(), r.denom()),
+            Number::Decimal(d) => format!("decimal:{}:{}", d.scale, d.mantissa),
+            Number::Integer(n, ty) => {
+                let sign = if ty.signed { 'i' } else { 'u' };
+                let overflow = if ty.checked { "checked" } else { "wrapping" };
+                format!("int:{}{}:{}:{}", sign, ty.bits, overflow, n)
+            }
+            Number::Complex(z) => format!("complex:{:?}:{:?}", z.re, z.im),
+        }
+    }
+
+    /// encodeした文字列を読む
+    pub fn decode(text: &str) -> Option<Self> {
+        let (kind, value) = text.split_once(':')?;
+        match kind {
+            "float" => value.parse().ok().map(Number::Float),
+            "rational" => {
+                let (numer, denom) = value.split_once('/')?;
+                let denom: BigInt = denom.parse().ok()?;
+                if denom.is_zero() {
+                    return None;
+                }
+                Some(Number::Rational(BigRational::new(
+                    numer.parse().ok()?,
+                    denom,
+                )))
+            }
+            "decimal" => {
+                let (scale, mantissa) = value.split_once(':')?;
+                Some(Number::Decimal(Decimal {
+                    mantissa: mantissa.parse().ok()?,
+                    scale: scale.parse().ok()?,
+                }))
+            }
+            "int" => {
+                let mut parts = value.splitn(3, ':');
+                let name = parts.next()?;
+                let checked = match parts.next()? {
+                    "checked" => true,
+                    "wrapping" => false,
+                    _ => return None,
+                };
+                let ty = IntType::parse(name, checked)?;
+                let n: BigInt = parts.next()?.parse().ok()?;
+                (n >= ty.min() && n <= ty.max()).then_some(Number::Integer(n, ty))
+            }
+            "complex" => {
+                let (re, im) = value.split_once(':')?;
+                Some(Number::Complex(Complex64::new(
+                    re.parse().ok()?,
+                    im.parse().ok()?,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// 基数radix（2、8、10、16）で表示する。整数型はビット列を、整数でない値は10進数で表示する
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        let prefix = match radix {
+            16 => "0x",
+            2 => "0b",
+            8 => "0o",
+            _ => return self.to_string(),
+        };
+        match self {
+            Number::Integer(n, ty) => {
+                format!("{}{}", prefix, ty.bit_pattern(n).to_str_radix(radix))
+            }
+            _ => match self.to_integer() {
+                Some(n) => {
+                    let sign = if n.is_negative() { "-" } else { "" };
+                    format!("{}{}{}", sign, prefix, n.abs().to_str_radix(radix))
+                }
+                None => self.to_string(),
+            },
+        }
+    }
+
+    /// 複素数の極形式（5∠0.9272952180016122）。degreesがtrueであれば偏角を度で表示する
+    ///
+    /// 複素数でなければ通常の表示になる。
+    pub fn to_polar_string(&self, degrees: bool) -> String {
+        let Number::Complex(z) = self else {
+            return self.to_string();
+        };
+        let (r, theta) = z.to_polar();
+        if degrees {
+            format!("{}∠{}°", r, theta.to_degrees())
+        } else {
+            format!("{}∠{}", r, theta)
+        }
+    }
+}
+
+/// 整数型のべき乗。負の指数は0の方向に切り捨てた逆数になる
+fn integer_pow(base: &BigInt, exponent: i32, ty: &IntType) -> Result<BigInt, ArithmeticError> {
+    if exponent < 0 {
+        // |base| >= 2 の逆数は0
+        let value = match base.to_i8() {
+            Some(1) => BigInt::one(),
+            Some(-1) if exponent % 2 == 0 => BigInt::one(),
+            Some(-1) => -BigInt::one(),
+            _ => BigInt::zero(),
+        };
+        return ty.fit(value);
+    }
+    if base.abs() <= BigInt::one() {
+        return ty.fit(base.pow(exponent as u32));
+    }
+    if ty.checked {
+        // 結果が型に収まるかを1回ずつ確かめる（|base| >= 2 なので高々128回で止まる）
+        let mut value = BigInt::one();
+        for _ in 0..exponent {
+            value = ty.fit(value * base)?;
+        }
+        Ok(value)
+    } else {
+        Ok(ty.wrap(base.modpow(&BigInt::from(exponent), &ty.modulus())))
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Float(x) => write!(f, "{}", x),
+            Number::Rational(r) if r.is_integer() => write!(f, "{}", r.numer()),
+            Number::Rational(r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Number::Decimal(d) => {
+                let digits = d.mantissa.abs().to_string();
+                let scale = d.scale as usize;
+                let digits = format!("{:0>width$}", digits, width = scale + 1);
+                let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+                let sign = if d.mantissa.is_negative() { "-" } else { "" };
+                if scale == 0 {
+                    write!(f, "{}{}", sign, int_part)
+                } else {
+                    write!(f, "{}{}.{}", sign, int_part, frac_part)
+                }
+            }
+            Number::Integer(n, _) => write!(f, "{}", n),
+            // 入力として読み直せる形式（3 + 4i、-2i）
+            Number::Complex(z) if z.re == 0.0 => write!(f, "{}i", z.im),
+            Number::Complex(z) if z.im < 0.0 => write!(f, "{} - {}i", z.re, -z.im),
+            Number::Complex(z) => write!(f, "{} + {}i", z.re, z.im),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArithmeticError, IntType, Number, NumberMode};
+    use crate::error::CalcError;
+    use crate::Context;
+
+    fn eval_sum(a: &str, b: &str, mode: NumberMode) -> String {
+        let a = Number::parse(a, mode).unwrap();
+        let b = Number::parse(b, mode).unwrap();
+        a.add(&b).unwrap().to_string()
+    }
+
+    fn int_mode(name: &str, checked: bool) -> NumberMode {
+        NumberMode::Integer(IntType::parse(name, checked).unwrap())
+    }
+
+    /// modeで式を評価して表示用の文字列にする
+    fn eval_in_mode(line: &str, mode: NumberMode) -> Result<String, CalcError> {
+        let mut ctx = Context::new();
+        ctx.number_mode = mode;
+        ctx.eval(line).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_modes() {
+        assert_eq!(
+            "0.30000000000000004",
+            eval_sum("0.1", "0.2", NumberMode::Float)
+        );
+        assert_eq!("3/10", eval_sum("0.1", "0.2", NumberMode::Rational));
+        assert_eq!("0.30", eval_sum("0.1", "0.2", NumberMode::Decimal(2)));
+        assert_eq!(
+            "1500001/1000",
+            eval_sum("1.5e3", "1e-3", NumberMode::Rational)
+        );
+        // 指数が大きすぎるリテラルは読まない
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("1.5e-2147483648", NumberMode::Rational)
+        );
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("1e10001", NumberMode::Decimal(2))
+        );
+    }
+
+    #[test]
+    fn test_exact_modes() {
+        let rational = NumberMode::Rational;
+        let decimal = NumberMode::Decimal(2);
+        assert_eq!(Ok("3/10".to_string()), eval_in_mode("0.1 + 0.2", rational));
+        assert_eq!(Ok("0.30".to_string()), eval_in_mode("0.1 + 0.2", decimal));
+        assert_eq!(Ok("1/3".to_string()), eval_in_mode("1 / 3", rational));
+        assert_eq!(Ok("0.33".to_string()), eval_in_mode("1 / 3", decimal));
+        assert_eq!(Ok("1/8".to_string()), eval_in_mode("(1/2) ^ 3", rational));
+        assert_eq!(
+            Ok("-5/3".to_string()),
+            eval_in_mode("-5/3 % 4 - 4", rational)
+        );
+        assert_eq!(
+            Ok("1/3".to_string()),
+            eval_in_mode("min(1/2, 1/3)", rational)
+        );
+        assert_eq!(
+            Ok("19.99".to_string()),
+            eval_in_mode("round(19.985, 2)", decimal)
+        );
+        assert_eq!(
+            Err(CalcError::DivisionByZero(1..2)),
+            eval_in_mode("0^-1", rational)
+        );
+    }
+
+    #[test]
+    fn test_approximate_in_exact_modes() {
+        // 誤差を含む関数の結果は10進数では丸め、有理数ではf64のまま
+        assert_eq!(
+            Ok("3.14".to_string()),
+            eval_in_mode("pi", NumberMode::Decimal(2))
+        );
+        assert_eq!(
+            Ok("1.5".to_string()),
+            eval_in_mode("sqrt(2.25)", NumberMode::Rational)
+        );
+    }
+
+    #[test]
+    fn test_integer_mode() {
+        let u8_mode = int_mode("u8", false);
+        let eval = |line| eval_in_mode(line, u8_mode).unwrap();
+        assert_eq!("255", eval("0xF0 | 0x0F"));
+        assert_eq!("245", eval("~0b1010"));
+        // 整数モードの ^ は排他的論理和、べき乗は **
+        assert_eq!("5", eval("6 ^ 3"));
+        assert_eq!("8", eval("2 ** 3"));
+        // 優先順位は | ^ & シフト 加減算 の順に高くなる
+        assert_eq!("5", eval("1 | 2 ^ 6 & 3 << 1"));
+        assert_eq!("12", eval("1 + 2 << 2"));
+        assert_eq!("0", eval("255 + 1"));
+        assert_eq!("3", eval("7 / 2"));
+        assert_eq!("3", eval("sqrt(10)"));
+    }
+
+    #[test]
+    fn test_checked_integer_mode() {
+        let i32_checked = int_mode("i32", true);
+        assert_eq!(
+            Err(CalcError::Overflow(11..12)),
+            eval_in_mode("2147483647 + 1", i32_checked)
+        );
+        assert_eq!(
+            Err(CalcError::NotInteger(0..3)),
+            eval_in_mode("1.5", i32_checked)
+        );
+    }
+
+    #[test]
+    fn test_bitwise_outside_integer_mode() {
+        let float = NumberMode::Float;
+        assert_eq!(Ok("12".to_string()), eval_in_mode("3 << 2", float));
+        assert_eq!(Ok("36".to_string()), eval_in_mode("6 ^ 2", float));
+        assert_eq!(
+            Err(CalcError::NotInteger(4..5)),
+            eval_in_mode("0.5 & 1", float)
+        );
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(Some(NumberMode::Rational), NumberMode::parse(" rational"));
+        assert_eq!(Some(int_mode("u8", false)), NumberMode::parse("u8"));
+        assert_eq!(
+            Some(int_mode("i32", true)),
+            NumberMode::parse("i32 checked")
+        );
+        assert_eq!(None, NumberMode::parse(" money"));
+        assert_eq!(None, NumberMode::parse("i7"));
+        assert_eq!(Some(NumberMode::Decimal(2)), NumberMode::parse(" decimal"));
+        assert_eq!(
+            Some(NumberMode::Decimal(6)),
+            NumberMode::parse(" decimal 6")
+        );
+        assert_eq!(
+            Some(NumberMode::Decimal(1000)),
+            NumberMode::parse("decimal 1000")
+        );
+        // 桁数が大きすぎると10のべき乗の計算が終わらない
+        assert_eq!(None, NumberMode::parse("decimal 1001"));
+        assert_eq!(None, NumberMode::parse("decimal 1000000000"));
+    }
+
+    #[test]
+    fn test_decimal_rounding() {
+        let mode = NumberMode::Decimal(2);
+        let one = Number::parse("1", mode).unwrap();
+        let three = Number::parse("3", mode).unwrap();
+        let two = Number::parse("2", mode).unwrap();
+        assert_eq!("0.33", one.div(&three).unwrap().to_string());
+        assert_eq!("0.67", two.div(&three).unwrap().to_string());
+        assert_eq!("-0.67", two.neg().unwrap().div(&three).unwrap().to_string());
+        assert_eq!("0.01", Number::parse("0.005", mode).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_round_to() {
+        let round = |x: &str, digits: &str, mode| {
+            let digits = Number::parse(digits, mode).unwrap().to_exponent()?;
+            Number::parse(x, mode).unwrap().round_to(digits)
+        };
+        let float = NumberMode::Float;
+        assert_eq!(Ok(Number::Float(2.72)), round("2.71828", "2", float));
+        assert_eq!(Ok(Number::Float(1200.0)), round("1234", "-2", float));
+        // f64では大きな桁数でも10のべき乗を有理数で作らない
+        assert_eq!(Ok(Number::Float(2.5)), round("2.5", "10000", float));
+        assert_eq!(Ok(Number::Float(0.0)), round("2.5", "-10000", float));
+        assert_eq!(
+            "19.990",
+            round("19.985", "2", NumberMode::Decimal(3))
+                .unwrap()
+                .to_string()
+        );
+        // 桁数は上限以下の整数だけ
+        assert_eq!(Err(ArithmeticError::Overflow), round("2", "1e9", float));
+        assert_eq!(Err(ArithmeticError::NotInteger), round("2", "1.5", float));
+    }
+
+    #[test]
+    fn test_exact_pow() {
+        let mode = NumberMode::Rational;
+        let two = Number::parse("2", mode).unwrap();
+        let minus_ten = Number::parse("-10", mode).unwrap();
+        assert_eq!("1/1024", two.pow(&minus_ten, mode).unwrap().to_string());
+        let half = Number::parse("0.5", mode).unwrap();
+        assert!(matches!(two.pow(&half, mode), Ok(Number::Float(_))));
+        // 結果が大きすぎるべき乗は計算しない
+        let three = Number::parse("3", mode).unwrap();
+        let huge = Number::parse("2000000000", mode).unwrap();
+        assert_eq!(Err(ArithmeticError::Overflow), three.pow(&huge, mode));
+        let one = Number::parse("1", mode).unwrap();
+        assert_eq!("1", one.pow(&huge, mode).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_float_overflow() {
+        let float = NumberMode::Float;
+        let number = |text| Number::parse(text, float).unwrap();
+        // f64で表せない結果は inf にせずエラー
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            number("2").pow(&number("1e9"), float)
+        );
+        assert_eq!(
+            Err(ArithmeticError::DivisionByZero),
+            number("0").pow(&number("-1"), float)
+        );
+        assert_eq!(
+            Ok(Number::Float(0.0)),
+            number("2").pow(&number("-1e9"), float)
+        );
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("1e400", float)
+        );
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("1e400i", float)
+        );
+        assert_eq!(Ok(Number::Float(0.0)), Number::parse("1e-400", float));
+    }
+
+    #[test]
+    fn test_mode_conversion() {
+        let value = Number::Float(0.1);
+        assert_eq!("1/10", value.to_mode(NumberMode::Rational).to_string());
+        assert_eq!("0.1000", value.to_mode(NumberMode::Decimal(4)).to_string());
+        let value = Number::Float(-2.7);
+        assert_eq!("-2", value.to_mode(int_mode("i32", false)).to_string());
+        assert_eq!(
+            "44",
+            Number::Float(300.0)
+                .to_mode(int_mode("u8", true))
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let wrapping = int_mode("i8", false);
+        let checked = int_mode("i8", true);
+        let add = |a: &str, b: &str, mode| Number::parse(a, mode)?.add(&Number::parse(b, mode)?);
+        assert_eq!("-128", add("127", "1", wrapping).unwrap().to_string());
+        assert_eq!(Err(ArithmeticError::Overflow), add("127", "1", checked));
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("200", checked)
+        );
+        // 基数つきのリテラルはビット列として読む
+        assert_eq!("-1", Number::parse("0xff", checked).unwrap().to_string());
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            Number::parse("0x100", checked)
+        );
+        assert_eq!(
+            Err(ArithmeticError::NotInteger),
+            Number::parse("1.5", checked)
+        );
+
+        let two = Number::parse("2", checked).unwrap();
+        let seven = Number::parse("7", checked).unwrap();
+        assert_eq!(Err(ArithmeticError::Overflow), two.pow(&seven, checked));
+        let wrapping_two = Number::parse("2", wrapping).unwrap();
+        assert_eq!(
+            "-128",
+            wrapping_two.pow(&seven, wrapping).unwrap().to_string()
+        );
+        let minus_seven = seven.neg().unwrap();
+        assert_eq!("-3", minus_seven.div(&two).unwrap().to_string());
+        assert_eq!("-4", minus_seven.floor_div(&two).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let mode = int_mode("u8", false);
+        let a = Number::parse("0b1100", mode).unwrap();
+        let b = Number::parse("0b1010", mode).unwrap();
+        let one = Number::parse("1", mode).unwrap();
+        let nine = Number::parse("9", mode).unwrap();
+        assert_eq!("8", a.bit_and(&b, mode).unwrap().to_string());
+        assert_eq!("14", a.bit_or(&b, mode).unwrap().to_string());
+        assert_eq!("6", a.bit_xor(&b, mode).unwrap().to_string());
+        assert_eq!("243", a.bit_not(mode).unwrap().to_string());
+        assert_eq!(
+            "128",
+            one.shift(&Number::parse("7", mode).unwrap(), true, mode)
+                .unwrap()
+                .to_string()
+        );
+        // wrappingでは幅で割った余りだけシフトする
+        assert_eq!("2", one.shift(&nine, true, mode).unwrap().to_string());
+        let checked = int_mode("u8", true);
+        let one = Number::parse("1", checked).unwrap();
+        assert_eq!(
+            Err(ArithmeticError::Overflow),
+            one.shift(&Number::parse("8", checked).unwrap(), true, checked)
+        );
+
+        // 整数モード以外でも整数の値であればビット演算できる
+        let mode = NumberMode::Float;
+        let six = Number::parse("6", mode).unwrap();
+        assert_eq!("-7", six.bit_not(mode).unwrap().to_string());
+        assert_eq!(
+            Err(ArithmeticError::NotInteger),
+            Number::parse("0.5", mode).unwrap().bit_and(&six, mode)
+        );
+    }
+
+    #[test]
+    fn test_radix_display() {
+        let mode = int_mode("i16", false);
+        let value = Number::parse("-1", mode).unwrap();
+        assert_eq!("0xffff", value.to_string_radix(16));
+        assert_eq!("-1", value.to_string_radix(10));
+        let value = Number::parse("0b1111_0000", mode).unwrap();
+        assert_eq!("0b11110000", value.to_string_radix(2));
+        assert_eq!("0o360", value.to_string_radix(8));
+        assert_eq!("-0xa", Number::Float(-10.0).to_string_radix(16));
+        assert_eq!("0.5", Number::Float(0.5).to_string_radix(16));
+    }
+
+    #[test]
+    fn test_encode() {
+        let rational = |text| Number::parse(text, NumberMode::Rational).unwrap();
+        let values = [
+            Number::Float(0.1),
+            Number::Float(-1e300),
+            Number::Float(f64::INFINITY),
+            rational("1").div(&rational("-3")).unwrap(),
+            Number::parse("-12.345", NumberMode::Decimal(2)).unwrap(),
+            Number::parse("0xff", int_mode("i8", false)).unwrap(),
+            Number::parse("200", int_mode("u8", true)).unwrap(),
+        ];
+        for value in &values {
+            assert_eq!(Some(value.clone()), Number::decode(&value.encode()));
+        }
+        assert_eq!("rational:-1/3", values[3].encode());
+        assert_eq!("decimal:2:-1235", values[4].encode());
+        assert_eq!("int:i8:wrapping:-1", values[5].encode());
+        assert_eq!(None, Number::decode("rational:1/0"));
+        assert_eq!(None, Number::decode("int:u8:wrapping:256"));
+        assert_eq!(None, Number::decode("complex:1"));
+    }
+
+    #[test]
+    fn test_complex() {
+        let mode = NumberMode::Float;
+        let z = Number::parse("3", mode)
+            .unwrap()
+            .add(&Number::parse("4i", mode).unwrap())
+            .unwrap();
+        assert_eq!("3 + 4i", z.to_string());
+        assert_eq!(
+            "3 - 4i",
+            z.sub(&Number::parse("8i", mode).unwrap())
+                .unwrap()
+                .to_string()
+        );
+        // 虚部が打ち消し合えば実数になる
+        let conj = Number::parse("-4i", mode)
+            .unwrap()
+            .add(&Number::Float(3.0))
+            .unwrap();
+        assert_eq!(Number::Float(25.0), z.mul(&conj).unwrap());
+        let minus_one = Number::Float(-1.0);
+        let root = minus_one.pow(&Number::Float(0.5), mode).unwrap();
+        assert_eq!("1i", root.to_string());
+        assert_eq!(
+            "5∠90°",
+            Number::parse("5i", mode).unwrap().to_polar_string(true)
+        );
+        assert_eq!(Err(ArithmeticError::NotReal), z.rem(&Number::Float(2.0)));
+        assert_eq!(Some(z.clone()), Number::decode(&z.encode()));
+        // 有理数モードでも虚数のリテラルはf64の複素数になる
+        let rational = NumberMode::Rational;
+        let half = Number::parse("1", rational)
+            .unwrap()
+            .div(&Number::parse("2", rational).unwrap())
+            .unwrap();
+        assert_eq!(
+            "0.5 + 2i",
+            half.add(&Number::parse("2i", rational).unwrap())
+                .unwrap()
+                .to_string()
+        );
+    }
+}
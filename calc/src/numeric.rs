@@ -0,0 +1,359 @@
+use crate::ast::{BinaryOp, Expr, ExprKind};
+use crate::error::CalcError;
+use crate::eval::{eval, Scope};
+use crate::lexer::Span;
+use crate::number::Number;
+use crate::symbolic;
+use crate::unit::Unit;
+use crate::value::{Quantity, Value};
+
+/// 式を引数にとる数値計算の組み込み関数の名前の一覧（solve は行列の関数と共有する）
+pub const NUMERIC_FUNCTION_NAMES: &[&str] = &["integrate"];
+
+/// ニュートン法、二分法の反復の上限
+const MAX_ITERATIONS: usize = 200;
+
+/// 解の相対的な精度
+const TOLERANCE: f64 = 1e-12;
+
+/// 二分法で符号の変わる区間を探すとき、guessから広げる回数（幅は毎回2倍にする）
+const MAX_EXPANSIONS: usize = 64;
+
+/// 適応型シンプソン法の許容誤差（積分の大きさに対する比）と分割の深さの上限
+const INTEGRATION_TOLERANCE: f64 = 1e-10;
+const INTEGRATION_DEPTH: usize = 20;
+
+/// 変数を実数tにしたときの式の値
+type Function<'a> = dyn Fn(f64) -> Result<f64, CalcError> + 'a;
+
+/// solve(f(x) = g(x), x, guess)。方程式の代わりに式fを書けば f(x) = 0 を解く
+///
+/// guessから始めるニュートン法で解を求め、収束しなければguessの周りで符号の変わる区間を探して
+/// 二分法で求める。導関数は記号微分で求め、微分できない式では差分で近似する。
+/// 解はguessと同じ単位になる。
+pub fn solve(args: &[Expr], scope: &Scope, span: &Span) -> Result<Value, CalcError> {
+    let [equation, var, guess] = args else {
+        return Err(CalcError::ArgumentCount(
+            "solve".to_string(),
+            "3".to_string(),
+            span.clone(),
+        ));
+    };
+    let name = variable_name(var)?;
+    let f = match &equation.kind {
+        ExprKind::Equation(lhs, rhs) => Expr::new(
+            ExprKind::Binary(BinaryOp::Sub, lhs.clone(), rhs.clone()),
+            equation.span.clone(),
+        ),
+        _ => equation.clone(),
+    };
+    let guess = real(eval(guess, scope)?, &guess.span)?;
+    let unit = &guess.unit;
+    let function = |expr: &Expr, t: f64| {
+        let value = eval(expr, &bind(scope, name, t, unit))?;
+        real(value, &expr.span).map(|q| q.number.to_f64())
+    };
+    let value = |t: f64| function(&f, t);
+    let slope: Box<Function> = match symbolic::derivative(&f, name, scope.ctx()) {
+        Ok(derivative) => Box::new(move |t| function(&derivative, t)),
+        Err(_) => Box::new(|t: f64| {
+            let h = 1e-6 * t.abs().max(1.0);
+            Ok((value(t + h)? - value(t - h)?) / (2.0 * h))
+        }),
+    };
+
+    // guessで評価できない式（未定義の名前など）はそのエラーにする
+    let start = guess.number.to_f64();
+    let at_start = value(start)?;
+    let root = newton(&value, slope.as_ref(), start)
+        .or_else(|| bisection(&value, start, at_start))
+        .ok_or_else(|| CalcError::NoConvergence(span.clone()))?;
+    Ok(Value::Scalar(Quantity {
+        number: Number::from_f64(root, scope.ctx().number_mode),
+        unit: unit.clone(),
+    }))
+}
+
+/// integrate(f, x, a, b)。適応型シンプソン法で求めるaからbまでの定積分
+///
+/// bはaの単位に換算し、結果の単位はfの単位とaの単位の積になる。
+pub fn integrate(args: &[Expr], scope: &Scope, span: &Span) -> Result<Value, CalcError> {
+    let [f, var, a, b] = args else {
+        return Err(CalcError::ArgumentCount(
+            "integrate".to_string(),
+            "4".to_string(),
+            span.clone(),
+        ));
+    };
+    let name = variable_name(var)?;
+    let a = real(eval(a, scope)?, &a.span)?;
+    let b = real(eval(b, scope)?, &b.span)?
+        .convert(&a.unit)
+        .map_err(|e| CalcError::value(e, span))?;
+    let (lower, upper) = (a.number.to_f64(), b.number.to_f64());
+
+    // 被積分関数の単位は下端での値の単位とし、他の点の値もその単位に換算する
+    let at_lower = real(eval(f, &bind(scope, name, lower, &a.unit))?, &f.span)?;
+    let value = |t: f64| {
+        let value = real(eval(f, &bind(scope, name, t, &a.unit))?, &f.span)?;
+        let value = value
+            .convert(&at_lower.unit)
+            .map_err(|e| CalcError::value(e, &f.span))?;
+        Ok(value.number.to_f64())
+    };
+    let (fa, fb) = (at_lower.number.to_f64(), value(upper)?);
+    let middle = (lower + upper) / 2.0;
+    let fm = value(middle)?;
+    let whole = (upper - lower) / 6.0 * (fa + 4.0 * fm + fb);
+    let tolerance = INTEGRATION_TOLERANCE * whole.abs().max(1.0);
+    let segment = Segment {
+        a: lower,
+        b: upper,
+        fa,
+        fm,
+        fb,
+        whole,
+    };
+    let integral = simpson(&value, &segment, tolerance, INTEGRATION_DEPTH)?;
+    if !integral.is_finite() {
+        return Err(CalcError::Domain("integrate".to_string(), span.clone()));
+    }
+
+    let mode = scope.ctx().number_mode;
+    let width = Quantity {
+        number: Number::parse("1", mode).map_err(|e| CalcError::arithmetic(e, span))?,
+        unit: a.unit,
+    };
+    Quantity {
+        number: Number::from_f64(integral, mode),
+        unit: at_lower.unit,
+    }
+    .mul(&width)
+    .map(Value::Scalar)
+    .map_err(|e| CalcError::value(e, span))
+}
+
+/// 束縛する変数の名前
+fn variable_name(var: &Expr) -> Result<&str, CalcError> {
+    match &var.kind {
+        ExprKind::Variable(name) => Ok(name),
+        _ => Err(CalcError::NotVariable(var.span.clone())),
+    }
+}
+
+/// 変数nameを単位unitの実数tにしたスコープ
+pub fn bind<'a>(scope: &Scope<'a>, name: &str, t: f64, unit: &Unit) -> Scope<'a> {
+    let value = Quantity {
+        number: Number::Float(t),
+        unit: unit.clone(),
+    };
+    scope.with_local(name, Value::Scalar(value))
+}
+
+/// 実数の値であること
+pub fn real(value: Value, span: &Span) -> Result<Quantity, CalcError> {
+    let quantity = value
+        .scalar()
+        .cloned()
+        .map_err(|e| CalcError::value(e, span))?;
+    match quantity.number {
+        Number::Complex(_) => Err(CalcError::NotReal(span.clone())),
+        _ => Ok(quantity),
+    }
+}
+
+/// startから始めるニュートン法。収束しなければNone
+fn newton(value: &Function, slope: &Function, start: f64) -> Option<f64> {
+    let mut x = start;
+    for _ in 0..MAX_ITERATIONS {
+        let y = value(x).ok()?;
+        if y == 0.0 {
+            return Some(x);
+        }
+        let step = y / slope(x).ok()?;
+        if !step.is_finite() {
+            return None;
+        }
+        x -= step;
+        if step.abs() <= TOLERANCE * x.abs().max(1.0) {
+            return Some(x);
+        }
+    }
+    None
+}
+
+/// startの両側へ幅を広げながら符号の変わる区間を探し、二分法で解を求める
+///
+/// 区間が極（1/x の x = 0）に縮んだ場合は解ではないためNoneにする。
+fn bisection(value: &Function, start: f64, at_start: f64) -> Option<f64> {
+    let mut width = 1e-3 * start.abs().max(1.0);
+    let (mut left, mut right) = ((start, at_start), (start, at_start));
+    for _ in 0..MAX_EXPANSIONS {
+        for (side, direction) in [(&mut left, -1.0), (&mut right, 1.0)] {
+            let x = start + direction * width;
+            // 定義域外の点と、アンダーフローかもしれない0（exp(-1000)）は飛ばす
+            let y = match value(x) {
+                Ok(y) if y != 0.0 && !y.is_nan() => y,
+                _ => continue,
+            };
+            if (y < 0.0) != (side.1 < 0.0) {
+                let bound = side.1.abs().max(y.abs());
+                let root = bisect(value, *side, (x, y))?;
+                return (value(root).ok()?.abs() <= bound).then_some(root);
+            }
+            *side = (x, y);
+        }
+        width *= 2.0;
+    }
+    None
+}
+
+/// 符号の異なる2点aとbの間の解
+fn bisect(value: &Function, a: (f64, f64), b: (f64, f64)) -> Option<f64> {
+    let (mut a, mut b) = (a, b);
+    for _ in 0..MAX_ITERATIONS {
+        let middle = a.0 + (b.0 - a.0) / 2.0;
+        if middle == a.0 || middle == b.0 {
+            return Some(middle);
+        }
+        let y = value(middle).ok()?;
+        if y == 0.0 {
+            return Some(middle);
+        }
+        if (y < 0.0) == (a.1 < 0.0) {
+            a = (middle, y);
+        } else {
+            b = (middle, y);
+        }
+    }
+    Some(a.0 + (b.0 - a.0) / 2.0)
+}
+
+/// 適応型シンプソン法の区間。両端と中点での値、区間全体のシンプソン則の値を持つ
+struct Segment {
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    whole: f64,
+}
+
+/// 区間を二分したシンプソン則の値が区間全体の値に十分近くなるまで分割する
+///
+/// 分割の深さが上限に達した区間はその時点の値を使う。
+fn simpson(
+    value: &Function,
+    segment: &Segment,
+    tolerance: f64,
+    depth: usize,
+) -> Result<f64, CalcError> {
+    let Segment {
+        a,
+        b,
+        fa,
+        fm,
+        fb,
+        whole,
+    } = *segment;
+    let m = (a + b) / 2.0;
+    let (left_middle, right_middle) = ((a + m) / 2.0, (m + b) / 2.0);
+    let (flm, frm) = (value(left_middle)?, value(right_middle)?);
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    let delta = left + right - whole;
+    if depth == 0 || delta.abs() <= 15.0 * tolerance {
+        return Ok(left + right + delta / 15.0);
+    }
+    let left = Segment {
+        a,
+        b: m,
+        fa,
+        fm: flm,
+        fb: fm,
+        whole: left,
+    };
+    let right = Segment {
+        a: m,
+        b,
+        fa: fm,
+        fm: frm,
+        fb,
+        whole: right,
+    };
+    Ok(simpson(value, &left, tolerance / 2.0, depth - 1)?
+        + simpson(value, &right, tolerance / 2.0, depth - 1)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CalcError;
+    use crate::Context;
+
+    fn eval(line: &str) -> Result<String, CalcError> {
+        Context::new().eval(line).map(|value| value.to_string())
+    }
+
+    fn approx(line: &str) -> f64 {
+        let ctx = Context::new();
+        ctx.eval(line).unwrap().scalar().unwrap().number.to_f64()
+    }
+
+    #[test]
+    fn test_solve() {
+        assert!((approx("solve(x^2 = 2, x, 1)") - 2f64.sqrt()).abs() < 1e-12);
+        assert!((approx("solve(x^2 = 2, x, -1)") + 2f64.sqrt()).abs() < 1e-12);
+        assert!((approx("solve(cos(x) = x, x, 0)") - 0.7390851332151607).abs() < 1e-12);
+        assert!((approx("solve(x^3 - 2*x - 5, x, 2)") - 2.0945514815423265).abs() < 1e-12);
+        // 微分できない式は差分で、ニュートン法が発散する式は二分法で解く
+        assert!((approx("solve(abs(x - 3) * (x - 3) = 1, x, 0)") - 4.0).abs() < 1e-9);
+        assert!(approx("solve(atan(x), x, 2)").abs() < 1e-9);
+        assert_eq!(Ok("2 m".to_string()), eval("solve(x^2 = 4 m^2, x, 1 m)"));
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        let span = 0..5;
+        assert_eq!(
+            Err(CalcError::NoConvergence(span.clone())),
+            eval("solve(x^2 = -1, x, 1)")
+        );
+        assert_eq!(
+            Err(CalcError::NoConvergence(span.clone())),
+            eval("solve(1 / x = 0, x, 1)")
+        );
+        assert_eq!(
+            Err(CalcError::NoConvergence(span.clone())),
+            eval("solve(exp(x), x, 0)")
+        );
+        assert_eq!(
+            Err(CalcError::NotVariable(13..14)),
+            eval("solve(x = 1, 2, 0)")
+        );
+        assert_eq!(
+            Err(CalcError::ArgumentCount(
+                "solve".to_string(),
+                "3".to_string(),
+                span
+            )),
+            eval("solve(x = 1, x)")
+        );
+        assert_eq!(Err(CalcError::MisplacedEquation(7..8)), eval("sqrt(x = 1)"));
+    }
+
+    #[test]
+    fn test_integrate() {
+        assert!((approx("integrate(x^2, x, 0, 3)") - 9.0).abs() < 1e-9);
+        assert!((approx("integrate(sin(x), x, 0, pi)") - 2.0).abs() < 1e-9);
+        assert!(
+            (approx("integrate(exp(-x^2), x, -5, 5)") - std::f64::consts::PI.sqrt()).abs() < 1e-9
+        );
+        assert!((approx("integrate(sqrt(x), x, 0, 1)") - 2.0 / 3.0).abs() < 1e-6);
+        assert!((approx("integrate(x, x, 2, 0)") + 2.0).abs() < 1e-12);
+        assert_eq!(Ok("6 m^2".to_string()), eval("integrate(2 m, x, 0 m, 3 m)"));
+        assert_eq!(
+            Err(CalcError::NotVariable(13..14)),
+            eval("integrate(x, 2, 0, 1)")
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, UnaryOp};
+use crate::error::CalcError;
+use crate::eval::LAST_RESULT;
+use crate::functions;
+use crate::lexer::{SpannedToken, Token};
+use crate::number::NumberMode;
+
+/// 単位の変換の演算子（x to km、x in km）。変数や関数の名前には使えない
+const CONVERSION_KEYWORDS: &[&str] = &["to", "in"];
+
+/// 代入文の左辺
+#[derive(Debug, PartialEq)]
+pub enum Definition {
+    Variable(String),              // rate = 0.08
+    Function(String, Vec<String>), // tax(x) = x * 1.1
+}
+
+/// 組み込みの定数・関数、ans、単位の変換の演算子など、定義に使えない名前か
+pub fn is_reserved(name: &str) -> bool {
+    functions::constant(name).is_some()
+        || functions::is_builtin(name)
+        || name == LAST_RESULT
+        || CONVERSION_KEYWORDS.contains(&name)
+}
+
+/// 行が代入文であれば、左辺と右辺の開始位置を返す
+pub fn parse_definition(tokens: &[SpannedToken]) -> Result<Option<(Definition, usize)>, CalcError> {
+    // 括弧の中の = は solve(x^2 = 2, x, 1) のような方程式なので、外側の = だけを見る
+    let mut depth = 0usize;
+    let Some(equals) = tokens.iter().position(|t| {
+        match t.token {
+            Token::LParen | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depth == 0 && t.token == Token::Equals
+    }) else {
+        return Ok(None);
+    };
+    let lhs = &tokens[..equals];
+    let invalid = || {
+        let start = lhs.first().map_or(0, |t| t.span.start);
+        CalcError::InvalidAssignment(start..tokens[equals].span.end)
+    };
+    let Some((
+        SpannedToken {
+            token: Token::Ident(name),
+            span,
+        },
+        rest,
+    )) = lhs.split_first()
+    else {
+        return Err(invalid());
+    };
+    if is_reserved(name) {
+        return Err(CalcError::ReservedName(name.clone(), span.clone()));
+    }
+    if rest.is_empty() {
+        return Ok(Some((Definition::Variable(name.clone()), equals + 1)));
+    }
+
+    // ( 仮引数, ... ) の形であること
+    let [SpannedToken {
+        token: Token::LParen,
+        ..
+    }, params @ .., SpannedToken {
+        token: Token::RParen,
+        ..
+    }] = rest
+    else {
+        return Err(invalid());
+    };
+    let mut names: Vec<String> = Vec::new();
+    for (i, param) in params.iter().enumerate() {
+        match (&param.token, i % 2) {
+            (Token::Ident(param_name), 0) => {
+                if names.contains(param_name) {
+                    return Err(CalcError::DuplicateParameter(
+                        param_name.clone(),
+                        param.span.clone(),
+                    ));
+                }
+                names.push(param_name.clone());
+            }
+            (Token::Comma, 1) if i + 1 < params.len() => (),
+            _ => return Err(CalcError::UnexpectedToken(param.span.clone())),
+        }
+    }
+    Ok(Some((
+        Definition::Function(name.clone(), names),
+        equals + 1,
+    )))
+}
+
+/// トークン列全体を式としてパースする。式の後にトークンが残っていればエラー
+///
+/// 整数モードでは ^ を排他的論理和として読む。
+pub fn parse(tokens: &[SpannedToken], mode: NumberMode) -> Result<Expr, CalcError> {
+    if tokens.is_empty() {
+        return Err(CalcError::UnexpectedEnd(0..1));
+    }
+    let (expr, index) = parse_conversion_expression(tokens, 0, mode)?;
+    match tokens.get(index) {
+        None => Ok(expr),
+        Some(SpannedToken {
+            token: Token::RParen | Token::RBracket,
+            span,
+        }) => Err(CalcError::UnbalancedParen(span.clone())),
+        Some(t) => Err(CalcError::TrailingInput(t.span.clone())),
+    }
+}
+
+/// カンマで区切った式の並び（plot sin(x), cos(x) ... の関数）をパースする
+pub fn parse_sequence(tokens: &[SpannedToken], mode: NumberMode) -> Result<Vec<Expr>, CalcError> {
+    if tokens.is_empty() {
+        return Err(CalcError::UnexpectedEnd(0..1));
+    }
+    let mut exprs = Vec::new();
+    let mut index = 0;
+    loop {
+        let (expr, next) = parse_conversion_expression(tokens, index, mode)?;
+        exprs.push(expr);
+        match tokens.get(next) {
+            None => return Ok(exprs),
+            Some(SpannedToken {
+                token: Token::Comma,
+                ..
+            }) => index = next + 1,
+            Some(SpannedToken {
+                token: Token::RParen | Token::RBracket,
+                span,
+            }) => return Err(CalcError::UnbalancedParen(span.clone())),
+            Some(t) => return Err(CalcError::TrailingInput(t.span.clone())),
+        }
+    }
+}
+
+/// index番目のトークン。入力が終わっていればエラー
+fn token_at(tokens: &[SpannedToken], index: usize) -> Result<&SpannedToken, CalcError> {
+    tokens.get(index).ok_or_else(|| {
+        let end = tokens.last().map_or(0, |t| t.span.end);
+        CalcError::UnexpectedEnd(end..end + 1)
+    })
+}
+
+/// 二項演算のノード。spanは演算子の位置
+fn binary(op: BinaryOp, operator: &SpannedToken, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::new(
+        ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        operator.span.clone(),
+    )
+}
+
+/// 単位の変換 x to unit（最も優先順位が低い）。spanは to、in の位置
+fn parse_conversion_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let (expr, index) = parse_bitwise_expression(tokens, index, 0, mode)?;
+    match tokens.get(index) {
+        Some(
+            keyword @ SpannedToken {
+                token: Token::Ident(name),
+                ..
+            },
+        ) if CONVERSION_KEYWORDS.contains(&name.as_str()) => {
+            let (unit, next) = parse_bitwise_expression(tokens, index + 1, 0, mode)?;
+            let kind = ExprKind::Convert(Box::new(expr), Box::new(unit));
+            Ok((Expr::new(kind, keyword.span.clone()), next))
+        }
+        _ => Ok((expr, index)),
+    }
+}
+
+/// ビット演算子（優先順位の低い順に | ^ & シフト）。^ は整数モードでのみ排他的論理和
+fn bitwise_operator(token: &Token, mode: NumberMode) -> Option<(usize, BinaryOp)> {
+    match token {
+        Token::Pipe => Some((0, BinaryOp::BitOr)),
+        Token::Caret if mode.is_integer() => Some((1, BinaryOp::BitXor)),
+        Token::Ampersand => Some((2, BinaryOp::BitAnd)),
+        Token::ShiftLeft => Some((3, BinaryOp::Shl)),
+        Token::ShiftRight => Some((3, BinaryOp::Shr)),
+        _ => None,
+    }
+}
+
+/// 優先順位がlevelのビット演算（左結合）。シフトより内側は加減算になる
+fn parse_bitwise_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    level: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    if level > 3 {
+        return parse_additive_expression(tokens, index, mode);
+    }
+    let (mut expr, mut index) = parse_bitwise_expression(tokens, index, level + 1, mode)?;
+    while let Some(operator) = tokens.get(index) {
+        let op = match bitwise_operator(&operator.token, mode) {
+            Some((op_level, op)) if op_level == level => op,
+            _ => break,
+        };
+        let (rhs, next) = parse_bitwise_expression(tokens, index + 1, level + 1, mode)?;
+        expr = binary(op, operator, expr, rhs);
+        index = next;
+    }
+    Ok((expr, index))
+}
+
+fn parse_additive_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let (mut expr, mut index) = parse_multiplicative_expression(tokens, index, mode)?;
+    while let Some(operator) = tokens.get(index) {
+        let op = match operator.token {
+            Token::Plus => BinaryOp::Add,
+            Token::Minus => BinaryOp::Sub,
+            _ => break,
+        };
+        let (rhs, next) = parse_multiplicative_expression(tokens, index + 1, mode)?;
+        expr = binary(op, operator, expr, rhs);
+        index = next;
+    }
+    Ok((expr, index))
+}
+
+fn parse_multiplicative_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let (mut expr, mut index) = parse_unary_expression(tokens, index, mode)?;
+    while let Some(operator) = tokens.get(index) {
+        let (op, start) = match &operator.token {
+            Token::Asterisk => (BinaryOp::Mul, index + 1),
+            Token::Slash => (BinaryOp::Div, index + 1),
+            Token::DoubleSlash => (BinaryOp::FloorDiv, index + 1),
+            Token::Percent => (BinaryOp::Rem, index + 1),
+            // 5 km のように続けて書いた名前は掛け算とみなす。spanは名前の位置
+            Token::Ident(name) if !CONVERSION_KEYWORDS.contains(&name.as_str()) => {
+                (BinaryOp::Mul, index)
+            }
+            _ => break,
+        };
+        let (rhs, next) = parse_unary_expression(tokens, start, mode)?;
+        expr = binary(op, operator, expr, rhs);
+        index = next;
+    }
+    Ok((expr, index))
+}
+
+/// 単項演算子。-2^2 は -(2^2) と解釈する。単項の + は読み飛ばす
+fn parse_unary_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let operator = token_at(tokens, index)?;
+    let op = match operator.token {
+        Token::Plus => return parse_unary_expression(tokens, index + 1, mode),
+        Token::Minus => UnaryOp::Neg,
+        Token::Tilde => UnaryOp::Not,
+        _ => return parse_power_expression(tokens, index, mode),
+    };
+    let (operand, next) = parse_unary_expression(tokens, index + 1, mode)?;
+    let expr = Expr::new(
+        ExprKind::Unary(op, Box::new(operand)),
+        operator.span.clone(),
+    );
+    Ok((expr, next))
+}
+
+/// べき乗（右結合）。指数には単項演算子を書ける（2^-1）
+///
+/// 整数モードでは ^ は排他的論理和になるため、** だけがべき乗になる。
+fn parse_power_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let (base, index) = parse_primary_expression(tokens, index, mode)?;
+    match tokens.get(index) {
+        Some(operator)
+            if operator.token == Token::DoubleAsterisk
+                || (operator.token == Token::Caret && !mode.is_integer()) =>
+        {
+            let (exponent, next) = parse_unary_expression(tokens, index + 1, mode)?;
+            Ok((binary(BinaryOp::Pow, operator, base, exponent), next))
+        }
+        _ => Ok((base, index)),
+    }
+}
+
+fn parse_primary_expression(
+    tokens: &[SpannedToken],
+    index: usize,
+    mode: NumberMode,
+) -> Result<(Expr, usize), CalcError> {
+    let first_token = token_at(tokens, index)?;
+    let span = first_token.span.clone();
+    match &first_token.token {
+        Token::LParen => {
+            let (expr, next) = parse_conversion_expression(tokens, index + 1, mode)?;
+            match tokens.get(next) {
+                Some(SpannedToken {
+                    token: Token::RParen,
+                    ..
+                }) => Ok((expr, next + 1)),
+                Some(t) => Err(CalcError::UnexpectedToken(t.span.clone())),
+                None => Err(CalcError::UnbalancedParen(span)),
+            }
+        }
+        Token::LBracket => {
+            let (elements, next) = parse_list(tokens, index, &Token::RBracket, mode)?;
+            Ok((Expr::new(ExprKind::List(elements), span), next))
+        }
+        Token::Number(text) => Ok((Expr::new(ExprKind::Number(text.clone()), span), index + 1)),
+        Token::MemoryRef(memory_name) => Ok((
+            Expr::new(ExprKind::Memory(memory_name.clone()), span),
+            index + 1,
+        )),
+        Token::HistoryRef(n) => Ok((Expr::new(ExprKind::History(*n), span), index + 1)),
+        Token::Ident(name) => {
+            if let Some(Token::LParen) = tokens.get(index + 1).map(|t| &t.token) {
+                let (args, next) = parse_list(tokens, index + 1, &Token::RParen, mode)?;
+                return Ok((Expr::new(ExprKind::Call(name.clone(), args), span), next));
+            }
+            Ok((Expr::new(ExprKind::Variable(name.clone()), span), index + 1))
+        }
+        _ => Err(CalcError::UnexpectedToken(span)),
+    }
+}
+
+/// 関数呼び出しの引数リスト ( 式, 式, ... ) や要素のリスト [ 式, 式, ... ] をパースする
+///
+/// indexは開き括弧の位置、closeは閉じ括弧のトークン。
+fn parse_list(
+    tokens: &[SpannedToken],
+    index: usize,
+    close: &Token,
+    mode: NumberMode,
+) -> Result<(Vec<Expr>, usize), CalcError> {
+    let open = &tokens[index];
+    let mut args = Vec::new();
+    let mut index = index + 1;
+    if tokens.get(index).map(|t| &t.token) == Some(close) {
+        return Ok((args, index + 1));
+    }
+    loop {
+        let (arg, next) = parse_conversion_expression(tokens, index, mode)?;
+        // 関数の引数には solve(x^2 = 2, x, 1) のような方程式も書ける
+        let (arg, next) = match tokens.get(next) {
+            Some(t) if t.token == Token::Equals && *close == Token::RParen => {
+                let (rhs, after) = parse_conversion_expression(tokens, next + 1, mode)?;
+                let kind = ExprKind::Equation(Box::new(arg), Box::new(rhs));
+                (Expr::new(kind, t.span.clone()), after)
+            }
+            _ => (arg, next),
+        };
+        args.push(arg);
+        match tokens.get(next) {
+            Some(SpannedToken {
+                token: Token::Comma,
+                ..
+            }) => index = next + 1,
+            Some(t) if t.token == *close => return Ok((args, next + 1)),
+            Some(t) => return Err(CalcError::UnexpectedToken(t.span.clone())),
+            None => return Err(CalcError::UnbalancedParen(open.span.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, BinaryOp, CalcError, Expr, ExprKind, NumberMode, UnaryOp};
+    use crate::lexer::Token;
+
+    fn parse_line(line: &str) -> Result<Expr, CalcError> {
+        parse(&Token::tokenize(line)?, NumberMode::Float)
+    }
+
+    fn number(text: &str, start: usize) -> Expr {
+        Expr::new(
+            ExprKind::Number(text.to_string()),
+            start..start + text.len(),
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        // 1 - -x * 2 は 1 - ((-x) * 2)
+        let expected = Expr::new(
+            ExprKind::Binary(
+                BinaryOp::Sub,
+                Box::new(number("1", 0)),
+                Box::new(Expr::new(
+                    ExprKind::Binary(
+                        BinaryOp::Mul,
+                        Box::new(Expr::new(
+                            ExprKind::Unary(
+                                UnaryOp::Neg,
+                                Box::new(Expr::new(ExprKind::Variable("x".to_string()), 5..6)),
+                            ),
+                            4..5,
+                        )),
+                        Box::new(number("2", 9)),
+                    ),
+                    7..8,
+                )),
+            ),
+            2..3,
+        );
+        assert_eq!(Ok(expected), parse_line("1 - -x * 2"));
+        assert_eq!(
+            Ok(Expr::new(ExprKind::List(Vec::new()), 0..1)),
+            parse_line("[]")
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(Err(CalcError::UnexpectedToken(4..5)), parse_line("1 + * 2"));
+        assert_eq!(Err(CalcError::UnexpectedEnd(3..4)), parse_line("1 +"));
+        assert_eq!(Err(CalcError::UnbalancedParen(0..1)), parse_line("(1 + 2"));
+        assert_eq!(
+            Err(CalcError::UnbalancedParen(5..6)),
+            parse_line("1 + 2) * 3")
+        );
+        assert_eq!(
+            Err(CalcError::UnbalancedParen(3..4)),
+            parse_line("max(1, 2")
+        );
+        assert_eq!(Err(CalcError::UnbalancedParen(0..1)), parse_line("[1, 2"));
+        assert_eq!(Err(CalcError::UnbalancedParen(6..7)), parse_line("[1, 2]]"));
+        assert_eq!(Err(CalcError::TrailingInput(2..3)), parse_line("1 2"));
+        assert_eq!(
+            Err(CalcError::TrailingInput(7..9)),
+            parse_line("1 to m to km")
+        );
+        assert_eq!(Err(CalcError::UnexpectedEnd(4..5)), parse_line("1 in"));
+        assert_eq!(Err(CalcError::UnexpectedEnd(0..1)), parse_line(""));
+    }
+}
@@ -0,0 +1,544 @@
+use crate::ast::Expr;
+use crate::error::CalcError;
+use crate::eval::{eval, Scope};
+use crate::lexer::Token;
+use crate::numeric::{bind, real};
+use crate::unit::Unit;
+use crate::{parser, Context};
+use std::fmt;
+
+/// グラフの横軸の変数
+pub const PLOT_VARIABLE: &str = "x";
+
+/// 関数の値を求める点の数（両端を含む）
+const SAMPLES: usize = 481;
+
+/// 端末に表示するグラフの大きさ（文字数）
+const WIDTH: usize = 60;
+const HEIGHT: usize = 16;
+
+/// SVGの大きさと、目盛りを書く余白
+const SVG_WIDTH: f64 = 640.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 50.0;
+
+/// 隣の点との差が縦軸の範囲のこの割合を超え、間の点の値が両端の間になければ、
+/// 不連続（1/x の x = 0 など）とみなして線をつながない
+const MAX_JUMP: f64 = 0.1;
+
+/// SVGの曲線の色と、ASCIIの曲線の記号（曲線の順に使い、足りなければ繰り返す）
+const COLORS: &[&str] = &["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd"];
+const MARKERS: &[char] = &['*', '+', 'o', '#', '@'];
+
+/// 点字の文字の中の点 (列, 行) のビット
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// 端末に描く文字
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Braille, // 1文字を2x4の点に分けて描く
+    Ascii,   // 1文字を1点とし、曲線ごとに記号を変える
+}
+
+/// グラフを描けない理由
+#[derive(Debug, PartialEq)]
+pub enum PlotError {
+    Input(String, CalcError), // 関数または範囲の端の文字列と、その中の位置を持つエラー
+    EmptyRange,               // 下端が上端以上
+}
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotError::Input(_, e) => write!(f, "{}", e),
+            PlotError::EmptyRange => write!(f, "plot range is empty"),
+        }
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+/// 式functions（, で区切る）の変数xをlowerからupperまで変えたグラフ
+///
+/// 範囲の両端と関数のエラーは、それぞれの文字列の中の位置で報告する。
+pub fn plot(functions: &str, lower: &str, upper: &str, ctx: &Context) -> Result<Plot, PlotError> {
+    let scope = Scope::global(ctx);
+    let bound = |text: &str| {
+        let text = text.trim();
+        Token::tokenize(text)
+            .map_err(CalcError::from)
+            .and_then(|tokens| parser::parse(&tokens, ctx.number_mode))
+            .and_then(|expr| {
+                let value = eval(&expr, &scope)?;
+                real(value, &expr.span)
+            })
+            .map_err(|e| PlotError::Input(text.to_string(), e))
+    };
+    let lower = bound(lower)?;
+    let upper_text = upper.trim();
+    let upper = bound(upper)?
+        .convert(&lower.unit)
+        .map_err(|e| {
+            let e = CalcError::value(e, &(0..upper_text.len()));
+            PlotError::Input(upper_text.to_string(), e)
+        })?
+        .number
+        .to_f64();
+    let lower_value = lower.number.to_f64();
+    if lower_value >= upper {
+        return Err(PlotError::EmptyRange);
+    }
+    let functions = functions.trim();
+    Token::tokenize(functions)
+        .map_err(CalcError::from)
+        .and_then(|tokens| parser::parse_sequence(&tokens, ctx.number_mode))
+        .and_then(|exprs| Plot::sample(&exprs, lower_value, upper, &lower.unit, &scope))
+        .map_err(|e| PlotError::Input(functions.to_string(), e))
+}
+
+/// 関数1つの標本
+struct Curve {
+    label: String,
+    points: Vec<Option<f64>>, // 値がない（定義域外、複素数）点はNone
+    jumps: Vec<usize>,        // 前の点と線でつながない（不連続な）点の番号
+}
+
+/// 標本化した関数のグラフ
+pub struct Plot {
+    curves: Vec<Curve>,
+    x: (f64, f64), // 横軸の範囲
+    y: (f64, f64), // 縦軸の範囲（値の最小から最大）
+}
+
+impl Plot {
+    /// 式exprsの変数xを単位unitの数としてlowerからupperまで変えた値を求める
+    ///
+    /// 一部の点で評価できない式はその点を飛ばす。どの点でも値がない式は最初のエラーにする。
+    pub fn sample(
+        exprs: &[Expr],
+        lower: f64,
+        upper: f64,
+        unit: &Unit,
+        scope: &Scope,
+    ) -> Result<Self, CalcError> {
+        let mode = scope.ctx().number_mode;
+        let x_at = |i: f64| lower + (upper - lower) * i / (SAMPLES - 1) as f64;
+        let value_at = |expr: &Expr, x: f64| {
+            eval(expr, &bind(scope, PLOT_VARIABLE, x, unit))
+                .and_then(|value| real(value, &expr.span))
+                .map(|y| Some(y.number.to_f64()).filter(|y| y.is_finite()))
+        };
+        let mut curves = Vec::new();
+        for expr in exprs {
+            let mut error = None;
+            let points: Vec<_> = (0..SAMPLES)
+                .map(|i| {
+                    value_at(expr, x_at(i as f64)).unwrap_or_else(|e| {
+                        error.get_or_insert(e);
+                        None
+                    })
+                })
+                .collect();
+            if points.iter().all(Option::is_none) {
+                return Err(error
+                    .unwrap_or_else(|| CalcError::Domain("plot".to_string(), expr.span.clone())));
+            }
+            curves.push(Curve {
+                label: expr.display(mode).to_string(),
+                points,
+                jumps: Vec::new(),
+            });
+        }
+
+        let values = || curves.iter().flat_map(|c| c.points.iter().flatten());
+        let (mut bottom, mut top) = (
+            values().copied().fold(f64::INFINITY, f64::min),
+            values().copied().fold(f64::NEG_INFINITY, f64::max),
+        );
+        // 定数の関数は上下に幅を持たせる
+        if top - bottom <= f64::EPSILON * top.abs().max(1.0) {
+            let margin = top.abs().max(1.0);
+            bottom -= margin;
+            top += margin;
+        }
+
+        // 大きく跳ぶ点の間の値を調べ、漸近線をまたいでいれば線をつながない
+        let max_jump = (top - bottom) * MAX_JUMP;
+        for (curve, expr) in curves.iter_mut().zip(exprs) {
+            curve.jumps = (1..SAMPLES)
+                .filter(|&i| match (curve.points[i - 1], curve.points[i]) {
+                    (Some(a), Some(b)) if (b - a).abs() > max_jump => {
+                        match value_at(expr, x_at(i as f64 - 0.5)) {
+                            Ok(Some(middle)) => !(a.min(b)..=a.max(b)).contains(&middle),
+                            _ => true,
+                        }
+                    }
+                    _ => false,
+                })
+                .collect();
+        }
+        Ok(Self {
+            curves,
+            x: (lower, upper),
+            y: (bottom, top),
+        })
+    }
+
+    /// 端末に表示するグラフ。縦軸の最大・最小と横軸の範囲の目盛り、x = 0 と y = 0 の軸、
+    /// 関数の一覧を添える
+    pub fn render(&self, style: Style) -> String {
+        let (dx, dy) = match style {
+            Style::Braille => (2, 4),
+            Style::Ascii => (1, 1),
+        };
+        let (columns, rows) = (WIDTH * dx, HEIGHT * dy);
+        let mut dots = vec![0u8; WIDTH * HEIGHT];
+        let mut marks = vec![None; WIDTH * HEIGHT];
+        for (i, curve) in self.curves.iter().enumerate() {
+            self.trace(curve, columns, rows, |column, row| {
+                let cell = row / dy * WIDTH + column / dx;
+                match style {
+                    Style::Braille => dots[cell] |= BRAILLE_DOTS[row % dy][column % dx],
+                    Style::Ascii => marks[cell] = Some(MARKERS[i % MARKERS.len()]),
+                }
+            });
+        }
+
+        let (x_axis, y_axis) = (self.zero_row(rows), self.zero_column(columns));
+        let (horizontal, vertical, cross, tick, corner) = match style {
+            Style::Braille => ('─', '│', '┼', '┤', '└'),
+            Style::Ascii => ('-', '|', '+', '+', '+'),
+        };
+        let labels: Vec<String> = (0..HEIGHT)
+            .map(|row| match row {
+                0 => label(self.y.1),
+                _ if row == HEIGHT - 1 => label(self.y.0),
+                _ if x_axis.map(|r| r / dy) == Some(row) => "0".to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        let margin = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+        let mut lines = Vec::new();
+        for (row, text) in labels.iter().enumerate() {
+            let frame = if text.is_empty() { vertical } else { tick };
+            let cells: String = (0..WIDTH)
+                .map(|column| {
+                    let cell = row * WIDTH + column;
+                    let on_x_axis = x_axis.map(|r| r / dy) == Some(row);
+                    let on_y_axis = y_axis.map(|c| c / dx) == Some(column);
+                    match (dots[cell], marks[cell]) {
+                        (0, Some(mark)) => mark,
+                        (0, None) if on_x_axis && on_y_axis => cross,
+                        (0, None) if on_x_axis => horizontal,
+                        (0, None) if on_y_axis => vertical,
+                        (0, None) => ' ',
+                        (bits, _) => char::from_u32(0x2800 + bits as u32).unwrap_or(' '),
+                    }
+                })
+                .collect();
+            lines.push(format!("{:>margin$} {}{}", text, frame, cells.trim_end()));
+        }
+        let indent = " ".repeat(margin + 2);
+        lines.push(format!(
+            "{} {}{}",
+            " ".repeat(margin),
+            corner,
+            horizontal.to_string().repeat(WIDTH)
+        ));
+        let (left, right) = (label(self.x.0), label(self.x.1));
+        let gap = WIDTH.saturating_sub(left.chars().count() + right.chars().count());
+        lines.push(format!(
+            "{}{}{}{}",
+            indent,
+            left,
+            " ".repeat(gap.max(1)),
+            right
+        ));
+        let legend: Vec<String> = match style {
+            Style::Braille => self.curves.iter().map(|c| c.label.clone()).collect(),
+            Style::Ascii => self
+                .curves
+                .iter()
+                .enumerate()
+                .map(|(i, c)| format!("{} {}", MARKERS[i % MARKERS.len()], c.label))
+                .collect(),
+        };
+        lines.push(format!("{}{}", indent, legend.join("   ")));
+        lines.join("\n")
+    }
+
+    /// SVGの画像。曲線は値のない点と不連続な点で途切れる
+    pub fn svg(&self) -> String {
+        let (width, height) = (SVG_WIDTH - 2.0 * SVG_MARGIN, SVG_HEIGHT - 2.0 * SVG_MARGIN);
+        let to_x = |x: f64| SVG_MARGIN + (x - self.x.0) / (self.x.1 - self.x.0) * width;
+        let to_y = |y: f64| SVG_MARGIN + (self.y.1 - y) / (self.y.1 - self.y.0) * height;
+        let mut elements = vec![
+            format!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+                SVG_WIDTH, SVG_HEIGHT
+            ),
+            format!(
+                r#"<rect width="{}" height="{}" fill="white"/>"#,
+                SVG_WIDTH, SVG_HEIGHT
+            ),
+            format!(
+                r#"<rect x="{0}" y="{0}" width="{1}" height="{2}" fill="none" stroke="black"/>"#,
+                SVG_MARGIN, width, height
+            ),
+        ];
+
+        // x = 0、y = 0 の軸
+        let axis = |x1: f64, y1: f64, x2: f64, y2: f64| {
+            format!(
+                r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="gray" stroke-dasharray="4 4"/>"#,
+                x1, y1, x2, y2
+            )
+        };
+        if self.y.0 <= 0.0 && 0.0 <= self.y.1 {
+            elements.push(axis(SVG_MARGIN, to_y(0.0), SVG_MARGIN + width, to_y(0.0)));
+        }
+        if self.x.0.min(self.x.1) <= 0.0 && 0.0 <= self.x.0.max(self.x.1) {
+            elements.push(axis(to_x(0.0), SVG_MARGIN, to_x(0.0), SVG_MARGIN + height));
+        }
+
+        for (i, curve) in self.curves.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let step = (self.x.1 - self.x.0) / (SAMPLES - 1) as f64;
+            // 切れ目ごとに M で新しい線を始める
+            let path: Vec<String> = self
+                .segments(curve)
+                .iter()
+                .flat_map(|segment| {
+                    segment.iter().enumerate().map(|(k, &(j, y))| {
+                        let x = self.x.0 + step * j as f64;
+                        let command = if k == 0 { 'M' } else { 'L' };
+                        format!("{}{:.2},{:.2}", command, to_x(x), to_y(y))
+                    })
+                })
+                .collect();
+            elements.push(format!(
+                r#"<path fill="none" stroke="{}" stroke-width="1.5" d="{}"/>"#,
+                color,
+                path.join(" ")
+            ));
+            elements.push(format!(
+                r#"<text x="{:.2}" y="{:.2}" font-size="12" text-anchor="end" fill="{}">{}</text>"#,
+                SVG_MARGIN + width - 8.0,
+                SVG_MARGIN + 16.0 * (i + 1) as f64,
+                color,
+                escape(&curve.label)
+            ));
+        }
+
+        // 縦軸の最大・最小と横軸の範囲の目盛り
+        let text = |x: f64, y: f64, anchor: &str, value: f64| {
+            format!(
+                r#"<text x="{:.2}" y="{:.2}" font-size="12" text-anchor="{}">{}</text>"#,
+                x,
+                y,
+                anchor,
+                escape(&label(value))
+            )
+        };
+        elements.push(text(SVG_MARGIN - 4.0, SVG_MARGIN + 4.0, "end", self.y.1));
+        elements.push(text(SVG_MARGIN - 4.0, SVG_MARGIN + height, "end", self.y.0));
+        elements.push(text(
+            SVG_MARGIN,
+            SVG_MARGIN + height + 16.0,
+            "start",
+            self.x.0,
+        ));
+        elements.push(text(
+            SVG_MARGIN + width,
+            SVG_MARGIN + height + 16.0,
+            "end",
+            self.x.1,
+        ));
+        elements.push("</svg>".to_string());
+        elements.join("\n") + "\n"
+    }
+
+    /// 曲線の点を途切れるまで隣の点と線でつなぎ、通る点 (列, 行) ごとにdrawを呼ぶ
+    fn trace(
+        &self,
+        curve: &Curve,
+        columns: usize,
+        rows: usize,
+        mut draw: impl FnMut(usize, usize),
+    ) {
+        for segment in self.segments(curve) {
+            let mut previous: Option<(f64, f64)> = None;
+            for (i, y) in segment {
+                let column = i as f64 / (SAMPLES - 1) as f64 * (columns - 1) as f64;
+                let row = self.row(y, rows);
+                let (from_column, from_row) = previous.unwrap_or((column, row));
+                let steps = (column - from_column)
+                    .abs()
+                    .max((row - from_row).abs())
+                    .ceil()
+                    .max(1.0) as usize;
+                for step in 0..=steps {
+                    let t = step as f64 / steps as f64;
+                    let c = from_column + (column - from_column) * t;
+                    let r = from_row + (row - from_row) * t;
+                    draw(c.round() as usize, r.round() as usize);
+                }
+                previous = Some((column, row));
+            }
+        }
+    }
+
+    /// 曲線を線でつなぐ点 (番号, 値) の並びに分ける
+    ///
+    /// 値のない点と、不連続な点（tan(x) の漸近線など）で分ける。
+    fn segments(&self, curve: &Curve) -> Vec<Vec<(usize, f64)>> {
+        let mut segments: Vec<Vec<(usize, f64)>> = Vec::new();
+        let mut previous = None;
+        for (i, y) in curve.points.iter().enumerate() {
+            match (*y, segments.last_mut()) {
+                (Some(y), Some(segment)) if previous.is_some() && !curve.jumps.contains(&i) => {
+                    segment.push((i, y))
+                }
+                (Some(y), _) => segments.push(vec![(i, y)]),
+                (None, _) => {}
+            }
+            previous = *y;
+        }
+        segments
+    }
+
+    /// 値yの点の行（上が0）
+    fn row(&self, y: f64, rows: usize) -> f64 {
+        (self.y.1 - y) / (self.y.1 - self.y.0) * (rows - 1) as f64
+    }
+
+    /// 範囲内にあれば y = 0 の点の行
+    fn zero_row(&self, rows: usize) -> Option<usize> {
+        (self.y.0 <= 0.0 && 0.0 <= self.y.1).then(|| self.row(0.0, rows).round() as usize)
+    }
+
+    /// 範囲内にあれば x = 0 の点の列
+    fn zero_column(&self, columns: usize) -> Option<usize> {
+        let (lower, upper) = self.x;
+        (lower.min(upper) <= 0.0 && 0.0 <= lower.max(upper))
+            .then(|| ((0.0 - lower) / (upper - lower) * (columns - 1) as f64).round() as usize)
+    }
+}
+
+/// 目盛りの数。有効数字4桁程度に丸め、大きい数と小さい数は指数で表す
+fn label(value: f64) -> String {
+    let text = if value != 0.0 && !(1e-3..1e5).contains(&value.abs()) {
+        format!("{:.2e}", value)
+    } else {
+        let text = format!("{:.3}", value);
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    };
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text
+    }
+}
+
+/// SVGの文字列に書けない文字を置き換える
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{label, Plot, Style, HEIGHT};
+    use crate::error::CalcError;
+    use crate::eval::Scope;
+    use crate::lexer::Token;
+    use crate::parser::parse_sequence;
+    use crate::unit::Unit;
+    use crate::Context;
+
+    fn plot(functions: &str, lower: f64, upper: f64) -> Result<Plot, CalcError> {
+        let ctx = Context::new();
+        let exprs = parse_sequence(&Token::tokenize(functions)?, ctx.number_mode)?;
+        Plot::sample(&exprs, lower, upper, &Unit::default(), &Scope::global(&ctx))
+    }
+
+    #[test]
+    fn test_render() {
+        let text = plot("x, -x", -1.0, 1.0).unwrap().render(Style::Ascii);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(HEIGHT + 3, lines.len());
+        // 左上から右下へ -x、左下から右上へ x
+        assert!(lines[0].starts_with(" 1 ++"));
+        assert!(lines[0].ends_with('*'));
+        assert!(lines[HEIGHT - 1].starts_with("-1 +*"));
+        assert!(lines[HEIGHT - 1].ends_with('+'));
+        assert_eq!(
+            "    -1                                                         1",
+            lines[HEIGHT + 1]
+        );
+        assert_eq!("    * x   + -x", lines[HEIGHT + 2]);
+
+        let text = plot("2", 1.0, 2.0).unwrap().render(Style::Braille);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("4 ┤", lines[0]);
+        assert_eq!(format!("  │{}", "⠉".repeat(60)), lines[HEIGHT / 2]);
+        assert_eq!(format!("0 ┤{}", "─".repeat(60)), lines[HEIGHT - 1]);
+    }
+
+    #[test]
+    fn test_sample() {
+        // 定義域外の点は飛ばし、どの点でも値がなければエラー
+        let svg = plot("sqrt(x)", -1.0, 1.0).unwrap().svg();
+        assert_eq!(1, svg.matches("<path").count());
+        assert_eq!(1, segments(&svg));
+        let svg = plot("1 / (x - 0.5)", 0.0, 1.0).unwrap().svg();
+        assert_eq!(2, segments(&svg));
+        assert!(svg.contains("1 / (x - 0.5)"));
+        assert_eq!(
+            Err(CalcError::UnknownIdentifier("y".to_string(), 0..1)),
+            plot("y", 0.0, 1.0).map(|_| ())
+        );
+        assert_eq!(
+            Err(CalcError::NotReal(0..4)),
+            plot("sqrt(x)", -2.0, -1.0).map(|_| ())
+        );
+    }
+
+    /// SVGの曲線の M で始まる線の数
+    fn segments(svg: &str) -> usize {
+        svg.split(" d=\"")
+            .skip(1)
+            .map(|d| d.matches('M').count())
+            .sum()
+    }
+
+    #[test]
+    fn test_discontinuity() {
+        // 漸近線の両側の点は、値があってもつながない
+        let svg = plot("1 / x", -1.0, 1.1).unwrap().svg();
+        assert_eq!(2, segments(&svg));
+        let svg = plot("tan(x)", -5.0, 5.0).unwrap().svg();
+        assert_eq!(5, segments(&svg));
+        // 急でも連続な曲線は途切れない
+        let svg = plot("exp(100 * x), x^3", 0.0, 1.0).unwrap().svg();
+        assert_eq!(2, segments(&svg));
+
+        // 端末のグラフでも漸近線 x = 0 をまたいで線を引かず、y軸が見える
+        let text = plot("1 / x", -1.0, 1.1).unwrap().render(Style::Ascii);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[..HEIGHT]
+            .iter()
+            .any(|line| line.matches('|').count() == 2));
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!("3.142", label(std::f64::consts::PI));
+        assert_eq!("-2", label(-2.0));
+        assert_eq!("0", label(-0.0001 * 0.0));
+        assert_eq!("1.23e5", label(123456.0));
+        assert_eq!("1.00e-4", label(0.0001));
+    }
+}
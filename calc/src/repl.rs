@@ -0,0 +1,371 @@
+use calc::{
+    AngleMode, CalcError, Context, LineResult, NumberMode, PlotError, Session, Style, Value,
+};
+use std::env;
+use std::fs;
+use std::io::stdin;
+use std::path::{Path, PathBuf};
+
+/// :load-data で読んだデータを入れる変数
+const DATA_VARIABLE: &str = "data";
+
+/// REPLの状態。評価の状態と、表示・保存の設定
+pub struct State {
+    pub ctx: Context,
+    pub radix: u32,     // 結果を表示する基数
+    pub polar: bool,    // 複素数を極形式（r∠θ）で表示する
+    pub autosave: bool, // 終了時に変数・メモリ・履歴を保存し、次回の起動時に読み込む
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            ctx: Context::new(),
+            radix: 10,
+            polar: false,
+            autosave: false,
+        }
+    }
+
+    /// 結果の表示。基数と複素数の表示形式の設定に従う
+    pub fn format_value(&self, value: &Value) -> String {
+        let polar = self.polar.then(|| self.ctx.angle_mode());
+        value.to_string_radix(self.radix, polar)
+    }
+}
+
+/// 1行の実行結果
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Empty,                    // 空行、コメントだけの行
+    Result(usize, Value),     // 履歴の$n番目に追加した値
+    Value(Value),             // 履歴に追加しない値（M+、M- の後のメモリの値）
+    Defined(String),          // 定義した関数の名前
+    Derivative(String),       // diff(f, x) の導関数の式
+    Message(String),          // コマンドの結果の通知
+    Text(String),             // 一覧、グラフなど複数行の出力
+    Error(String, CalcError), // 入力（またはその一部）と、その中の位置を持つエラー
+    Failure(String),          // 位置のないエラー（コマンドの使い方、ファイルの読み書き）
+}
+
+/// 1行を実行する。# から行末まではコメント
+pub fn run_line(line: &str, state: &mut State) -> Outcome {
+    let line = line.split('#').next().unwrap_or_default().trim_end();
+    let command = line.trim();
+    if command.is_empty() {
+        return Outcome::Empty;
+    }
+
+    // 設定の切り替え
+    match command {
+        ":deg" => {
+            state.ctx.set_angle_mode(AngleMode::Degrees);
+            return Outcome::Message("angle mode: degrees".to_string());
+        }
+        ":rad" => {
+            state.ctx.set_angle_mode(AngleMode::Radians);
+            return Outcome::Message("angle mode: radians".to_string());
+        }
+        // 結果を表示する基数
+        ":hex" | ":bin" | ":oct" | ":dec" => {
+            state.radix = match command {
+                ":hex" => 16,
+                ":bin" => 2,
+                ":oct" => 8,
+                _ => 10,
+            };
+            return Outcome::Message(format!("output radix: {}", state.radix));
+        }
+        // 複素数の表示形式
+        ":polar" | ":rect" => {
+            state.polar = command == ":polar";
+            let form = if state.polar { "polar" } else { "rectangular" };
+            return Outcome::Message(format!("complex display: {}", form));
+        }
+        _ => (),
+    }
+    if let Some(args) = command.strip_prefix(":mode") {
+        // 引数がなければ現在のモードを表示する
+        if args.trim().is_empty() {
+            return Outcome::Message(format!("number mode: {}", state.ctx.number_mode()));
+        }
+        return match NumberMode::parse(args) {
+            Some(mode) => {
+                state.ctx.set_number_mode(mode);
+                Outcome::Message(format!("number mode: {}", mode))
+            }
+            None => Outcome::Failure(
+                "usage: :mode float | rational | decimal [scale] | i8..u128 [checked]".to_string(),
+            ),
+        };
+    }
+
+    // 式をどう解釈したか
+    if let Some(text) = command.strip_prefix(":show") {
+        let text = text.trim();
+        return match state.ctx.show(text) {
+            Ok((parsed, simplified)) => Outcome::Text(format!(
+                " => parsed: {}\n => simplified: {}",
+                parsed, simplified
+            )),
+            Err(e) => Outcome::Error(text.to_string(), e),
+        };
+    }
+
+    // 関数のグラフ
+    if let Some(args) = command
+        .strip_prefix("plot ")
+        .filter(|args| args.contains(" from "))
+    {
+        return plot_command(args, &state.ctx);
+    }
+
+    // 定義済みの名前の一覧
+    match command {
+        "vars" => return Outcome::Text(list(state.ctx.variables(), "")),
+        "funcs" => {
+            let mut functions: Vec<_> = state.ctx.functions().collect();
+            functions.sort();
+            let lines: Vec<_> = functions
+                .into_iter()
+                .map(|(_, source)| format!("    {}", source))
+                .collect();
+            return Outcome::Text(lines.join("\n"));
+        }
+        "history" => return Outcome::Text(list_history(state)),
+        "memlist" => return Outcome::Text(list(state.ctx.memory(), "mem")),
+        "memclear" => {
+            state.ctx.clear_memory();
+            return Outcome::Message("memory cleared".to_string());
+        }
+        _ => (),
+    }
+
+    // 変数・メモリ・履歴の保存と読み込み
+    if let Some(rest) = command.strip_prefix(':') {
+        let (name, args) = rest.split_once(' ').unwrap_or((rest, ""));
+        if let Some(outcome) = session_command(name, args.trim(), state) {
+            return outcome;
+        }
+    }
+
+    match state.ctx.eval_line(line) {
+        Ok(LineResult::Value(result)) => Outcome::Result(state.ctx.history().len(), result),
+        Ok(LineResult::Memory(result)) => Outcome::Value(result),
+        Ok(LineResult::Defined(name)) => Outcome::Defined(name),
+        Ok(LineResult::Derivative(expr)) => Outcome::Derivative(expr),
+        Err(e) => Outcome::Error(line.to_string(), e),
+    }
+}
+
+/// 標準入力から1行読む（改行は除く）。入力の終わりではNone
+pub fn read_line() -> Option<String> {
+    let mut line = String::new();
+    match stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+/// 変数・メモリ・履歴の保存先の既定（設定ディレクトリの calc/session.txt）
+pub fn session_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("session.txt"))
+}
+
+/// 入力行の履歴の保存先（設定ディレクトリの calc/history.txt）
+pub fn history_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("history.txt"))
+}
+
+/// 設定ディレクトリの calc。XDG_CONFIG_HOME、HOME/.config、APPDATA の順に探す
+fn config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("calc"))
+}
+
+/// :save [path]、:load [path]、:autosave on|off、:load-data path column を実行する。
+/// それ以外のコマンドであればNone
+///
+/// pathを省略すると設定ディレクトリのファイルを使う。
+fn session_command(command: &str, args: &str, state: &mut State) -> Option<Outcome> {
+    let path = || -> Option<PathBuf> {
+        if args.is_empty() {
+            session_path()
+        } else {
+            Some(PathBuf::from(args))
+        }
+    };
+    let outcome = match command {
+        "save" | "load" => {
+            let Some(path) = path() else {
+                return Some(Outcome::Failure(
+                    "no config directory; give a path".to_string(),
+                ));
+            };
+            let result = if command == "save" {
+                Session::save(&state.ctx, &path, state.autosave).map(|()| "saved to")
+            } else {
+                Session::load(&path).map(|saved| {
+                    saved.restore(&mut state.ctx);
+                    "loaded from"
+                })
+            };
+            match result {
+                Ok(done) => Outcome::Message(format!("{} {}", done, path.display())),
+                Err(e) => Outcome::Failure(format!("{}: {}", path.display(), e)),
+            }
+        }
+        // 設定もファイルに保存するため、切り替えた時点で保存する
+        "autosave" => match args {
+            "on" | "off" => {
+                state.autosave = args == "on";
+                match session_path() {
+                    Some(path) => match Session::save(&state.ctx, &path, state.autosave) {
+                        Ok(()) => {
+                            Outcome::Message(format!("autosave: {} ({})", args, path.display()))
+                        }
+                        Err(e) => Outcome::Failure(format!("{}: {}", path.display(), e)),
+                    },
+                    None => Outcome::Failure("no config directory".to_string()),
+                }
+            }
+            _ => Outcome::Failure("usage: :autosave on | off".to_string()),
+        },
+        "load-data" => match args.rsplit_once(' ') {
+            Some((path, column)) => load_data(path.trim(), column, &mut state.ctx),
+            None => Outcome::Failure("usage: :load-data file column".to_string()),
+        },
+        _ => return None,
+    };
+    Some(outcome)
+}
+
+/// plot f(x), g(x), ... from a to b [ascii] [> file.svg] を実行する
+///
+/// グラフを点字（asciiではASCIIの記号）で描き、ファイル名があればSVGでも保存する。
+fn plot_command(args: &str, ctx: &Context) -> Outcome {
+    let (args, path) = match args.rsplit_once(" > ") {
+        Some((args, path)) => (args, Some(path.trim())),
+        None => (args, None),
+    };
+    let (args, style) = match args.trim_end().strip_suffix(" ascii") {
+        Some(args) => (args, Style::Ascii),
+        None => (args, Style::Braille),
+    };
+    let Some((functions, (lower, upper))) = args
+        .rsplit_once(" from ")
+        .and_then(|(functions, range)| Some((functions, range.split_once(" to ")?)))
+    else {
+        return Outcome::Failure(
+            "usage: plot f(x), ... from a to b [ascii] [> file.svg]".to_string(),
+        );
+    };
+    let plot = match ctx.plot(functions, lower, upper) {
+        Ok(plot) => plot,
+        Err(PlotError::Input(text, e)) => return Outcome::Error(text, e),
+        Err(e) => return Outcome::Failure(e.to_string()),
+    };
+    let mut text = plot.render(style);
+    if let Some(path) = path {
+        match fs::write(path, plot.svg()) {
+            Ok(()) => text.push_str(&format!("\n => saved plot to {}", path)),
+            Err(e) => return Outcome::Failure(format!("{}: {}", path, e)),
+        }
+    }
+    Outcome::Text(text)
+}
+
+/// pathのcolumnの列の数をベクトルにして変数dataに入れる。pathが - であれば標準入力から空行まで読む
+fn load_data(path: &str, column: &str, ctx: &mut Context) -> Outcome {
+    let text = if path == "-" {
+        let lines: Vec<_> = std::iter::from_fn(read_line)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        Ok(lines.join("\n"))
+    } else {
+        fs::read_to_string(path)
+    };
+    let result = match text {
+        Ok(text) => ctx
+            .load_data(DATA_VARIABLE, &text, column)
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(count) => Outcome::Message(format!("loaded {} values into {}", count, DATA_VARIABLE)),
+        Err(e) => Outcome::Failure(format!("{}: {}", path, e)),
+    }
+}
+
+/// 名前順の「名前 = 値」の一覧。名前にはprefixをつける
+fn list<'a>(values: impl Iterator<Item = (&'a str, &'a Value)>, prefix: &str) -> String {
+    let mut values: Vec<_> = values.collect();
+    values.sort_by_key(|&(name, _)| name);
+    let lines: Vec<_> = values
+        .into_iter()
+        .map(|(name, value)| format!("    {}{} = {}", prefix, name, value))
+        .collect();
+    lines.join("\n")
+}
+
+fn list_history(state: &State) -> String {
+    let lines: Vec<_> = state
+        .ctx
+        .history()
+        .enumerate()
+        .map(|(i, (input, value))| {
+            format!("    ${}  {} => {}", i + 1, input, state.format_value(value))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_line, Outcome, State};
+    use calc::CalcError;
+
+    #[test]
+    fn test_run_line() {
+        let mut state = State::new();
+        assert_eq!(Outcome::Empty, run_line("  # comment", &mut state));
+        assert_eq!(
+            Outcome::Defined("sq".to_string()),
+            run_line("sq(x) = x^2 # square", &mut state)
+        );
+        let Outcome::Result(1, value) = run_line("sq(3)", &mut state) else {
+            panic!("expected a result");
+        };
+        assert_eq!("9", state.format_value(&value));
+        assert_eq!(
+            Outcome::Message("angle mode: degrees".to_string()),
+            run_line(":deg", &mut state)
+        );
+        assert_eq!(
+            Outcome::Text("    sq(x) = x^2".to_string()),
+            run_line("funcs", &mut state)
+        );
+        assert_eq!(
+            Outcome::Error("1 / 0".to_string(), CalcError::DivisionByZero(2..3)),
+            run_line("1 / 0  # oops", &mut state)
+        );
+        assert!(matches!(
+            run_line(":mode nope", &mut state),
+            Outcome::Failure(_)
+        ));
+        // 表示の設定はREPLの状態に持つ
+        run_line(":hex", &mut state);
+        let Outcome::Result(2, value) = run_line("255", &mut state) else {
+            panic!("expected a result");
+        };
+        assert_eq!("0xff", state.format_value(&value));
+        assert_eq!(
+            Outcome::Text("    $1  sq(3) => 0x9\n    $2  255 => 0xff".to_string()),
+            run_line("history", &mut state)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::value::Value;
+use crate::{Context, HistoryEntry};
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+/// 保存ファイルの1行目
+const HEADER: &str = "# calc session";
+
+/// 保存・読み込みの失敗
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    Syntax(usize), // 読めない行（1始まり）
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(e) => write!(f, "{}", e),
+            SessionError::Syntax(line) => write!(f, "invalid session file at line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+/// ファイルに保存した変数・メモリ・履歴と自動保存の設定
+#[derive(Debug)]
+pub struct Session {
+    pub autosave: bool,
+    variables: HashMap<String, Value>,
+    memory: HashMap<String, Value>,
+    history: Vec<(String, Value)>,
+}
+
+impl Session {
+    /// pathを読む。読めない行があればその行番号をエラーにする
+    pub fn load(path: &Path) -> Result<Self, SessionError> {
+        decode(&fs::read_to_string(path)?)
+    }
+
+    /// ctxの変数・メモリ・履歴と自動保存の設定をpathに書く。ディレクトリがなければ作る
+    pub fn save(ctx: &Context, path: &Path, autosave: bool) -> Result<(), SessionError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, encode(ctx, autosave))?;
+        Ok(())
+    }
+
+    /// 変数・メモリ・履歴をctxのものと置き換える（関数と設定はそのまま）
+    pub fn restore(self, ctx: &mut Context) {
+        ctx.variables = self.variables;
+        ctx.memory.slots = self.memory;
+        ctx.history = self
+            .history
+            .into_iter()
+            .map(|(input, value)| HistoryEntry { input, value })
+            .collect();
+    }
+}
+
+/// 1行に1件ずつ、値は表現ごと書く（var x float:0.5、history float:3.0 1 + 2）
+fn encode(ctx: &Context, autosave: bool) -> String {
+    let mut lines = vec![
+        HEADER.to_string(),
+        format!("autosave {}", if autosave { "on" } else { "off" }),
+    ];
+    for (kind, values) in [("var", &ctx.variables), ("mem", &ctx.memory.slots)] {
+        let mut names: Vec<_> = values.keys().collect();
+        names.sort();
+        for name in names {
+            lines.push(format!("{} {} {}", kind, name, values[name].encode()));
+        }
+    }
+    for entry in &ctx.history {
+        lines.push(format!("history {} {}", entry.value.encode(), entry.input));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn decode(text: &str) -> Result<Session, SessionError> {
+    let mut session = Session {
+        autosave: false,
+        variables: HashMap::new(),
+        memory: HashMap::new(),
+        history: Vec::new(),
+    };
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || SessionError::Syntax(i + 1);
+        let mut fields = line.splitn(3, ' ');
+        let (Some(kind), Some(first)) = (fields.next(), fields.next()) else {
+            return Err(error());
+        };
+        let rest = fields.next();
+        match (kind, rest) {
+            ("autosave", None) => {
+                session.autosave = match first {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(error()),
+                }
+            }
+            ("var" | "mem", Some(value)) => {
+                let value = Value::decode(value).ok_or_else(error)?;
+                let values = if kind == "var" {
+                    &mut session.variables
+                } else {
+                    &mut session.memory
+                };
+                values.insert(first.to_string(), value);
+            }
+            ("history", Some(input)) => {
+                let value = Value::decode(first).ok_or_else(error)?;
+                session.history.push((input.to_string(), value));
+            }
+            _ => return Err(error()),
+        }
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, SessionError};
+    use crate::number::{Number, NumberMode};
+    use crate::unit::Unit;
+    use crate::value::{Quantity, Value};
+    use crate::Context;
+
+    #[test]
+    fn test_round_trip() {
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("rate".to_string(), Value::new(Number::Float(0.08)));
+        let trip = Value::Scalar(Quantity {
+            number: Number::Float(5.3),
+            unit: Unit::parse("km").unwrap(),
+        });
+        ctx.variables.insert("trip".to_string(), trip);
+        let third = Number::parse("1", NumberMode::Rational)
+            .and_then(|one| one.div(&Number::parse("3", NumberMode::Rational).unwrap()))
+            .unwrap();
+        ctx.memory.slots.insert("a".to_string(), Value::new(third));
+        ctx.push_history("1 + 2", Value::new(Number::Float(3.0)));
+        ctx.push_history("ans * rate", Value::new(Number::Float(0.24)));
+
+        let text = encode(&ctx, true);
+        assert_eq!(
+            "# calc session\n\
+             autosave on\n\
+             var rate float:0.08\n\
+             var trip float:5.3@km\n\
+             mem a rational:1/3\n\
+             history float:3.0 1 + 2\n\
+             history float:0.24 ans * rate\n",
+            text
+        );
+
+        let mut restored = Context::new();
+        let session = decode(&text).unwrap();
+        assert!(session.autosave);
+        session.restore(&mut restored);
+        assert_eq!(ctx.variables, restored.variables);
+        assert_eq!(ctx.memory.slots, restored.memory.slots);
+        assert_eq!(text, encode(&restored, true));
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let line = |result: Result<_, SessionError>| match result {
+            Err(SessionError::Syntax(line)) => Some(line),
+            _ => None,
+        };
+        assert_eq!(Some(2), line(decode("autosave off\nvar x\n")));
+        assert_eq!(Some(1), line(decode("mem a float:abc\n")));
+        assert_eq!(Some(3), line(decode("# calc session\n\nundo 1 2\n")));
+        assert_eq!(None, line(decode("# comment\n\nautosave off\n")));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::ast::{BinaryOp, Expr, ExprKind};
+use crate::eval::{eval, Scope};
+use crate::functions;
+use crate::number::Number;
+use crate::symbolic;
+use crate::Context;
+use std::cmp::Ordering;
+
+/// 定数だけの部分式を計算済みの値に置き換え、x + 0 や x * 1 を x にする
+///
+/// 変数・メモリ・ユーザー定義関数を含む部分式や、評価するとエラーになる部分式はそのまま残す
+/// （エラーは評価した時に報告する）。
+pub fn simplify(expr: &Expr, ctx: &Context) -> Expr {
+    let kind = match &expr.kind {
+        ExprKind::Unary(op, operand) => ExprKind::Unary(*op, Box::new(simplify(operand, ctx))),
+        ExprKind::Binary(op, lhs, rhs) => {
+            let lhs = simplify(lhs, ctx);
+            let rhs = simplify(rhs, ctx);
+            if let Some(operand) = identity(*op, &lhs, &rhs, ctx) {
+                return operand;
+            }
+            ExprKind::Binary(*op, Box::new(lhs), Box::new(rhs))
+        }
+        // diff(f, x) は導関数の式にする
+        ExprKind::Call(name, args) if name == "diff" && args.len() == 2 => {
+            match symbolic::derivative_of(args, ctx, &expr.span) {
+                Ok((derivative, _)) => return derivative,
+                Err(_) => ExprKind::Call(name.clone(), args.clone()),
+            }
+        }
+        ExprKind::Call(name, args) => ExprKind::Call(
+            name.clone(),
+            args.iter().map(|arg| simplify(arg, ctx)).collect(),
+        ),
+        ExprKind::Convert(value, unit) => {
+            ExprKind::Convert(Box::new(simplify(value, ctx)), unit.clone())
+        }
+        ExprKind::List(elements) => {
+            ExprKind::List(elements.iter().map(|e| simplify(e, ctx)).collect())
+        }
+        ExprKind::Equation(lhs, rhs) => {
+            ExprKind::Equation(Box::new(simplify(lhs, ctx)), Box::new(simplify(rhs, ctx)))
+        }
+        _ => return expr.clone(),
+    };
+    let expr = Expr::new(kind, expr.span.clone());
+    match fold(&expr, ctx) {
+        Some(value) => Expr::new(ExprKind::Constant(value), expr.span),
+        None => expr,
+    }
+}
+
+/// 子がすべて定数であれば、式を評価した値
+fn fold(expr: &Expr, ctx: &Context) -> Option<Number> {
+    let is_constant = |e: &Expr| matches!(e.kind, ExprKind::Number(_) | ExprKind::Constant(_));
+    let foldable = match &expr.kind {
+        ExprKind::Unary(_, operand) => is_constant(operand),
+        ExprKind::Binary(_, lhs, rhs) => is_constant(lhs) && is_constant(rhs),
+        ExprKind::Call(name, args) => {
+            functions::FUNCTION_NAMES.contains(&name.as_str())
+                && !ctx.functions.contains_key(name)
+                && args.iter().all(is_constant)
+        }
+        _ => false,
+    };
+    if !foldable {
+        return None;
+    }
+    // 発散した値は数値リテラルとして表示できないため残す
+    eval(expr, &Scope::global(ctx))
+        .ok()
+        .and_then(|value| value.plain().ok().cloned())
+        .filter(|number| number.to_f64().is_finite())
+}
+
+/// 定数の値
+fn constant_value(expr: &Expr, ctx: &Context) -> Option<Number> {
+    match &expr.kind {
+        ExprKind::Number(text) => Number::parse(text, ctx.number_mode).ok(),
+        ExprKind::Constant(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// 演算しても値が変わらない場合（x + 0、x * 1 など）、残る方の式
+fn identity(op: BinaryOp, lhs: &Expr, rhs: &Expr, ctx: &Context) -> Option<Expr> {
+    let is_zero = |e: &Expr| constant_value(e, ctx).is_some_and(|v| v.is_zero());
+    let is_one = |e: &Expr| {
+        constant_value(e, ctx)
+            .is_some_and(|v| v.compare(&Number::Float(1.0)) == Some(Ordering::Equal))
+    };
+    match op {
+        BinaryOp::Add if is_zero(lhs) => Some(rhs.clone()),
+        BinaryOp::Add | BinaryOp::Sub if is_zero(rhs) => Some(lhs.clone()),
+        BinaryOp::Mul if is_one(lhs) => Some(rhs.clone()),
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Pow if is_one(rhs) => Some(lhs.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simplify;
+    use crate::lexer::Token;
+    use crate::number::NumberMode;
+    use crate::parser::parse;
+    use crate::Context;
+
+    fn simplified(line: &str, ctx: &Context) -> String {
+        let tokens = Token::tokenize(line).unwrap();
+        let expr = parse(&tokens, ctx.number_mode).unwrap();
+        simplify(&expr, ctx).display(ctx.number_mode).to_string()
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let ctx = Context::new();
+        assert_eq!("7", simplified("1 + 2 * 3", &ctx));
+        assert_eq!("6 * x", simplified("(1 + 2) * 2 * x", &ctx));
+        assert_eq!("x * 2 * 3", simplified("x * 2 * 3", &ctx));
+        assert_eq!("x + 3", simplified("x + sqrt(9)", &ctx));
+        assert_eq!("(-1) ^ x", simplified("(1 - 2) ^ x", &ctx));
+        assert_eq!("2 * pi", simplified("2 * pi", &ctx));
+        // エラーになる部分式は残す
+        assert_eq!("x + 1 / 0", simplified("x + 1 / 0", &ctx));
+        assert_eq!("ln(0)", simplified("ln(0)", &ctx));
+
+        let mut ctx = Context::new();
+        ctx.number_mode = NumberMode::Rational;
+        assert_eq!("1/3 * x", simplified("1 / 3 * x", &ctx));
+        assert_eq!("x / (1/3)", simplified("x / (1 / 3)", &ctx));
+    }
+
+    #[test]
+    fn test_identities() {
+        let ctx = Context::new();
+        assert_eq!("x", simplified("0 + x * 1", &ctx));
+        assert_eq!("x", simplified("(x - 0) / (2 - 1)", &ctx));
+        assert_eq!("x ^ y", simplified("x ^ (y ^ 1)", &ctx));
+        assert_eq!("0 - x", simplified("0 - x", &ctx));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::number::{ArithmeticError, Number, NumberMode};
+use crate::unit::Unit;
+use crate::value::{Quantity, Value, ValueError};
+use std::cmp::Ordering;
+use std::slice;
+
+/// 統計の組み込み関数の名前の一覧
+pub const STATS_FUNCTION_NAMES: &[&str] = &[
+    "sum",
+    "mean",
+    "median",
+    "mode",
+    "var",
+    "stdev",
+    "percentile",
+    "linreg",
+];
+
+/// 統計の組み込み関数を呼び出す。nameがそれでなければNone
+///
+/// データはベクトル1つか、数を並べた引数（mean(1, 2, 3)）で渡す。単位のあるデータは最初の要素の単位に
+/// そろえて計算する。var、stdevは標本分散・標本標準偏差（n - 1 で割る）。
+pub fn call(
+    name: &str,
+    args: &[Value],
+    mode: NumberMode,
+    span: &Span,
+) -> Option<Result<Value, CalcError>> {
+    if !STATS_FUNCTION_NAMES.contains(&name) {
+        return None;
+    }
+    Some(evaluate(name, args, mode, span))
+}
+
+fn evaluate(name: &str, args: &[Value], mode: NumberMode, span: &Span) -> Result<Value, CalcError> {
+    let error = |e: ValueError| CalcError::value(e, span);
+    let domain = || CalcError::Domain(name.to_string(), span.clone());
+    match (name, args) {
+        ("percentile", [data, p]) => {
+            let data = data_of(elements_of(data)).map_err(error)?;
+            let p = p.plain().map_err(error)?;
+            let zero = Number::Float(0.0);
+            let hundred = Number::Float(100.0);
+            if p.compare(&zero) == Some(Ordering::Less)
+                || p.compare(&hundred) == Some(Ordering::Greater)
+            {
+                return Err(domain());
+            }
+            let fraction = count(100, mode)
+                .and_then(|hundred| Ok(p.div(&hundred)?))
+                .map_err(error)?;
+            let sorted = sorted(&data.numbers).map_err(error)?;
+            let x = percentile_of(&sorted, &fraction, mode).map_err(error)?;
+            Ok(data.quantity(x))
+        }
+        ("linreg", [xs, ys]) => {
+            let (xs, ys) = (elements_of(xs), elements_of(ys));
+            if xs.len() != ys.len() {
+                return Err(error(args[0].mismatch(&args[1])));
+            }
+            let (x, y) = (data_of(xs).map_err(error)?, data_of(ys).map_err(error)?);
+            // xがすべて同じ値であれば直線が決まらない
+            let first = &x.numbers[0];
+            if x.numbers
+                .iter()
+                .all(|v| v.compare(first) == Some(Ordering::Equal))
+            {
+                return Err(domain());
+            }
+            linreg(&x, &y, mode).map_err(error)
+        }
+        ("percentile" | "linreg", _) => Err(CalcError::ArgumentCount(
+            name.to_string(),
+            "2".to_string(),
+            span.clone(),
+        )),
+        _ => {
+            let data = match args {
+                [data] => data_of(elements_of(data)),
+                _ => args
+                    .iter()
+                    .map(|arg| arg.scalar().cloned())
+                    .collect::<Result<Vec<_>, _>>()
+                    .and_then(|elements| data_of(&elements)),
+            }
+            .map_err(error)?;
+            if (name == "var" || name == "stdev") && data.numbers.len() < 2 {
+                return Err(domain());
+            }
+            summary(name, &data, mode).map_err(error)
+        }
+    }
+}
+
+/// 最初の要素の単位にそろえた数の並びと、その単位
+struct Data {
+    numbers: Vec<Number>,
+    unit: Unit,
+}
+
+impl Data {
+    fn quantity(&self, number: Number) -> Value {
+        Value::Scalar(Quantity {
+            number,
+            unit: self.unit.clone(),
+        })
+    }
+}
+
+/// ベクトルの要素。数は1つの要素とみなし、行列は全要素を並べる
+fn elements_of(value: &Value) -> &[Quantity] {
+    match value {
+        Value::Scalar(q) => slice::from_ref(q),
+        Value::Vector(elements) => elements,
+        Value::Matrix(matrix) => matrix.elements(),
+    }
+}
+
+/// 要素を最初の要素の単位にそろえる。空であればエラー
+fn data_of(elements: &[Quantity]) -> Result<Data, ValueError> {
+    let Some(first) = elements.first() else {
+        return Err(ValueError::Shape("no data".to_string()));
+    };
+    let numbers = elements
+        .iter()
+        .map(|q| q.convert(&first.unit).map(|q| q.number))
+        .collect::<Result<_, _>>()?;
+    Ok(Data {
+        numbers,
+        unit: first.unit.clone(),
+    })
+}
+
+/// 整数nをmodeの表現にする
+fn count(n: usize, mode: NumberMode) -> Result<Number, ValueError> {
+    Ok(Number::parse(&n.to_string(), mode)?)
+}
+
+/// 小さい順に並べた数。複素数があればエラー
+fn sorted(numbers: &[Number]) -> Result<Vec<Number>, ValueError> {
+    if numbers.iter().any(|x| matches!(x, Number::Complex(_))) {
+        return Err(ArithmeticError::NotReal.into());
+    }
+    let mut numbers = numbers.to_vec();
+    numbers.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+    Ok(numbers)
+}
+
+fn sum(numbers: &[Number]) -> Result<Number, ArithmeticError> {
+    let (first, rest) = numbers.split_first().expect("data is never empty");
+    rest.iter().try_fold(first.clone(), |sum, x| sum.add(x))
+}
+
+fn mean(numbers: &[Number], mode: NumberMode) -> Result<Number, ValueError> {
+    Ok(sum(numbers)?.div(&count(numbers.len(), mode)?)?)
+}
+
+/// 標本分散（要素は2つ以上）
+fn variance(numbers: &[Number], mode: NumberMode) -> Result<Number, ValueError> {
+    let mean = mean(numbers, mode)?;
+    let squares = numbers
+        .iter()
+        .map(|x| x.sub(&mean).and_then(|d| d.mul(&d)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sum(&squares)?.div(&count(numbers.len() - 1, mode)?)?)
+}
+
+fn sqrt(x: &Number, mode: NumberMode) -> Result<Number, ValueError> {
+    Ok(x.pow(&Number::Float(0.5), mode)?)
+}
+
+/// データ全体から1つの値を求める関数
+fn summary(name: &str, data: &Data, mode: NumberMode) -> Result<Value, ValueError> {
+    let numbers = &data.numbers;
+    match name {
+        "sum" => Ok(data.quantity(sum(numbers)?)),
+        "mean" => Ok(data.quantity(mean(numbers, mode)?)),
+        "median" => {
+            let half = Number::parse("0.5", mode).unwrap_or(Number::Float(0.5));
+            percentile_of(&sorted(numbers)?, &half, mode).map(|x| data.quantity(x))
+        }
+        // 最も多く現れる値。同数であれば小さい方
+        "mode" => {
+            let sorted = sorted(numbers)?;
+            let runs = sorted.chunk_by(|a, b| a.compare(b) == Some(Ordering::Equal));
+            let most = runs
+                .rev()
+                .max_by_key(|run| run.len())
+                .expect("data is never empty");
+            Ok(data.quantity(most[0].clone()))
+        }
+        "var" => Ok(Value::Scalar(Quantity {
+            number: variance(numbers, mode)?,
+            unit: data.unit.pow(2),
+        })),
+        _ => Ok(data.quantity(sqrt(&variance(numbers, mode)?, mode)?)),
+    }
+}
+
+/// 小さい順に並べた数の、割合fraction（0 ≦ fraction ≦ 1）の位置の値。順位の間は線形に補間する
+fn percentile_of(
+    sorted: &[Number],
+    fraction: &Number,
+    mode: NumberMode,
+) -> Result<Number, ValueError> {
+    let rank = fraction.mul(&count(sorted.len() - 1, mode)?)?;
+    let lower = (rank.to_f64().floor() as usize).min(sorted.len() - 1);
+    let Some(upper) = sorted.get(lower + 1) else {
+        return Ok(sorted[lower].clone());
+    };
+    let weight = rank.sub(&count(lower, mode)?)?;
+    let step = upper.sub(&sorted[lower])?.mul(&weight)?;
+    Ok(sorted[lower].add(&step)?)
+}
+
+/// 最小二乗法による直線 y = slope x + intercept の当てはめ。結果は [slope, intercept, r]（rは相関係数）
+fn linreg(x: &Data, y: &Data, mode: NumberMode) -> Result<Value, ValueError> {
+    let (mean_x, mean_y) = (mean(&x.numbers, mode)?, mean(&y.numbers, mode)?);
+    let mut sxx = Vec::new();
+    let mut sxy = Vec::new();
+    let mut syy = Vec::new();
+    for (xi, yi) in x.numbers.iter().zip(&y.numbers) {
+        let dx = xi.sub(&mean_x)?;
+        let dy = yi.sub(&mean_y)?;
+        sxx.push(dx.mul(&dx)?);
+        sxy.push(dx.mul(&dy)?);
+        syy.push(dy.mul(&dy)?);
+    }
+    let (sxx, sxy, syy) = (sum(&sxx)?, sum(&sxy)?, sum(&syy)?);
+    let slope = sxy.div(&sxx)?;
+    let intercept = mean_y.sub(&slope.mul(&mean_x)?)?;
+    // yがすべて同じ値であれば相関係数は決まらない
+    let r = match sxy.div(&sqrt(&sxx.mul(&syy)?, mode)?) {
+        Ok(r) => r,
+        Err(ArithmeticError::DivisionByZero) => Number::Float(f64::NAN),
+        Err(e) => return Err(e.into()),
+    };
+
+    // 傾きの単位は y の単位 / x の単位
+    let slope = Quantity {
+        number: slope,
+        unit: y.unit.clone(),
+    }
+    .div(&Quantity {
+        number: count(1, mode)?,
+        unit: x.unit.clone(),
+    })?;
+    let intercept = Quantity {
+        number: intercept,
+        unit: y.unit.clone(),
+    };
+    Ok(Value::Vector(vec![slope, intercept, Quantity::new(r)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CalcError;
+    use crate::number::NumberMode;
+    use crate::Context;
+
+    fn eval(line: &str, mode: NumberMode) -> Result<String, CalcError> {
+        let mut ctx = Context::new();
+        ctx.number_mode = mode;
+        ctx.eval(line).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_summary() {
+        let float = NumberMode::Float;
+        let data = "[2, 4, 4, 4, 5, 5, 7, 9]";
+        let call = |name: &str| eval(&format!("{}({})", name, data), float).unwrap();
+        assert_eq!("40", call("sum"));
+        assert_eq!("5", call("mean"));
+        assert_eq!("4.5", call("median"));
+        assert_eq!("4", call("mode"));
+        assert_eq!("4.571428571428571", call("var"));
+        assert_eq!("2.138089935299395", call("stdev"));
+        assert_eq!(Ok("2.5".to_string()), eval("median(4, 1, 3, 2)", float));
+        assert_eq!(Ok("0.75 m".to_string()), eval("mean([1 m, 50 cm])", float));
+        assert_eq!(Ok("2 m^2".to_string()), eval("var([1 m, 3 m])", float));
+
+        // 有理数のモードでは誤差なく計算する
+        let rational = NumberMode::Rational;
+        assert_eq!(
+            Ok("32/7".to_string()),
+            eval(&format!("var({})", data), rational)
+        );
+
+        assert_eq!(
+            Err(CalcError::Domain("stdev".to_string(), 0..5)),
+            eval("stdev([5])", float)
+        );
+        assert_eq!(Err(CalcError::NotReal(0..4)), eval("mode([1i, 2])", float));
+        assert_eq!(
+            Err(CalcError::ShapeMismatch("no data".to_string(), 0..4)),
+            eval("mean([])", float)
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let float = NumberMode::Float;
+        let data = "[15, 20, 35, 40, 50]";
+        let call = |p: &str| eval(&format!("percentile({}, {})", data, p), float);
+        assert_eq!(Ok("15".to_string()), call("0"));
+        assert_eq!(Ok("20".to_string()), call("25"));
+        assert_eq!(Ok("29".to_string()), call("40"));
+        assert_eq!(Ok("50".to_string()), call("100"));
+        assert_eq!(
+            Err(CalcError::Domain("percentile".to_string(), 0..10)),
+            call("101")
+        );
+        assert_eq!(
+            Err(CalcError::ArgumentCount(
+                "percentile".to_string(),
+                "2".to_string(),
+                0..10
+            )),
+            eval(&format!("percentile({})", data), float)
+        );
+    }
+
+    #[test]
+    fn test_linreg() {
+        let float = NumberMode::Float;
+        assert_eq!(
+            Ok("[2, 1, 1]".to_string()),
+            eval("linreg([1, 2, 3], [3, 5, 7])", float)
+        );
+        assert_eq!(
+            Ok("[1/2, 1, 0.5]".to_string()),
+            eval("linreg([1, 2, 3], [1, 3, 2])", NumberMode::Rational)
+        );
+        assert_eq!(
+            Ok("[2 m/s, 1 m, 1]".to_string()),
+            eval("linreg([1 s, 2 s], [3 m, 5 m])", float)
+        );
+        assert_eq!(
+            Err(CalcError::Domain("linreg".to_string(), 0..6)),
+            eval("linreg([1, 1], [2, 3])", float)
+        );
+        assert_eq!(
+            Err(CalcError::ShapeMismatch(
+                "vector of 2 and vector of 3".to_string(),
+                0..6
+            )),
+            eval("linreg([1, 2], [1, 2, 3])", float)
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, UnaryOp};
+use crate::error::CalcError;
+use crate::functions::AngleMode;
+use crate::lexer::Span;
+use crate::number::{Number, NumberMode};
+use crate::simplify::simplify;
+use crate::{Context, MAX_CALL_DEPTH};
+
+/// 式を引数にとる組み込み関数の名前の一覧
+pub const SYMBOLIC_FUNCTION_NAMES: &[&str] = &["diff"];
+
+/// diff(f, x) または diff(f, x, a) の引数から、簡単にした導関数と変数の名前を求める
+pub fn derivative_of<'a>(
+    args: &'a [Expr],
+    ctx: &Context,
+    span: &Span,
+) -> Result<(Expr, &'a str), CalcError> {
+    let (f, var) = match args {
+        [f, var] | [f, var, _] => (f, var),
+        _ => {
+            let expected = "2 or 3".to_string();
+            return Err(CalcError::ArgumentCount(
+                "diff".to_string(),
+                expected,
+                span.clone(),
+            ));
+        }
+    };
+    let ExprKind::Variable(name) = &var.kind else {
+        return Err(CalcError::NotVariable(var.span.clone()));
+    };
+    Ok((derivative(f, name, ctx)?, name))
+}
+
+/// 式fの変数varについての導関数を簡単にしたもの
+pub fn derivative(f: &Expr, var: &str, ctx: &Context) -> Result<Expr, CalcError> {
+    let derivative = Derivative { var, ctx, depth: 0 }.of(f)?;
+    Ok(simplify(&derivative, ctx))
+}
+
+/// 変数varについての微分
+struct Derivative<'a> {
+    var: &'a str,
+    ctx: &'a Context,
+    depth: usize, // 展開したユーザー定義関数の深さ
+}
+
+impl Derivative<'_> {
+    fn of(&self, expr: &Expr) -> Result<Expr, CalcError> {
+        let span = &expr.span;
+        let not_differentiable =
+            |name: &str| Err(CalcError::NotDifferentiable(name.to_string(), span.clone()));
+        match &expr.kind {
+            ExprKind::Variable(name) if name == self.var => Ok(number("1", span)),
+            ExprKind::Number(_)
+            | ExprKind::Constant(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Memory(_)
+            | ExprKind::History(_) => Ok(number("0", span)),
+            ExprKind::Unary(UnaryOp::Neg, u) => Ok(neg(self.of(u)?, span)),
+            ExprKind::Unary(UnaryOp::Not, _) => not_differentiable("~"),
+            ExprKind::Binary(op, u, v) => self.binary(*op, u, v, span),
+            ExprKind::List(elements) => Ok(Expr::new(
+                ExprKind::List(
+                    elements
+                        .iter()
+                        .map(|e| self.of(e))
+                        .collect::<Result<_, _>>()?,
+                ),
+                span.clone(),
+            )),
+            ExprKind::Convert(..) => not_differentiable("to"),
+            ExprKind::Equation(..) => not_differentiable("="),
+            ExprKind::Call(name, args) => self.call(name, args, span),
+        }
+    }
+
+    /// 和・差・積・商の微分と、べき乗の微分
+    fn binary(&self, op: BinaryOp, u: &Expr, v: &Expr, span: &Span) -> Result<Expr, CalcError> {
+        let (du, dv) = (self.of(u)?, self.of(v)?);
+        let (u, v) = (u.clone(), v.clone());
+        Ok(match op {
+            BinaryOp::Add => add(du, dv, span),
+            BinaryOp::Sub => sub(du, dv, span),
+            // (uv)' = u'v + uv'
+            BinaryOp::Mul => add(mul(du, v, span), mul(u, dv, span), span),
+            // (u/v)' = (u'v - uv') / v^2
+            BinaryOp::Div if is_zero(&dv) => div(du, v, span),
+            BinaryOp::Div => div(
+                sub(mul(du, v.clone(), span), mul(u, dv, span), span),
+                pow(v, number("2", span), span),
+                span,
+            ),
+            // (u^n)' = n u^(n-1) u'
+            BinaryOp::Pow if is_zero(&dv) => {
+                let exponent = sub(v.clone(), number("1", span), span);
+                mul(mul(v, pow(u, exponent, span), span), du, span)
+            }
+            // (e^v)' = e^v v'、(a^v)' = a^v ln(a) v'
+            BinaryOp::Pow if is_zero(&du) => {
+                let power = pow(u.clone(), v, span);
+                if self.is_euler(&u) {
+                    mul(power, dv, span)
+                } else {
+                    mul(mul(power, call("ln", u, span), span), dv, span)
+                }
+            }
+            // (u^v)' = u^v (v' ln(u) + v u' / u)
+            BinaryOp::Pow => {
+                let power = pow(u.clone(), v.clone(), span);
+                let log_term = mul(dv, call("ln", u.clone(), span), span);
+                let power_term = div(mul(v, du, span), u, span);
+                mul(power, add(log_term, power_term, span), span)
+            }
+            op => {
+                let symbol = op.symbol(self.ctx.number_mode);
+                return Err(CalcError::NotDifferentiable(
+                    symbol.to_string(),
+                    span.clone(),
+                ));
+            }
+        })
+    }
+
+    /// 関数の微分（合成関数の微分）。ユーザー定義関数は本体を展開して微分する
+    fn call(&self, name: &str, args: &[Expr], span: &Span) -> Result<Expr, CalcError> {
+        if let Some(function) = self.ctx.functions.get(name) {
+            if function.params.len() != args.len() {
+                let expected = function.params.len().to_string();
+                return Err(CalcError::ArgumentCount(
+                    name.to_string(),
+                    expected,
+                    span.clone(),
+                ));
+            }
+            if self.depth >= MAX_CALL_DEPTH {
+                return Err(CalcError::RecursionLimit(span.clone()));
+            }
+            let body = substitute(&function.body, &function.params, args);
+            let inner = Derivative {
+                depth: self.depth + 1,
+                ..*self
+            };
+            return inner.of(&body);
+        }
+        if name == "diff" {
+            let (derivative, _) = derivative_of(args, self.ctx, span)?;
+            return self.of(&derivative);
+        }
+        // 2引数の関数は1引数の関数で書き直す
+        match (name, args) {
+            ("log", [x, base]) => {
+                let quotient = div(
+                    call("ln", x.clone(), span),
+                    call("ln", base.clone(), span),
+                    span,
+                );
+                return self.of(&quotient);
+            }
+            ("hypot", [x, y]) => {
+                let two = || number("2", span);
+                let squares = add(
+                    pow(x.clone(), two(), span),
+                    pow(y.clone(), two(), span),
+                    span,
+                );
+                return self.of(&call("sqrt", squares, span));
+            }
+            _ => (),
+        }
+        let [u] = args else {
+            return Err(CalcError::NotDifferentiable(name.to_string(), span.clone()));
+        };
+        let du = self.of(u)?;
+        let u = u.clone();
+        let one = || number("1", span);
+        let degrees = self.ctx.angle_mode == AngleMode::Degrees;
+        // 度数法では三角関数の引数、逆三角関数の結果が180/pi倍になる
+        let radians = |e: Expr| {
+            if degrees {
+                div(
+                    mul(e, variable("pi", span), span),
+                    number("180", span),
+                    span,
+                )
+            } else {
+                e
+            }
+        };
+        let degrees_of = |e: Expr| {
+            if degrees {
+                div(
+                    mul(e, number("180", span), span),
+                    variable("pi", span),
+                    span,
+                )
+            } else {
+                e
+            }
+        };
+        // 1 - u^2
+        let one_minus_square = || sub(one(), pow(u.clone(), number("2", span), span), span);
+        let outer = match name {
+            "sqrt" => div(
+                one(),
+                mul(number("2", span), call("sqrt", u, span), span),
+                span,
+            ),
+            "abs" => div(u.clone(), call("abs", u, span), span),
+            "sin" => radians(call("cos", u, span)),
+            "cos" => radians(neg(call("sin", u, span), span)),
+            "tan" => radians(div(
+                one(),
+                pow(call("cos", u, span), number("2", span), span),
+                span,
+            )),
+            "asin" => degrees_of(div(one(), call("sqrt", one_minus_square(), span), span)),
+            "acos" => degrees_of(neg(
+                div(one(), call("sqrt", one_minus_square(), span), span),
+                span,
+            )),
+            "atan" => degrees_of(div(
+                one(),
+                add(one(), pow(u, number("2", span), span), span),
+                span,
+            )),
+            "ln" => div(one(), u, span),
+            "log10" => div(
+                one(),
+                mul(u, call("ln", number("10", span), span), span),
+                span,
+            ),
+            "exp" => call("exp", u, span),
+            _ => return Err(CalcError::NotDifferentiable(name.to_string(), span.clone())),
+        };
+        Ok(mul(outer, du, span))
+    }
+
+    /// 自然対数の底 e か（変数や仮引数で上書きされていない）
+    fn is_euler(&self, expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Variable(name) if name == "e" && self.var != "e")
+    }
+}
+
+/// 仮引数paramsをargsの式に置き換えた本体
+fn substitute(body: &Expr, params: &[String], args: &[Expr]) -> Expr {
+    let kind = match &body.kind {
+        ExprKind::Variable(name) => match params.iter().position(|p| p == name) {
+            Some(i) => return args[i].clone(),
+            None => return body.clone(),
+        },
+        ExprKind::Unary(op, operand) => {
+            ExprKind::Unary(*op, Box::new(substitute(operand, params, args)))
+        }
+        ExprKind::Binary(op, lhs, rhs) => ExprKind::Binary(
+            *op,
+            Box::new(substitute(lhs, params, args)),
+            Box::new(substitute(rhs, params, args)),
+        ),
+        ExprKind::Call(name, call_args) => ExprKind::Call(
+            name.clone(),
+            call_args
+                .iter()
+                .map(|arg| substitute(arg, params, args))
+                .collect(),
+        ),
+        ExprKind::List(elements) => ExprKind::List(
+            elements
+                .iter()
+                .map(|e| substitute(e, params, args))
+                .collect(),
+        ),
+        ExprKind::Convert(value, unit) => {
+            ExprKind::Convert(Box::new(substitute(value, params, args)), unit.clone())
+        }
+        ExprKind::Equation(lhs, rhs) => ExprKind::Equation(
+            Box::new(substitute(lhs, params, args)),
+            Box::new(substitute(rhs, params, args)),
+        ),
+        _ => return body.clone(),
+    };
+    Expr::new(kind, body.span.clone())
+}
+
+fn number(text: &str, span: &Span) -> Expr {
+    Expr::new(ExprKind::Number(text.to_string()), span.clone())
+}
+
+fn variable(name: &str, span: &Span) -> Expr {
+    Expr::new(ExprKind::Variable(name.to_string()), span.clone())
+}
+
+fn call(name: &str, arg: Expr, span: &Span) -> Expr {
+    Expr::new(ExprKind::Call(name.to_string(), vec![arg]), span.clone())
+}
+
+/// 数値リテラルまたは計算済みの値の値
+fn literal(expr: &Expr) -> Option<f64> {
+    match &expr.kind {
+        ExprKind::Number(text) => Number::parse(text, NumberMode::Float)
+            .ok()
+            .map(|n| n.to_f64()),
+        ExprKind::Constant(value) => Some(value.to_f64()),
+        _ => None,
+    }
+}
+
+/// 位置を除いて同じ式か
+fn same(a: &Expr, b: &Expr) -> bool {
+    let mode = NumberMode::Float;
+    a.display(mode).to_string() == b.display(mode).to_string()
+}
+
+fn is_zero(expr: &Expr) -> bool {
+    literal(expr) == Some(0.0)
+}
+
+fn is_one(expr: &Expr) -> bool {
+    literal(expr) == Some(1.0)
+}
+
+// 導関数を組み立てる演算。0や1との演算はその場で簡単にする
+
+fn binary(op: BinaryOp, lhs: Expr, rhs: Expr, span: &Span) -> Expr {
+    Expr::new(
+        ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        span.clone(),
+    )
+}
+
+fn add(lhs: Expr, rhs: Expr, span: &Span) -> Expr {
+    if is_zero(&lhs) {
+        rhs
+    } else if is_zero(&rhs) {
+        lhs
+    } else {
+        binary(BinaryOp::Add, lhs, rhs, span)
+    }
+}
+
+fn sub(lhs: Expr, rhs: Expr, span: &Span) -> Expr {
+    if is_zero(&rhs) {
+        lhs
+    } else if is_zero(&lhs) {
+        neg(rhs, span)
+    } else {
+        binary(BinaryOp::Sub, lhs, rhs, span)
+    }
+}
+
+/// 積。定数の係数は前に出してまとめ（3 * (2 * x) は 6 * x）、1 / d との積は商にする
+fn mul(lhs: Expr, rhs: Expr, span: &Span) -> Expr {
+    if is_zero(&lhs) || is_zero(&rhs) {
+        return number("0", span);
+    } else if is_one(&lhs) {
+        return rhs;
+    } else if is_one(&rhs) {
+        return lhs;
+    }
+    let reciprocal = |e: &Expr| match &e.kind {
+        ExprKind::Binary(BinaryOp::Div, n, d) if is_one(n) => Some((**d).clone()),
+        _ => None,
+    };
+    if let Some(d) = reciprocal(&lhs) {
+        return div(rhs, d, span);
+    }
+    if let Some(d) = reciprocal(&rhs) {
+        return div(lhs, d, span);
+    }
+    if literal(&rhs).is_some() && literal(&lhs).is_none() {
+        return mul(rhs, lhs, span);
+    }
+    if let ExprKind::Binary(BinaryOp::Mul, c, rest) = &rhs.kind {
+        if literal(&lhs).is_some() && literal(c).is_some() {
+            let coefficient = binary(BinaryOp::Mul, lhs, (**c).clone(), span);
+            return binary(BinaryOp::Mul, coefficient, (**rest).clone(), span);
+        }
+    }
+    binary(BinaryOp::Mul, lhs, rhs, span)
+}
+
+/// 商。同じ式どうしの商は1にする
+fn div(lhs: Expr, rhs: Expr, span: &Span) -> Expr {
+    if is_zero(&lhs) {
+        number("0", span)
+    } else if is_one(&rhs) {
+        lhs
+    } else if same(&lhs, &rhs) {
+        number("1", span)
+    } else {
+        binary(BinaryOp::Div, lhs, rhs, span)
+    }
+}
+
+fn pow(base: Expr, exponent: Expr, span: &Span) -> Expr {
+    if is_zero(&exponent) {
+        number("1", span)
+    } else if is_one(&exponent) {
+        base
+    } else {
+        binary(BinaryOp::Pow, base, exponent, span)
+    }
+}
+
+fn neg(operand: Expr, span: &Span) -> Expr {
+    if is_zero(&operand) {
+        return operand;
+    }
+    match operand.kind {
+        ExprKind::Unary(UnaryOp::Neg, inner) => *inner,
+        _ => Expr::new(
+            ExprKind::Unary(UnaryOp::Neg, Box::new(operand)),
+            span.clone(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::derivative_of;
+    use crate::ast::ExprKind;
+    use crate::error::CalcError;
+    use crate::functions::AngleMode;
+    use crate::lexer::Token;
+    use crate::parser::parse;
+    use crate::Context;
+
+    /// diff(...) の行の導関数を表示する
+    fn derivative(line: &str, ctx: &Context) -> Result<String, CalcError> {
+        let expr = parse(&Token::tokenize(line)?, ctx.number_mode)?;
+        let ExprKind::Call(_, args) = &expr.kind else {
+            panic!("not a call: {}", line);
+        };
+        let (derivative, _) = derivative_of(args, ctx, &expr.span)?;
+        Ok(derivative.display(ctx.number_mode).to_string())
+    }
+
+    #[test]
+    fn test_rules() {
+        let ctx = Context::new();
+        let d = |line| derivative(line, &ctx).unwrap();
+        assert_eq!("6 * x - 2", d("diff(3 * x^2 - 2 * x + 1, x)"));
+        assert_eq!("cos(x) * x + sin(x)", d("diff(sin(x) * x, x)"));
+        assert_eq!("(1 - ln(x)) / x ^ 2", d("diff(ln(x) / x, x)"));
+        assert_eq!("2 * e ^ (2 * x)", d("diff(e^(2 * x), x)"));
+        assert_eq!("x ^ x * (ln(x) + 1)", d("diff(x^x, x)"));
+        assert_eq!("-sin(x)", d("diff(cos(x), x)"));
+        assert_eq!("6 * x", d("diff(diff(x^3, x), x)"));
+        assert_eq!("0", d("diff(y^2, x)"));
+        assert_eq!("[1, 2 * x]", d("diff([x, x^2], x)"));
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut ctx = Context::new();
+        let tokens = Token::tokenize("f(t) = t^2 + 3 * t").unwrap();
+        crate::eval_line("f(t) = t^2 + 3 * t", &tokens, &mut ctx).unwrap();
+        assert_eq!(
+            Ok("2 * x + 3".to_string()),
+            derivative("diff(f(x), x)", &ctx)
+        );
+
+        // 度数法では三角関数の微分に pi / 180 がかかる
+        ctx.angle_mode = AngleMode::Degrees;
+        assert_eq!(
+            Ok("cos(x) * pi / 180".to_string()),
+            derivative("diff(sin(x), x)", &ctx)
+        );
+
+        assert_eq!(
+            Err(CalcError::NotDifferentiable("floor".to_string(), 5..10)),
+            derivative("diff(floor(x), x)", &ctx)
+        );
+        assert_eq!(
+            Err(CalcError::NotDifferentiable("%".to_string(), 7..8)),
+            derivative("diff(x % 2, x)", &ctx)
+        );
+        assert_eq!(
+            Err(CalcError::NotVariable(8..9)),
+            derivative("diff(x, 2)", &ctx)
+        );
+    }
+}
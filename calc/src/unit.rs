@@ -0,0 +1,357 @@
+use crate::number::{Number, NumberMode};
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// 基本次元の指数（長さ、質量、時間、電流、温度、物質量、情報量）
+type Dimension = [i32; 7];
+
+const NONE: Dimension = [0, 0, 0, 0, 0, 0, 0];
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const INFORMATION: Dimension = [0, 0, 0, 0, 0, 0, 1];
+
+/// 組み込みの単位
+///
+/// 値 x の単位は SI基本単位で (x + offset) * factor になる。factorとoffsetは 5/9 のような分数も書ける。
+struct UnitDef {
+    name: &'static str,
+    factor: &'static str,
+    offset: &'static str, // 0以外は温度（degC、degF）だけ
+    dimension: Dimension,
+}
+
+const fn unit(name: &'static str, factor: &'static str, dimension: Dimension) -> UnitDef {
+    UnitDef {
+        name,
+        factor,
+        offset: "0",
+        dimension,
+    }
+}
+
+const UNITS: &[UnitDef] = &[
+    // 長さ・面積・体積（in は変換の演算子のため、インチは inch）
+    unit("m", "1", LENGTH),
+    unit("km", "1000", LENGTH),
+    unit("cm", "0.01", LENGTH),
+    unit("mm", "0.001", LENGTH),
+    unit("um", "0.000001", LENGTH),
+    unit("nm", "0.000000001", LENGTH),
+    unit("inch", "0.0254", LENGTH),
+    unit("ft", "0.3048", LENGTH),
+    unit("yd", "0.9144", LENGTH),
+    unit("mi", "1609.344", LENGTH),
+    unit("nmi", "1852", LENGTH),
+    unit("ha", "10000", AREA),
+    unit("acre", "4046.8564224", AREA),
+    unit("L", "0.001", VOLUME),
+    unit("mL", "0.000001", VOLUME),
+    // 質量
+    unit("kg", "1", MASS),
+    unit("g", "0.001", MASS),
+    unit("mg", "0.000001", MASS),
+    unit("t", "1000", MASS),
+    unit("lb", "0.45359237", MASS),
+    unit("oz", "0.028349523125", MASS),
+    // 時間・速さ・周波数
+    unit("s", "1", TIME),
+    unit("ms", "0.001", TIME),
+    unit("us", "0.000001", TIME),
+    unit("ns", "0.000000001", TIME),
+    unit("min", "60", TIME),
+    unit("h", "3600", TIME),
+    unit("day", "86400", TIME),
+    unit("week", "604800", TIME),
+    unit("yr", "31557600", TIME),
+    unit("mph", "0.44704", SPEED),
+    unit("knot", "1852/3600", SPEED),
+    unit("Hz", "1", FREQUENCY),
+    unit("kHz", "1000", FREQUENCY),
+    unit("MHz", "1000000", FREQUENCY),
+    unit("GHz", "1000000000", FREQUENCY),
+    // 力・エネルギー・仕事率・圧力
+    unit("N", "1", FORCE),
+    unit("kN", "1000", FORCE),
+    unit("lbf", "4.4482216152605", FORCE),
+    unit("J", "1", ENERGY),
+    unit("kJ", "1000", ENERGY),
+    unit("cal", "4.184", ENERGY),
+    unit("kcal", "4184", ENERGY),
+    unit("Wh", "3600", ENERGY),
+    unit("kWh", "3600000", ENERGY),
+    unit("W", "1", POWER),
+    unit("kW", "1000", POWER),
+    unit("MW", "1000000", POWER),
+    unit("Pa", "1", PRESSURE),
+    unit("kPa", "1000", PRESSURE),
+    unit("bar", "100000", PRESSURE),
+    unit("atm", "101325", PRESSURE),
+    unit("psi", "4.4482216152605/0.00064516", PRESSURE),
+    // 電気
+    unit("A", "1", CURRENT),
+    unit("mA", "0.001", CURRENT),
+    unit("V", "1", VOLTAGE),
+    // 温度・物質量
+    unit("K", "1", TEMPERATURE),
+    UnitDef {
+        name: "degC",
+        factor: "1",
+        offset: "273.15",
+        dimension: TEMPERATURE,
+    },
+    UnitDef {
+        name: "degF",
+        factor: "5/9",
+        offset: "459.67",
+        dimension: TEMPERATURE,
+    },
+    unit("mol", "1", AMOUNT),
+    // 情報量（KB は1000バイト、KiB は1024バイト）
+    unit("bit", "1", INFORMATION),
+    unit("kbit", "1000", INFORMATION),
+    unit("Mbit", "1000000", INFORMATION),
+    unit("Gbit", "1000000000", INFORMATION),
+    unit("B", "8", INFORMATION),
+    unit("KB", "8000", INFORMATION),
+    unit("MB", "8000000", INFORMATION),
+    unit("GB", "8000000000", INFORMATION),
+    unit("TB", "8000000000000", INFORMATION),
+    unit("KiB", "8192", INFORMATION),
+    unit("MiB", "8388608", INFORMATION),
+    unit("GiB", "8589934592", INFORMATION),
+    unit("TiB", "8796093022208", INFORMATION),
+];
+
+fn definition(name: &str) -> Option<&'static UnitDef> {
+    UNITS.iter().find(|def| def.name == name)
+}
+
+/// 定義の数値（"0.0254"、"5/9"）を有理数として読む
+fn ratio(text: &str) -> BigRational {
+    let decimal = |text: &str| match Number::parse(text, NumberMode::Rational) {
+        Ok(Number::Rational(r)) => r,
+        _ => unreachable!("invalid unit definition {}", text),
+    };
+    match text.split_once('/') {
+        Some((numer, denom)) => decimal(numer) / decimal(denom),
+        None => decimal(text),
+    }
+}
+
+/// 単位の積（km/h は km^1 h^-1）。空であれば単位なし
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Unit {
+    terms: BTreeMap<&'static str, i32>,
+}
+
+impl Unit {
+    /// 組み込みの単位
+    pub fn named(name: &str) -> Option<Self> {
+        let def = definition(name)?;
+        Some(Self {
+            terms: BTreeMap::from([(def.name, 1)]),
+        })
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    fn dimension(&self) -> Dimension {
+        let mut dimension = NONE;
+        for (name, power) in &self.terms {
+            for (d, base) in dimension
+                .iter_mut()
+                .zip(definition(name).unwrap().dimension)
+            {
+                *d += base * power;
+            }
+        }
+        dimension
+    }
+
+    /// km/m のように打ち消し合って次元がない
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimension() == NONE
+    }
+
+    pub fn same_dimension(&self, other: &Unit) -> bool {
+        self.dimension() == other.dimension()
+    }
+
+    /// SI基本単位での大きさ（km は 1000）
+    fn factor(&self) -> BigRational {
+        self.terms
+            .iter()
+            .map(|(name, power)| ratio(definition(name).unwrap().factor).pow(*power))
+            .product()
+    }
+
+    /// 同じ次元のotherへの換算の比率（km から m は 1000）。単位なしへの比率はSI基本単位での大きさ
+    pub fn ratio_to(&self, other: &Unit) -> BigRational {
+        self.factor() / other.factor()
+    }
+
+    /// 温度だけの単位（K、degC、degF）であれば、その定義
+    fn temperature(&self) -> Option<&'static UnitDef> {
+        match self.terms.iter().collect::<Vec<_>>()[..] {
+            [(name, 1)] => definition(name).filter(|def| def.dimension == TEMPERATURE),
+            _ => None,
+        }
+    }
+
+    /// 温度の原点の違いも含めて other に換算する x * ratio + offset の (ratio, offset)
+    ///
+    /// 温度どうし以外は原点の違いはなく、offsetは0になる。
+    pub fn conversion_to(&self, other: &Unit) -> (BigRational, BigRational) {
+        let scale = self.ratio_to(other);
+        match (self.temperature(), other.temperature()) {
+            (Some(from), Some(to)) => {
+                let offset = ratio(from.offset) * &scale - ratio(to.offset);
+                (scale, offset)
+            }
+            _ => (scale, BigRational::zero()),
+        }
+    }
+
+    /// self * other^sign（signは掛け算で1、割り算で-1）と、数の部分に掛ける比率
+    ///
+    /// selfに同じ次元の別の単位があれば、otherの単位をそちらに換算する（60 km/h * 30 min は h にそろえる）。
+    pub fn combine(&self, other: &Unit, sign: i32) -> (Unit, BigRational) {
+        let mut terms = self.terms.clone();
+        let mut scale = BigRational::one();
+        for (&name, &power) in &other.terms {
+            let power = power * sign;
+            let dimension = definition(name).unwrap().dimension;
+            let same = self
+                .terms
+                .keys()
+                .find(|&&n| n != name && definition(n).unwrap().dimension == dimension);
+            let name = match same {
+                Some(&existing) => {
+                    let factor = |n| ratio(definition(n).unwrap().factor);
+                    scale *= (factor(name) / factor(existing)).pow(power);
+                    existing
+                }
+                None => name,
+            };
+            let entry = terms.entry(name).or_insert(0);
+            *entry += power;
+            if *entry == 0 {
+                terms.remove(name);
+            }
+        }
+        (Unit { terms }, scale)
+    }
+
+    /// self^power
+    pub fn pow(&self, power: i32) -> Unit {
+        Unit {
+            terms: self
+                .terms
+                .iter()
+                .map(|(&name, &p)| (name, p * power))
+                .filter(|&(_, p)| p != 0)
+                .collect(),
+        }
+    }
+
+    /// Displayの表記（kg*m/s^2、s^-1）を読む
+    pub fn parse(text: &str) -> Option<Self> {
+        let (numer, denom) = text.split_once('/').unwrap_or((text, ""));
+        let denom = denom.trim_start_matches('(').trim_end_matches(')');
+        let mut unit = Unit::default();
+        for (part, sign) in [(numer, 1), (denom, -1)] {
+            for term in part.split('*').filter(|term| !term.is_empty()) {
+                let (name, power) = match term.split_once('^') {
+                    Some((name, power)) => (name, power.parse::<i32>().ok()?),
+                    None => (term, 1),
+                };
+                let def = definition(name)?;
+                *unit.terms.entry(def.name).or_insert(0) += power * sign;
+            }
+        }
+        unit.terms.retain(|_, power| *power != 0);
+        (!unit.is_none()).then_some(unit)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let term = |name: &str, power: i32| match power {
+            1 => name.to_string(),
+            _ => format!("{}^{}", name, power),
+        };
+        let numer: Vec<_> = self
+            .terms
+            .iter()
+            .filter(|(_, &p)| p > 0)
+            .map(|(name, &p)| term(name, p))
+            .collect();
+        // 分子がなければ負の指数で書く（s^-1）
+        if numer.is_empty() {
+            let terms: Vec<_> = self.terms.iter().map(|(name, &p)| term(name, p)).collect();
+            return write!(f, "{}", terms.join("*"));
+        }
+        let denom: Vec<_> = self
+            .terms
+            .iter()
+            .filter(|(_, &p)| p < 0)
+            .map(|(name, &p)| term(name, -p))
+            .collect();
+        write!(f, "{}", numer.join("*"))?;
+        match denom.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", denom[0]),
+            _ => write!(f, "/({})", denom.join("*")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Unit;
+
+    fn unit(text: &str) -> Unit {
+        Unit::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_display() {
+        for text in ["km/h", "kg*m/s^2", "m/(kg*s)", "s^-1", "m^2*s^-1"] {
+            let parsed = unit(text);
+            assert_eq!(parsed, unit(&parsed.to_string()));
+        }
+        assert_eq!("m/s", unit("m^2*s^-1/m").to_string());
+        assert_eq!("s^-1", unit("s^-1").to_string());
+        assert_eq!("m/(kg*s)", unit("m/(s*kg)").to_string());
+        assert_eq!(None, Unit::parse("furlong"));
+        assert_eq!(None, Unit::parse("m/m"));
+    }
+
+    #[test]
+    fn test_combine() {
+        let (result, scale) = unit("km/h").combine(&unit("min"), 1);
+        assert_eq!("km", result.to_string());
+        assert_eq!("1/60", scale.to_string());
+        assert!(unit("N*m").same_dimension(&unit("J")));
+        assert!(!unit("N").same_dimension(&unit("J")));
+        assert_eq!("1000", unit("km").ratio_to(&unit("m")).to_string());
+        assert_eq!("131072/125", unit("MiB").ratio_to(&unit("KB")).to_string());
+    }
+}
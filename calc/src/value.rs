@@ -0,0 +1,531 @@
+use crate::functions::AngleMode;
+use crate::matrix::Matrix;
+use crate::number::{ArithmeticError, Number, NumberMode};
+use crate::unit::Unit;
+use num_traits::{One, Zero};
+use std::convert::Infallible;
+use std::fmt;
+
+/// 評価の結果。数（単位つきのこともある）、ベクトル、行列のいずれか
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(Quantity),
+    Vector(Vec<Quantity>),
+    Matrix(Matrix),
+}
+
+/// 単位つきの数（5 km）。単位なしの数は空の単位を持つ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub number: Number,
+    pub unit: Unit,
+}
+
+/// 単位つきの演算の失敗
+#[derive(Debug, PartialEq)]
+pub enum ValueError {
+    Arithmetic(ArithmeticError),
+    IncompatibleUnits(Unit, Unit), // 次元の違う単位の加減算・変換
+    UnitNotAllowed,                // 単位のある値をビット演算や指数などに使った
+    UnitPower,                     // 単位のある値の整数でない指数
+    NotScalar,                     // 数が必要な所にベクトルや行列
+    Shape(String),                 // ベクトル・行列の形が合わない（説明）
+    Singular,                      // 逆行列のない行列
+}
+
+impl From<ArithmeticError> for ValueError {
+    fn from(e: ArithmeticError) -> Self {
+        ValueError::Arithmetic(e)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::new(Number::Float(value))
+    }
+}
+
+impl Value {
+    /// 単位なしの数
+    pub fn new(number: Number) -> Self {
+        Value::Scalar(Quantity::new(number))
+    }
+
+    /// 数（単位つきでもよい）。ベクトルや行列であればエラー
+    pub fn scalar(&self) -> Result<&Quantity, ValueError> {
+        match self {
+            Value::Scalar(quantity) => Ok(quantity),
+            _ => Err(ValueError::NotScalar),
+        }
+    }
+
+    /// 単位なしの数。単位があるか、ベクトルや行列であればエラー
+    pub fn plain(&self) -> Result<&Number, ValueError> {
+        self.scalar()?.plain()
+    }
+
+    /// 単位なしの実数であればf64にした値
+    pub fn to_f64(&self) -> Option<f64> {
+        match self.plain() {
+            Ok(Number::Complex(_)) | Err(_) => None,
+            Ok(number) => Some(number.to_f64()),
+        }
+    }
+
+    /// エラー表示に使う形の説明（scalar、vector of 3、2x3 matrix）
+    pub fn shape(&self) -> String {
+        match self {
+            Value::Scalar(_) => "scalar".to_string(),
+            Value::Vector(elements) => format!("vector of {}", elements.len()),
+            Value::Matrix(matrix) => matrix.shape(),
+        }
+    }
+
+    pub fn to_mode(&self, mode: NumberMode) -> Self {
+        let Ok(value) = self.map(|q| Ok::<_, Infallible>(q.to_mode(mode)));
+        value
+    }
+
+    /// 要素ごとにopを適用する
+    pub fn map<E>(&self, mut op: impl FnMut(&Quantity) -> Result<Quantity, E>) -> Result<Self, E> {
+        Ok(match self {
+            Value::Scalar(q) => Value::Scalar(op(q)?),
+            Value::Vector(elements) => {
+                Value::Vector(elements.iter().map(op).collect::<Result<_, _>>()?)
+            }
+            Value::Matrix(matrix) => Value::Matrix(matrix.map(op)?),
+        })
+    }
+
+    /// 同じ形の値の要素どうしにopを適用する。一方が数であれば、もう一方のすべての要素と組み合わせる
+    fn zip(
+        &self,
+        other: &Self,
+        op: impl Fn(&Quantity, &Quantity) -> Result<Quantity, ValueError>,
+    ) -> Result<Self, ValueError> {
+        match (self, other) {
+            (Value::Scalar(a), _) => other.map(|b| op(a, b)),
+            (_, Value::Scalar(b)) => self.map(|a| op(a, b)),
+            (Value::Vector(a), Value::Vector(b)) if a.len() == b.len() => Ok(Value::Vector(
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| op(a, b))
+                    .collect::<Result<_, _>>()?,
+            )),
+            (Value::Matrix(a), Value::Matrix(b)) if a.shape() == b.shape() => {
+                Ok(Value::Matrix(a.zip(b, op)?))
+            }
+            _ => Err(self.mismatch(other)),
+        }
+    }
+
+    /// selfとotherの形が合わないエラー
+    pub fn mismatch(&self, other: &Self) -> ValueError {
+        ValueError::Shape(format!("{} and {}", self.shape(), other.shape()))
+    }
+
+    /// 要素ごとに換算する
+    pub fn convert(&self, unit: &Unit) -> Result<Self, ValueError> {
+        self.map(|q| q.convert(unit))
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, ValueError> {
+        self.zip(other, Quantity::add)
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, ValueError> {
+        self.zip(other, Quantity::sub)
+    }
+
+    pub fn rem(&self, other: &Self) -> Result<Self, ValueError> {
+        self.zip(other, Quantity::rem)
+    }
+
+    /// 掛け算。行列どうし、行列とベクトルは行列の積（ベクトルは行列の右では列、左では行とみなす）、
+    /// それ以外は要素ごとの積
+    pub fn mul(&self, other: &Self) -> Result<Self, ValueError> {
+        match (self, other) {
+            (Value::Matrix(a), Value::Matrix(b)) => a.mul(b).map(Value::Matrix),
+            (Value::Matrix(a), Value::Vector(v)) => a.mul_vector(v).map(Value::Vector),
+            (Value::Vector(v), Value::Matrix(a)) => a.transpose().mul_vector(v).map(Value::Vector),
+            _ => self.zip(other, Quantity::mul),
+        }
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, ValueError> {
+        self.zip(other, Quantity::div)
+    }
+
+    pub fn floor_div(&self, other: &Self) -> Result<Self, ValueError> {
+        self.zip(other, Quantity::floor_div)
+    }
+
+    pub fn neg(&self) -> Result<Self, ValueError> {
+        self.map(Quantity::neg)
+    }
+
+    /// 要素ごとのべき乗
+    pub fn pow(&self, exponent: &Self, mode: NumberMode) -> Result<Self, ValueError> {
+        self.zip(exponent, |base, exponent| base.pow(exponent, mode))
+    }
+
+    /// 要素をquantityで文字列にする（[1, 2]、[[1, 2], [3, 4]]）
+    fn format_with(&self, quantity: &impl Fn(&Quantity) -> String) -> String {
+        let list = |elements: &[Quantity]| {
+            let elements: Vec<_> = elements.iter().map(quantity).collect();
+            format!("[{}]", elements.join(", "))
+        };
+        match self {
+            Value::Scalar(q) => quantity(q),
+            Value::Vector(elements) => list(elements),
+            Value::Matrix(matrix) => {
+                let rows: Vec<_> = matrix.rows().map(list).collect();
+                format!("[{}]", rows.join(", "))
+            }
+        }
+    }
+
+    /// 要素の数の部分をnumberで文字列にして表示する
+    pub fn format(&self, number: impl Fn(&Number) -> String) -> String {
+        self.format_with(&|q: &Quantity| q.format(&number))
+    }
+
+    /// 基数radixで表示する。polarがあれば複素数をその角度の単位の極形式（r∠θ）で表示する
+    pub fn to_string_radix(&self, radix: u32, polar: Option<AngleMode>) -> String {
+        self.format(|number| match (number, polar) {
+            (Number::Complex(_), Some(mode)) => number.to_polar_string(mode == AngleMode::Degrees),
+            _ => number.to_string_radix(radix),
+        })
+    }
+
+    /// 保存用の文字列（float:5.3@km、[float:1.0,float:2.0]）
+    pub fn encode(&self) -> String {
+        self.format_with(&Quantity::encode).replace(", ", ",")
+    }
+
+    pub fn decode(text: &str) -> Option<Self> {
+        let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) else {
+            return Quantity::decode(text).map(Value::Scalar);
+        };
+        if !inner.starts_with('[') {
+            let elements = inner
+                .split(',')
+                .map(Quantity::decode)
+                .collect::<Option<_>>()?;
+            return Some(Value::Vector(elements));
+        }
+        let rows = inner
+            .strip_prefix('[')?
+            .strip_suffix(']')?
+            .split("],[")
+            .map(|row| row.split(',').map(Quantity::decode).collect())
+            .collect::<Option<_>>()?;
+        Matrix::from_rows(rows).ok().map(Value::Matrix)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(Number::to_string))
+    }
+}
+
+impl Quantity {
+    /// 単位なしの数
+    pub fn new(number: Number) -> Self {
+        Self {
+            number,
+            unit: Unit::default(),
+        }
+    }
+
+    /// 単位なしの数。単位があればエラー
+    pub fn plain(&self) -> Result<&Number, ValueError> {
+        if self.unit.is_none() {
+            Ok(&self.number)
+        } else {
+            Err(ValueError::UnitNotAllowed)
+        }
+    }
+
+    pub fn to_mode(&self, mode: NumberMode) -> Self {
+        Self {
+            number: self.number.to_mode(mode),
+            unit: self.unit.clone(),
+        }
+    }
+
+    /// 同じ次元の単位unitに換算する。温度どうしは原点の違いも換算する（0 degC to K は 273.15 K）
+    pub fn convert(&self, unit: &Unit) -> Result<Self, ValueError> {
+        if self.unit == *unit {
+            return Ok(self.clone());
+        }
+        if !self.unit.same_dimension(unit) {
+            return Err(ValueError::IncompatibleUnits(
+                self.unit.clone(),
+                unit.clone(),
+            ));
+        }
+        let (ratio, offset) = self.unit.conversion_to(unit);
+        Ok(Self {
+            number: self.number.affine(&ratio, &offset)?,
+            unit: unit.clone(),
+        })
+    }
+
+    /// 加減算などで、otherをselfの単位にそろえた数
+    ///
+    /// 温度は差として扱い、原点の違いは換算しない（10 degC + 5 K は 15 degC）。
+    fn aligned(&self, other: &Self) -> Result<Number, ValueError> {
+        if self.unit == other.unit {
+            return Ok(other.number.clone());
+        }
+        if !self.unit.same_dimension(&other.unit) {
+            return Err(ValueError::IncompatibleUnits(
+                self.unit.clone(),
+                other.unit.clone(),
+            ));
+        }
+        let ratio = other.unit.ratio_to(&self.unit);
+        Ok(other.number.affine(&ratio, &Zero::zero())?)
+    }
+
+    /// 結果の単位はselfの単位になる（5 km + 300 m は 5.3 km）
+    pub fn add(&self, other: &Self) -> Result<Self, ValueError> {
+        let number = self.number.add(&self.aligned(other)?)?;
+        Ok(self.with_number(number))
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, ValueError> {
+        let number = self.number.sub(&self.aligned(other)?)?;
+        Ok(self.with_number(number))
+    }
+
+    pub fn rem(&self, other: &Self) -> Result<Self, ValueError> {
+        let number = self.number.rem(&self.aligned(other)?)?;
+        Ok(self.with_number(number))
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, ValueError> {
+        self.combine(other, self.number.mul(&other.number)?, 1)
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, ValueError> {
+        self.combine(other, self.number.div(&other.number)?, -1)
+    }
+
+    /// 切り捨て除算。単位があれば割り算の結果を切り捨てる
+    pub fn floor_div(&self, other: &Self) -> Result<Self, ValueError> {
+        if self.unit.is_none() && other.unit.is_none() {
+            return Ok(Self::new(self.number.floor_div(&other.number)?));
+        }
+        let quotient = self.div(other)?;
+        let number = quotient.number.map_exact(f64::floor, |r| r.floor())?;
+        Ok(quotient.with_number(number))
+    }
+
+    pub fn neg(&self) -> Result<Self, ValueError> {
+        Ok(self.with_number(self.number.neg()?))
+    }
+
+    /// べき乗。単位のある値の指数は単位なしの整数であること（(3 m)^2 は 9 m^2）
+    pub fn pow(&self, exponent: &Self, mode: NumberMode) -> Result<Self, ValueError> {
+        let exponent = exponent.plain()?;
+        if self.unit.is_none() {
+            return Ok(Self::new(self.number.pow(exponent, mode)?));
+        }
+        let power = exponent.to_f64();
+        if power.fract() != 0.0 || power.abs() > i32::MAX as f64 {
+            return Err(ValueError::UnitPower);
+        }
+        Ok(Self {
+            number: self.number.pow(exponent, mode)?,
+            unit: self.unit.pow(power as i32),
+        })
+    }
+
+    /// 数の部分numberと、selfとotherの単位の積（signが-1であれば商）
+    ///
+    /// 次元がなくなれば（km/m）単位なしの数にする。
+    fn combine(&self, other: &Self, number: Number, sign: i32) -> Result<Self, ValueError> {
+        let (unit, ratio) = self.unit.combine(&other.unit, sign);
+        let zero = Zero::zero();
+        if !unit.is_none() && unit.is_dimensionless() {
+            let ratio = ratio * unit.ratio_to(&Unit::default());
+            return Ok(Self::new(number.affine(&ratio, &zero)?));
+        }
+        let number = if ratio.is_one() {
+            number
+        } else {
+            number.affine(&ratio, &zero)?
+        };
+        Ok(Self { number, unit })
+    }
+
+    fn with_number(&self, number: Number) -> Self {
+        Self {
+            number,
+            unit: self.unit.clone(),
+        }
+    }
+
+    /// 数の部分をnumberで文字列にして、単位をつけて表示する
+    pub fn format(&self, number: impl Fn(&Number) -> String) -> String {
+        let number = number(&self.number);
+        if self.unit.is_none() {
+            number
+        } else {
+            format!("{} {}", number, self.unit)
+        }
+    }
+
+    /// 保存用の文字列（float:5.3@km）
+    pub fn encode(&self) -> String {
+        if self.unit.is_none() {
+            self.number.encode()
+        } else {
+            format!("{}@{}", self.number.encode(), self.unit)
+        }
+    }
+
+    pub fn decode(text: &str) -> Option<Self> {
+        match text.split_once('@') {
+            Some((number, unit)) => Some(Self {
+                number: Number::decode(number)?,
+                unit: Unit::parse(unit)?,
+            }),
+            None => Number::decode(text).map(Self::new),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(Number::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Quantity, Value, ValueError};
+    use crate::number::{Number, NumberMode};
+    use crate::unit::Unit;
+
+    fn value(number: f64, unit: &str) -> Quantity {
+        Quantity {
+            number: Number::Float(number),
+            unit: Unit::parse(unit).unwrap(),
+        }
+    }
+
+    fn plain(number: f64) -> Quantity {
+        Quantity::new(Number::Float(number))
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            "5.3 km",
+            value(5.0, "km")
+                .add(&value(300.0, "m"))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "120 km",
+            value(60.0, "km/h")
+                .mul(&value(2.0, "h"))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "30 km",
+            value(60.0, "km/h")
+                .mul(&value(30.0, "min"))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "50",
+            value(5.0, "km")
+                .div(&value(100.0, "m"))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "16 m^2",
+            value(4.0, "m").mul(&value(4.0, "m")).unwrap().to_string()
+        );
+        assert_eq!(
+            Err(ValueError::IncompatibleUnits(
+                Unit::parse("km").unwrap(),
+                Unit::parse("h").unwrap()
+            )),
+            value(5.0, "km").add(&value(1.0, "h"))
+        );
+        let two = Quantity::new(Number::Float(2.0));
+        let half = Quantity::new(Number::Float(0.5));
+        let mode = NumberMode::Float;
+        assert_eq!(
+            "9 m^2",
+            value(3.0, "m").pow(&two, mode).unwrap().to_string()
+        );
+        assert_eq!(Err(ValueError::UnitPower), value(3.0, "m").pow(&half, mode));
+        assert_eq!(
+            Err(ValueError::UnitNotAllowed),
+            two.pow(&value(1.0, "m"), mode)
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let convert = |v: Quantity, unit: &str| v.convert(&Unit::parse(unit).unwrap()).unwrap();
+        assert_eq!("3145.728 KB", convert(value(3.0, "MiB"), "KB").to_string());
+        assert_eq!(
+            "212 degF",
+            convert(value(100.0, "degC"), "degF").to_string()
+        );
+        assert_eq!("273.15 K", convert(value(0.0, "degC"), "K").to_string());
+
+        // 有理数・10進数のモードでは誤差なく換算する
+        let miles = Quantity {
+            number: Number::parse("1", NumberMode::Decimal(3)).unwrap(),
+            unit: Unit::parse("mi").unwrap(),
+        };
+        assert_eq!("1.609 km", convert(miles, "km").to_string());
+    }
+
+    #[test]
+    fn test_encode() {
+        let distance = value(5.3, "km/h");
+        assert_eq!("float:5.3@km/h", distance.encode());
+        assert_eq!(Some(distance.clone()), Quantity::decode(&distance.encode()));
+        assert_eq!(None, Quantity::decode("float:1@furlong"));
+
+        let vector = Value::Vector(vec![plain(1.0), value(2.5, "m")]);
+        assert_eq!("[float:1.0,float:2.5@m]", vector.encode());
+        assert_eq!(Some(vector.clone()), Value::decode(&vector.encode()));
+        let matrix = "[[float:1.0,float:2.0],[float:3.0,float:4.0]]";
+        assert_eq!(matrix, Value::decode(matrix).unwrap().encode());
+        assert_eq!(None, Value::decode("[[float:1.0],[float:3.0,float:4.0]]"));
+    }
+
+    #[test]
+    fn test_elementwise() {
+        let vector = |numbers: &[f64]| Value::Vector(numbers.iter().map(|&x| plain(x)).collect());
+        let a = vector(&[1.0, 2.0, 3.0]);
+        let two = Value::new(Number::Float(2.0));
+        assert_eq!("[2, 4, 6]", a.add(&a).unwrap().to_string());
+        assert_eq!("[2, 4, 6]", two.mul(&a).unwrap().to_string());
+        assert_eq!(
+            "[1, 4, 9]",
+            a.pow(&two, NumberMode::Float).unwrap().to_string()
+        );
+        assert_eq!("[-1, 0, 1]", a.sub(&two).unwrap().to_string());
+        assert_eq!(
+            Err(ValueError::Shape("vector of 3 and vector of 2".to_string())),
+            a.add(&vector(&[1.0, 2.0]))
+        );
+        assert_eq!(Err(ValueError::NotScalar), a.plain());
+    }
+}
@@ -0,0 +1,211 @@
+//! 正規表現エンジン
+mod codegen;
+pub mod dfa;
+pub mod evaluator;
+pub mod parser;
+mod pikevm;
+pub mod serialize;
+
+use crate::helper::DynError;
+use parser::{CharClass, AST};
+use serialize::Kind;
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+/// 命令列を構成する命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Char(char),
+    Match,
+    Jump(usize),
+    Split(usize, usize),
+    Class(CharClass), // 文字クラスに含まれる1文字
+    AssertStart,      // 行頭であれば次へ進む
+    AssertEnd,        // 行末であれば次へ進む
+}
+
+impl Instruction {
+    /// 1文字を消費する命令であれば、文字コードcにマッチするか判定
+    pub fn matches(&self, c: u32) -> Option<bool> {
+        match self {
+            Instruction::Char(ch) => Some(*ch as u32 == c),
+            Instruction::Class(class) => Some(class.contains(c)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Char(c) => write!(f, "char {}", c),
+            Instruction::Match => write!(f, "match"),
+            Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
+            Instruction::Split(addr1, addr2) => write!(f, "split {:>04}, {:>04}", addr1, addr2),
+            Instruction::Class(class) => {
+                write!(f, "class {}", if class.negated { "^" } else { "" })?;
+                for (lo, hi) in &class.ranges {
+                    write!(f, "{}-{}", lo, hi)?;
+                }
+                Ok(())
+            }
+            Instruction::AssertStart => write!(f, "assert start"),
+            Instruction::AssertEnd => write!(f, "assert end"),
+        }
+    }
+}
+
+/// マッチングに用いる評価器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Backtrack, // バックトラック法
+    PikeVm,    // Pike VM
+    Dfa,       // DFA
+}
+
+/// コンパイル済みの正規表現
+#[derive(Debug, Clone)]
+pub struct Regex {
+    code: Vec<Instruction>,
+}
+
+impl Regex {
+    /// 正規表現をパースし、命令列にコンパイル
+    pub fn new(expr: &str) -> Result<Self, DynError> {
+        let ast = parser::parse(expr)?;
+        Self::from_ast(&ast)
+    }
+
+    /// ASTを命令列にコンパイル
+    ///
+    /// globなど、正規表現以外の構文から作ったASTもこのエンジンで実行できる。
+    pub fn from_ast(ast: &AST) -> Result<Self, DynError> {
+        let code = codegen::get_code(ast)?;
+        Ok(Self { code })
+    }
+
+    /// コンパイル済みの命令列
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    /// 命令列をバージョン付きのバイナリ形式に変換
+    ///
+    /// 起動時に多数のパターンをパースし直す代わりに、保存しておいたものを読み込める。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize::wrap(Kind::Program, &serialize::write_program(&self.code))
+    }
+
+    /// to_bytesで変換したバイト列から復元
+    ///
+    /// 形式のバージョンとチェックサムを検査し、一致しない場合はエラーを返す。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DynError> {
+        let payload = serialize::unwrap(Kind::Program, bytes)?;
+        let code = serialize::read_program(payload)?;
+        Ok(Self { code })
+    }
+
+    /// 行中にマッチする箇所があるか判定
+    pub fn is_match(&self, line: &str) -> Result<bool, DynError> {
+        Ok(self.find(line)?.is_some())
+    }
+
+    /// 行中で最も左にあるマッチを探し、その範囲をバイト位置で返す
+    pub fn find(&self, line: &str) -> Result<Option<Range<usize>>, DynError> {
+        self.find_with(Engine::Backtrack, line)
+    }
+
+    /// 評価器を指定してfindを行う
+    ///
+    /// どの評価器でも同じマッチを返す。Engine::Dfaは呼び出しごとにDFAを構築するため、
+    /// 繰り返し使う場合はdfa::Dfa::newで構築したものを使い回すこと。
+    pub fn find_with(&self, engine: Engine, line: &str) -> Result<Option<Range<usize>>, DynError> {
+        let chars: Vec<char> = line.chars().collect();
+        let span = match engine {
+            Engine::Backtrack => evaluator::eval(&self.code, &chars)?,
+            Engine::PikeVm => pikevm::eval(&self.code, &chars)?,
+            Engine::Dfa => dfa::Dfa::new(&self.code)?.find(&chars),
+        };
+        Ok(span.map(|(start, end)| byte_offset(line, start)..byte_offset(line, end)))
+    }
+
+    /// 文字位置startから始まる最長のマッチを探し、その終了位置を文字単位で返す
+    pub(crate) fn longest_match_at(
+        &self,
+        chars: &[char],
+        start: usize,
+    ) -> Result<Option<usize>, DynError> {
+        Ok(pikevm::longest_at(&self.code, chars, start)?)
+    }
+}
+
+/// 文字単位の位置をバイト単位の位置に変換
+fn byte_offset(line: &str, char_pos: usize) -> usize {
+    line.char_indices()
+        .nth(char_pos)
+        .map_or(line.len(), |(i, _)| i)
+}
+
+/// 正規表現と文字列をマッチング
+///
+/// 例：do_matching("abc|(de|cd)+", "decddede")
+pub fn do_matching(expr: &str, line: &str) -> Result<bool, DynError> {
+    Regex::new(expr)?.is_match(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{do_matching, Regex};
+
+    #[test]
+    fn test_matching() {
+        // パースエラー
+        assert!(do_matching("+b", "bbb").is_err());
+        assert!(do_matching("*b", "bbb").is_err());
+        assert!(do_matching("|b", "bbb").is_err());
+        assert!(do_matching("?b", "bbb").is_err());
+        assert!(do_matching("(a", "a").is_err());
+        assert!(do_matching("a)", "a").is_err());
+        assert!(do_matching("[ab", "a").is_err());
+        assert!(do_matching("[z-a]", "a").is_err());
+
+        // パース成功、マッチ成功
+        assert!(do_matching("abc|def", "def").unwrap());
+        assert!(do_matching("(abc)*", "abcabc").unwrap());
+        assert!(do_matching("(ab|cd)+", "abcdcd").unwrap());
+        assert!(do_matching("abc?", "ab").unwrap());
+        assert!(do_matching("x", "abxcd").unwrap());
+        assert!(do_matching("[a-c]+d", "xbcad").unwrap());
+        assert!(do_matching("[^a-c]", "abx").unwrap());
+        assert!(do_matching("^a.c$", "abc").unwrap());
+        assert!(do_matching("[]]", "]").unwrap());
+        assert!(do_matching("\\.", ".").unwrap());
+
+        // パース成功、マッチ失敗
+        assert!(!do_matching("abc|def", "efa").unwrap());
+        assert!(!do_matching("(ab|cd)+", "").unwrap());
+        assert!(!do_matching("abc?", "acb").unwrap());
+        assert!(!do_matching("[^a-c]", "abc").unwrap());
+        assert!(!do_matching("^bc", "abc").unwrap());
+        assert!(!do_matching("ab$", "abc").unwrap());
+        assert!(!do_matching("\\.", "a").unwrap());
+    }
+
+    #[test]
+    fn test_find() {
+        let regex = Regex::new("b+c").unwrap();
+        assert_eq!(Some(1..4), regex.find("abbcd").unwrap());
+        assert_eq!(None, regex.find("abd").unwrap());
+
+        // マルチバイト文字はバイト位置で返す
+        let regex = Regex::new("い+").unwrap();
+        assert_eq!(Some(3..9), regex.find("あいいう").unwrap());
+
+        // 空文字列にマッチする繰り返しでも停止する
+        let regex = Regex::new("(a*)*b").unwrap();
+        assert_eq!(Some(0..4), regex.find("aaab").unwrap());
+        assert_eq!(None, regex.find("aaaa").unwrap());
+    }
+}
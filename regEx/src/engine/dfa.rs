@@ -0,0 +1,338 @@
+//! 命令列をDFA（決定性有限オートマトン）に変換し、マッチングを行う
+//!
+//! DFAの状態は、Pike VMのスレッドの並び（優先順位付きのプログラムカウンタの列）に対応する。
+//! Match命令より後ろのスレッドを切り捨てることで、バックトラック法と同じマッチを返す。
+use super::{
+    evaluator::EvalError,
+    serialize::{self, Kind, Reader, SerializeError, Writer},
+    Instruction,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// DFAの状態数の上限
+const MAX_STATES: usize = 10_000;
+
+/// 何にもマッチしない状態
+const DEAD: usize = 0;
+
+/// DFA構築時のエラーを表現するための型
+#[derive(Debug)]
+pub enum DfaError {
+    Eval(EvalError), // 不正な命令列
+    TooManyStates,   // 状態数が上限を超えた
+}
+
+impl Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DfaError::Eval(e) => write!(f, "{}", e),
+            DfaError::TooManyStates => write!(f, "DfaError: more than {} states", MAX_STATES),
+        }
+    }
+}
+
+impl Error for DfaError {}
+
+impl From<EvalError> for DfaError {
+    fn from(e: EvalError) -> Self {
+        DfaError::Eval(e)
+    }
+}
+
+/// 遷移表を持つDFA
+///
+/// 入力文字は、命令列で区別されない文字同士を同じクラスにまとめてから遷移表を引く。
+/// 行末の表明は入力を読み終えた時点で評価するため、状態ごとに行末での受理フラグを持つ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dfa {
+    boundaries: Vec<u32>,      // 文字クラスの境界（昇順）
+    is_match: Vec<bool>,       // 状態ごとの受理フラグ
+    accept_at_end: Vec<bool>,  // 状態ごとの行末での受理フラグ
+    table: Vec<usize>,         // 状態 * クラス数 + クラス => 次の状態
+    start: usize,              // 行の途中から始める場合の初期状態
+    start_line: usize,         // 行頭から始める場合の初期状態
+    empty_line_accepted: bool, // 空行の行頭（兼行末）で受理するか
+}
+
+/// pcからJump、Split、成立する表明をたどり、到達した命令を優先順位の順にthreadsへ追加
+///
+/// at_endがfalseの場合、行末の表明は成否を保留してthreadsに残す。
+fn add_thread(
+    inst: &[Instruction],
+    threads: &mut Vec<usize>,
+    pc: usize,
+    at_start: bool,
+    at_end: bool,
+) -> Result<(), EvalError> {
+    let mut stack = vec![pc];
+    while let Some(pc) = stack.pop() {
+        if threads.contains(&pc) {
+            continue;
+        }
+        threads.push(pc);
+        match inst.get(pc).ok_or(EvalError::InvalidPC)? {
+            Instruction::Jump(addr) => stack.push(*addr),
+            Instruction::Split(addr1, addr2) => {
+                stack.push(*addr2);
+                stack.push(*addr1);
+            }
+            Instruction::AssertStart if at_start => stack.push(pc + 1),
+            Instruction::AssertEnd if at_end => stack.push(pc + 1),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// 文字を消費する命令、Match、保留中の行末の表明だけを残し、Match以降を切り捨てたスレッドの並び
+fn normalize(inst: &[Instruction], threads: Vec<usize>) -> Vec<usize> {
+    let mut result = Vec::new();
+    for pc in threads {
+        match inst[pc] {
+            Instruction::Char(_) | Instruction::Class(_) | Instruction::AssertEnd => {
+                result.push(pc)
+            }
+            Instruction::Match => {
+                result.push(pc);
+                break;
+            }
+            _ => (),
+        }
+    }
+    result
+}
+
+/// 入力を読み終えた時点で、スレッドの並びがマッチに到達するか判定
+fn accepts_at_end(
+    inst: &[Instruction],
+    threads: &[usize],
+    at_start: bool,
+) -> Result<bool, EvalError> {
+    let mut reached = Vec::new();
+    for &pc in threads {
+        add_thread(inst, &mut reached, pc, at_start, true)?;
+    }
+    Ok(reached.iter().any(|pc| inst[*pc] == Instruction::Match))
+}
+
+/// 文字を消費する命令が区別する文字の境界を求める
+fn char_boundaries(inst: &[Instruction]) -> Vec<u32> {
+    let mut boundaries = Vec::new();
+    for i in inst {
+        match i {
+            Instruction::Char(c) => {
+                boundaries.push(*c as u32);
+                boundaries.push(*c as u32 + 1);
+            }
+            Instruction::Class(class) => {
+                for (lo, hi) in &class.ranges {
+                    boundaries.push(*lo as u32);
+                    boundaries.push(*hi as u32 + 1);
+                }
+            }
+            _ => (),
+        }
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+impl Dfa {
+    /// 命令列からDFAを構築
+    pub fn new(inst: &[Instruction]) -> Result<Self, DfaError> {
+        let boundaries = char_boundaries(inst);
+        let num_classes = boundaries.len() + 1;
+
+        // 状態0は空のスレッド列（DEAD）
+        let mut states: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::from([(Vec::new(), DEAD)]);
+        let mut intern = |states: &mut Vec<Vec<usize>>, threads: Vec<usize>| {
+            if let Some(id) = ids.get(&threads) {
+                return Ok(*id);
+            }
+            if states.len() >= MAX_STATES {
+                return Err(DfaError::TooManyStates);
+            }
+            let id = states.len();
+            ids.insert(threads.clone(), id);
+            states.push(threads);
+            Ok(id)
+        };
+
+        let mut threads = Vec::new();
+        add_thread(inst, &mut threads, 0, false, false)?;
+        let start = intern(&mut states, normalize(inst, threads))?;
+
+        let mut threads = Vec::new();
+        add_thread(inst, &mut threads, 0, true, false)?;
+        let line_threads = normalize(inst, threads);
+        let empty_line_accepted = accepts_at_end(inst, &line_threads, true)?;
+        let start_line = intern(&mut states, line_threads)?;
+
+        let mut table = Vec::new();
+        let mut current = 0;
+        while current < states.len() {
+            for class in 0..num_classes {
+                // クラスの代表となる文字コード（クラス内で最小の値）
+                let rep = if class == 0 { 0 } else { boundaries[class - 1] };
+                let mut next = Vec::new();
+                for &pc in &states[current] {
+                    if inst[pc].matches(rep) == Some(true) {
+                        add_thread(inst, &mut next, pc + 1, false, false)?;
+                    }
+                }
+                let id = intern(&mut states, normalize(inst, next))?;
+                table.push(id);
+            }
+            current += 1;
+        }
+
+        let is_match = states
+            .iter()
+            .map(|s| s.last().is_some_and(|pc| inst[*pc] == Instruction::Match))
+            .collect();
+        let accept_at_end = states
+            .iter()
+            .map(|s| accepts_at_end(inst, s, false))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            boundaries,
+            is_match,
+            accept_at_end,
+            table,
+            start,
+            start_line,
+            empty_line_accepted,
+        })
+    }
+
+    /// 状態数
+    pub fn num_states(&self) -> usize {
+        self.is_match.len()
+    }
+
+    /// 遷移表をバージョン付きのバイナリ形式に変換
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.usize(self.boundaries.len());
+        for b in &self.boundaries {
+            w.u32(*b);
+        }
+        w.usize(self.num_states());
+        for (is_match, accept_at_end) in self.is_match.iter().zip(&self.accept_at_end) {
+            w.bool(*is_match);
+            w.bool(*accept_at_end);
+        }
+        for next in &self.table {
+            w.usize(*next);
+        }
+        w.usize(self.start);
+        w.usize(self.start_line);
+        w.bool(self.empty_line_accepted);
+        serialize::wrap(Kind::Dfa, &w.bytes)
+    }
+
+    /// to_bytesで変換したバイト列から復元
+    ///
+    /// 形式のバージョンとチェックサムに加え、遷移先が状態の範囲内であることを検査する。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializeError> {
+        let mut r = Reader::new(serialize::unwrap(Kind::Dfa, bytes)?);
+        let num_boundaries = r.count(4)?;
+        let boundaries = (0..num_boundaries)
+            .map(|_| r.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !boundaries.windows(2).all(|w| w[0] < w[1]) {
+            return Err(SerializeError::InvalidData("unsorted boundaries"));
+        }
+
+        let num_states = r.count(2)?;
+        let mut is_match = Vec::with_capacity(num_states);
+        let mut accept_at_end = Vec::with_capacity(num_states);
+        for _ in 0..num_states {
+            is_match.push(r.bool()?);
+            accept_at_end.push(r.bool()?);
+        }
+
+        let num_classes = boundaries.len() + 1;
+        let table_len = num_states
+            .checked_mul(num_classes)
+            .ok_or(SerializeError::Truncated)?;
+        let mut table = Vec::new();
+        for _ in 0..table_len {
+            table.push(r.usize()?);
+        }
+        let start = r.usize()?;
+        let start_line = r.usize()?;
+        let empty_line_accepted = r.bool()?;
+        if !r.is_empty() {
+            return Err(SerializeError::InvalidData("trailing bytes"));
+        }
+        if num_states == 0
+            || table
+                .iter()
+                .chain([&start, &start_line])
+                .any(|s| *s >= num_states)
+        {
+            return Err(SerializeError::InvalidData("state out of range"));
+        }
+
+        Ok(Self {
+            boundaries,
+            is_match,
+            accept_at_end,
+            table,
+            start,
+            start_line,
+            empty_line_accepted,
+        })
+    }
+
+    /// 文字が属するクラス
+    fn class_of(&self, c: char) -> usize {
+        self.boundaries.partition_point(|b| *b <= c as u32)
+    }
+
+    /// startから始まるマッチのうち、最も優先順位の高いものの終了位置を返す
+    fn find_at(&self, line: &[char], start: usize) -> Option<usize> {
+        if line.is_empty() {
+            return self.empty_line_accepted.then_some(0);
+        }
+
+        let num_classes = self.boundaries.len() + 1;
+        let mut state = if start == 0 {
+            self.start_line
+        } else {
+            self.start
+        };
+        let mut end = None;
+        for sp in start..=line.len() {
+            let accepted = if sp == line.len() {
+                self.accept_at_end[state]
+            } else {
+                self.is_match[state]
+            };
+            if accepted {
+                end = Some(sp);
+            }
+            match line.get(sp) {
+                Some(c) => state = self.table[state * num_classes + self.class_of(*c)],
+                None => break,
+            }
+            if state == DEAD {
+                break;
+            }
+        }
+        end
+    }
+
+    /// 行中で最も左にあるマッチを探し、(開始位置, 終了位置)を文字単位で返す
+    pub fn find(&self, line: &[char]) -> Option<(usize, usize)> {
+        (0..=line.len()).find_map(|start| self.find_at(line, start).map(|end| (start, end)))
+    }
+}
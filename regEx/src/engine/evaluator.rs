@@ -0,0 +1,114 @@
+//! 命令列と入力文字列を受け取り、マッチングを行う（バックトラック法）
+use super::Instruction;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 評価時のエラーを表現するための型
+#[derive(Debug)]
+pub enum EvalError {
+    InvalidPC, // 命令列の範囲外を指すプログラムカウンタ
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EvalError: {:?}", self)
+    }
+}
+
+impl Error for EvalError {}
+
+/// 評価済みの（プログラムカウンタ, 文字位置）の組を記録する表
+///
+/// 一度失敗した組は、どの開始位置から到達しても失敗するため、
+/// 開始位置をまたいで共有できる。また、空文字列にマッチする繰り返しの無限ループも防ぐ。
+struct Visited {
+    bits: Vec<u64>,
+    width: usize,
+}
+
+impl Visited {
+    fn new(inst_len: usize, line_len: usize) -> Self {
+        let width = line_len + 1;
+        Self {
+            bits: vec![0; (inst_len * width).div_ceil(64)],
+            width,
+        }
+    }
+
+    /// 未評価であれば評価済みにしてtrueを返す
+    fn insert(&mut self, pc: usize, sp: usize) -> bool {
+        let idx = pc * self.width + sp;
+        let (word, bit) = (idx / 64, 1 << (idx % 64));
+        let is_new = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        is_new
+    }
+}
+
+/// 深さ優先で評価し、マッチした場合は終了位置を返す
+///
+/// Splitでは第1分岐を優先し、失敗した場合に第2分岐へバックトラックする。
+/// 再帰の代わりにスタックを用いるため、長い入力でもスタックオーバーフローしない。
+fn eval_depth(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+    visited: &mut Visited,
+) -> Result<Option<usize>, EvalError> {
+    let mut stack = vec![(0, start)];
+    while let Some((mut pc, mut sp)) = stack.pop() {
+        loop {
+            if pc >= inst.len() {
+                return Err(EvalError::InvalidPC);
+            }
+            if !visited.insert(pc, sp) {
+                break;
+            }
+            match &inst[pc] {
+                Instruction::Char(_) | Instruction::Class(_) => {
+                    let c = line.get(sp).map(|c| *c as u32);
+                    if c.and_then(|c| inst[pc].matches(c)) == Some(true) {
+                        pc += 1;
+                        sp += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Instruction::Match => return Ok(Some(sp)),
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Split(addr1, addr2) => {
+                    stack.push((*addr2, sp));
+                    pc = *addr1;
+                }
+                Instruction::AssertStart => {
+                    if sp == 0 {
+                        pc += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Instruction::AssertEnd => {
+                    if sp == line.len() {
+                        pc += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 行中で最も左にあるマッチを探し、(開始位置, 終了位置)を文字単位で返す
+pub fn eval(inst: &[Instruction], line: &[char]) -> Result<Option<(usize, usize)>, EvalError> {
+    let mut visited = Visited::new(inst.len(), line.len());
+    for start in 0..=line.len() {
+        if let Some(end) = eval_depth(inst, line, start, &mut visited)? {
+            return Ok(Some((start, end)));
+        }
+    }
+    Ok(None)
+}
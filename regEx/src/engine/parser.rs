@@ -6,7 +6,7 @@ use std::{
 };
 
 /// 抽象構文木を表現するための型
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AST {
     Char(char),
     Plus(Box<AST>),
@@ -14,6 +14,37 @@ pub enum AST {
     Question(Box<AST>),
     Or(Box<AST>, Box<AST>),
     Seq(Vec<AST>),
+    Class(CharClass), // 文字クラス
+    Start,            // 行頭
+    End,              // 行末
+}
+
+/// 文字クラス。rangesのいずれかに含まれる文字（negatedの場合は含まれない文字）にマッチ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharClass {
+    pub ranges: Vec<(char, char)>, // 両端を含む文字の範囲
+    pub negated: bool,
+}
+
+impl CharClass {
+    /// 任意の1文字にマッチするクラス
+    pub fn any() -> Self {
+        Self {
+            ranges: Vec::new(),
+            negated: true,
+        }
+    }
+
+    /// 文字コードがクラスに含まれるか判定
+    ///
+    /// DFAでは文字として有効でない値も代表値として渡すため、u32で判定する。
+    pub fn contains(&self, c: u32) -> bool {
+        let found = self
+            .ranges
+            .iter()
+            .any(|(lo, hi)| *lo as u32 <= c && c <= *hi as u32);
+        found != self.negated
+    }
 }
 
 /// パースエラーを表現するための型
@@ -24,6 +55,8 @@ pub enum ParserError {
     InvalidRightParen(usize),   // 開き括弧なし
     NoPrev(usize),              // + 、　｜　、*、　?の前に何もない
     NoRightParen,               // 閉じ括弧なし
+    NoRightBracket(usize),      // 閉じ角括弧なし
+    InvalidRange(usize),        // 開始が終了より大きい文字の範囲
     Empty,                      //空のパターン
 }
 /// パースエラーを表示するために、Displayトレイトを実装
@@ -38,6 +71,12 @@ impl Display for ParserError {
             }
             ParserError::NoPrev(pos) => write!(f, "No previous character at position {}", pos),
             ParserError::NoRightParen => write!(f, "No right parenthesis"),
+            ParserError::NoRightBracket(pos) => {
+                write!(f, "No right bracket for '[' at position {}", pos)
+            }
+            ParserError::InvalidRange(pos) => {
+                write!(f, "Invalid character range at position {}", pos)
+            }
             ParserError::Empty => write!(f, "Empty pattern"),
         }
     }
@@ -49,7 +88,7 @@ impl Error for ParserError {}
 fn parse_escape(pos: usize, c: char) -> Result<AST, ParserError> {
     // posが現在の文字位置、cがエスケープする特殊文字
     match c {
-        '\\' | '+' | '*' | '?' | '|' | '(' | ')' => Ok(AST::Char(c)),
+        '\\' | '+' | '*' | '?' | '|' | '(' | ')' | '[' | ']' | '.' | '^' | '$' => Ok(AST::Char(c)),
         _ => {
             let err = ParserError::InvalidEscape(pos, c);
             Err(err)
@@ -57,7 +96,36 @@ fn parse_escape(pos: usize, c: char) -> Result<AST, ParserError> {
     }
 }
 
+/// [...]の中身を文字クラスに変換
+///
+/// itemsは(文字, エスケープされているか)の並び。先頭の^は否定、a-zは範囲を表す。
+fn parse_class(pos: usize, items: &[(char, bool)]) -> Result<AST, ParserError> {
+    let (negated, mut items) = match items.split_first() {
+        Some((('^', false), rest)) => (true, rest),
+        _ => (false, items),
+    };
+
+    let mut ranges = Vec::new();
+    while let Some(((lo, _), rest)) = items.split_first() {
+        match rest {
+            [('-', false), (hi, _), rest @ ..] => {
+                if lo > hi {
+                    return Err(ParserError::InvalidRange(pos));
+                }
+                ranges.push((*lo, *hi));
+                items = rest;
+            }
+            _ => {
+                ranges.push((*lo, *lo));
+                items = rest;
+            }
+        }
+    }
+    Ok(AST::Class(CharClass { ranges, negated }))
+}
+
 /// parse_plus_star_question関数で利用する列挙型（限量子）
+#[allow(clippy::upper_case_acronyms)]
 enum PSQ {
     Plus,
     Star,
@@ -112,17 +180,23 @@ pub fn parse(expr: &str) -> Result<AST, ParserError> {
     // 内部状態を表現するための型
     // Char:文字列処理中
     // Escape:エスケープ処理中
+    // Class:[...]の中身を処理中
+    // ClassEscape:[...]の中でエスケープ処理中
     enum ParseState {
         Char,
         Escape,
+        Class,
+        ClassEscape,
     }
 
     let mut seq = Vec::new(); // 現在のSeqコンテキスト
     let mut seq_or = Vec::new(); // 現在のOrコンテキスト
     let mut stack = Vec::new(); //コンテキストのスタック
     let mut state = ParseState::Char; // 現在の状態
+    let mut class_pos = 0; // 処理中の[の位置
+    let mut class_items = Vec::new(); // 処理中の[...]の中身
 
-    for (i, c) in expr.chars().emumerate() {
+    for (i, c) in expr.chars().enumerate() {
         match &state {
             ParseState::Char => match c {
                 '+' => {
@@ -150,18 +224,26 @@ pub fn parse(expr: &str) -> Result<AST, ParserError> {
                         seq = prev;
                         seq_or = prev_or;
                     } else {
-                        let err = Box::new(ParserError::InvalidRightParen(i));
+                        let err = ParserError::InvalidRightParen(i);
                         return Err(err);
                     }
                 }
                 '|' => {
                     if seq.is_empty() {
-                        return Err(Box::new(ParserError::NoPrev(i)));
+                        return Err(ParserError::NoPrev(i));
                     } else {
                         let prev = take(&mut seq);
                         seq_or.push(AST::Seq(prev));
                     }
                 }
+                '[' => {
+                    class_pos = i;
+                    class_items.clear();
+                    state = ParseState::Class;
+                }
+                '.' => seq.push(AST::Class(CharClass::any())),
+                '^' => seq.push(AST::Start),
+                '$' => seq.push(AST::End),
                 '\\' => state = ParseState::Escape,
                 _ => seq.push(AST::Char(c)),
             },
@@ -169,11 +251,31 @@ pub fn parse(expr: &str) -> Result<AST, ParserError> {
                 seq.push(parse_escape(i, c)?);
                 state = ParseState::Char;
             }
+            ParseState::Class => match c {
+                // 先頭（^の直後を含む）の]は通常の文字
+                ']' if !matches!(class_items[..], [] | [('^', false)]) => {
+                    seq.push(parse_class(class_pos, &class_items)?);
+                    state = ParseState::Char;
+                }
+                '\\' => state = ParseState::ClassEscape,
+                _ => class_items.push((c, false)),
+            },
+            ParseState::ClassEscape => {
+                class_items.push((c, true));
+                state = ParseState::Class;
+            }
+        }
+    }
+
+    match state {
+        ParseState::Class | ParseState::ClassEscape => {
+            return Err(ParserError::NoRightBracket(class_pos));
         }
+        ParseState::Escape | ParseState::Char => (),
     }
 
     if !stack.is_empty() {
-        return Err(Box::new(ParserError::NoRightParen));
+        return Err(ParserError::NoRightParen);
     }
 
     if !seq.is_empty() {
@@ -183,6 +285,6 @@ pub fn parse(expr: &str) -> Result<AST, ParserError> {
     if let Some(ast) = fold_or(seq_or) {
         Ok(ast)
     } else {
-        Err(Box::new(ParserError::Empty))
+        Err(ParserError::Empty)
     }
 }
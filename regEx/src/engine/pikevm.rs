@@ -0,0 +1,146 @@
+//! 命令列と入力文字列を受け取り、マッチングを行う（Pike VM）
+//!
+//! 全てのスレッドを入力1文字ごとに並行して進めるため、入力長と命令数の積に比例する時間で終わる。
+//! スレッドは優先順位の順に並べ、バックトラック法と同じマッチを返す。
+use super::{evaluator::EvalError, Instruction};
+
+/// 優先順位付きのスレッドの集合
+struct Threads {
+    list: Vec<(usize, usize)>, // (プログラムカウンタ, マッチ開始位置)
+    visited: Vec<usize>,       // たどったプログラムカウンタ
+    on_list: Vec<bool>,        // プログラムカウンタごとのたどり済みフラグ
+}
+
+impl Threads {
+    fn new(inst_len: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            visited: Vec::new(),
+            on_list: vec![false; inst_len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        for pc in self.visited.drain(..) {
+            self.on_list[pc] = false;
+        }
+    }
+
+    /// 位置spでpcからJump、Split、成立する表明をたどり、
+    /// 到達した文字を消費する命令とMatch命令を優先順位の順に登録
+    fn add(
+        &mut self,
+        inst: &[Instruction],
+        line: &[char],
+        pc: usize,
+        start: usize,
+        sp: usize,
+    ) -> Result<(), EvalError> {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            let on_list = self.on_list.get_mut(pc).ok_or(EvalError::InvalidPC)?;
+            if *on_list {
+                continue;
+            }
+            *on_list = true;
+            self.visited.push(pc);
+            match &inst[pc] {
+                Instruction::Jump(addr) => stack.push(*addr),
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(*addr2);
+                    stack.push(*addr1);
+                }
+                Instruction::AssertStart => {
+                    if sp == 0 {
+                        stack.push(pc + 1);
+                    }
+                }
+                Instruction::AssertEnd => {
+                    if sp == line.len() {
+                        stack.push(pc + 1);
+                    }
+                }
+                Instruction::Char(_) | Instruction::Class(_) | Instruction::Match => {
+                    self.list.push((pc, start))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 行中で最も左にあるマッチを探し、(開始位置, 終了位置)を文字単位で返す
+pub fn eval(inst: &[Instruction], line: &[char]) -> Result<Option<(usize, usize)>, EvalError> {
+    let mut clist = Threads::new(inst.len());
+    let mut nlist = Threads::new(inst.len());
+    let mut matched = None;
+
+    for sp in 0..=line.len() {
+        // マッチが見つかるまでは、各位置から始まるスレッドを最低の優先順位で追加
+        if matched.is_none() {
+            clist.add(inst, line, 0, sp, sp)?;
+        }
+        if clist.list.is_empty() && matched.is_some() {
+            break;
+        }
+
+        for &(pc, start) in &clist.list {
+            match &inst[pc] {
+                Instruction::Char(_) | Instruction::Class(_) => {
+                    let c = line.get(sp).map(|c| *c as u32);
+                    if c.and_then(|c| inst[pc].matches(c)) == Some(true) {
+                        nlist.add(inst, line, pc + 1, start, sp + 1)?;
+                    }
+                }
+                Instruction::Match => {
+                    // これより優先順位の低いスレッドは不要
+                    matched = Some((start, sp));
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        std::mem::swap(&mut clist, &mut nlist);
+        nlist.clear();
+    }
+
+    Ok(matched)
+}
+
+/// 位置startから始まるマッチのうち、最も長いものの終了位置を返す
+///
+/// 字句解析の最長一致に用いる。優先順位は考慮せず、到達可能な全てのスレッドを進める。
+pub fn longest_at(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+) -> Result<Option<usize>, EvalError> {
+    let mut clist = Threads::new(inst.len());
+    let mut nlist = Threads::new(inst.len());
+    let mut longest = None;
+
+    clist.add(inst, line, 0, start, start)?;
+    for sp in start..=line.len() {
+        if clist.list.is_empty() {
+            break;
+        }
+        for &(pc, _) in &clist.list {
+            match &inst[pc] {
+                Instruction::Char(_) | Instruction::Class(_) => {
+                    let c = line.get(sp).map(|c| *c as u32);
+                    if c.and_then(|c| inst[pc].matches(c)) == Some(true) {
+                        nlist.add(inst, line, pc + 1, start, sp + 1)?;
+                    }
+                }
+                Instruction::Match => longest = Some(sp),
+                _ => unreachable!(),
+            }
+        }
+        std::mem::swap(&mut clist, &mut nlist);
+        nlist.clear();
+    }
+
+    Ok(longest)
+}
@@ -0,0 +1,375 @@
+//! コンパイル済みの命令列とDFAを、バージョン付きのバイナリ形式に変換
+//!
+//! 形式は次のとおり。数値は全てリトルエンディアン。
+//!
+//! ```text
+//! magic    : b"REGX"
+//! version  : u16
+//! kind     : u8      (0: 命令列, 1: DFA)
+//! length   : u64     (payloadのバイト数)
+//! payload  : [u8; length]
+//! checksum : u32     (ここまでの全バイトのCRC-32)
+//! ```
+use super::{parser::CharClass, Instruction};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 形式を識別するための先頭のバイト列
+const MAGIC: &[u8; 4] = b"REGX";
+
+/// 形式のバージョン。互換性のない変更を行った場合は増やす
+pub const FORMAT_VERSION: u16 = 1;
+
+/// 格納しているデータの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Program = 0,
+    Dfa = 1,
+}
+
+/// 読み込み時のエラーを表現するための型
+#[derive(Debug, PartialEq, Eq)]
+pub enum SerializeError {
+    BadMagic,                  // 先頭のバイト列が異なる
+    UnsupportedVersion(u16),   // 対応していないバージョン
+    WrongKind(u8),             // 期待と異なるデータの種類
+    Truncated,                 // データが途中で終わっている
+    ChecksumMismatch,          // チェックサムが一致しない（データの破損）
+    InvalidData(&'static str), // 内容が不正
+}
+
+impl Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializeError::BadMagic => write!(f, "Not a compiled regex"),
+            SerializeError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported format version {} (expected {})",
+                v, FORMAT_VERSION
+            ),
+            SerializeError::WrongKind(k) => write!(f, "Unexpected data kind {}", k),
+            SerializeError::Truncated => write!(f, "Data is truncated"),
+            SerializeError::ChecksumMismatch => write!(f, "Checksum mismatch"),
+            SerializeError::InvalidData(msg) => write!(f, "Invalid data: {}", msg),
+        }
+    }
+}
+
+impl Error for SerializeError {}
+
+/// CRC-32（IEEE 802.3）
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// payloadにヘッダとチェックサムを付与
+pub(crate) fn wrap(kind: Kind, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(payload.len() + 19);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(kind as u8);
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+/// ヘッダとチェックサムを検査し、payloadを取り出す
+pub(crate) fn unwrap(kind: Kind, bytes: &[u8]) -> Result<&[u8], SerializeError> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(SerializeError::BadMagic);
+    }
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(SerializeError::UnsupportedVersion(version));
+    }
+    let actual_kind = reader.u8()?;
+    if actual_kind != kind as u8 {
+        return Err(SerializeError::WrongKind(actual_kind));
+    }
+    let len = reader.usize()?;
+    let payload = reader.take(len)?;
+
+    let body_len = reader.pos;
+    let checksum = reader.u32()?;
+    if !reader.is_empty() {
+        return Err(SerializeError::InvalidData("trailing bytes"));
+    }
+    if crc32(&bytes[..body_len]) != checksum {
+        return Err(SerializeError::ChecksumMismatch);
+    }
+    Ok(payload)
+}
+
+/// バイト列への書き込み
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    pub fn u32(&mut self, n: u32) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    /// usizeはプラットフォームによらずu64として書き込む
+    pub fn usize(&mut self, n: usize) {
+        self.bytes.extend_from_slice(&(n as u64).to_le_bytes());
+    }
+
+    pub fn bool(&mut self, b: bool) {
+        self.u8(b as u8);
+    }
+
+    pub fn char(&mut self, c: char) {
+        self.u32(c as u32);
+    }
+}
+
+/// バイト列からの読み込み
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SerializeError> {
+        let end = self.pos.checked_add(n).ok_or(SerializeError::Truncated)?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(SerializeError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SerializeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SerializeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SerializeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SerializeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, SerializeError> {
+        usize::try_from(u64::from_le_bytes(self.array()?))
+            .map_err(|_| SerializeError::InvalidData("length overflows usize"))
+    }
+
+    /// 要素数を読み込み、残りのバイト数で収まらない値を拒否する
+    ///
+    /// 破損したデータで巨大な領域を確保しないための検査。
+    pub fn count(&mut self, min_elem_size: usize) -> Result<usize, SerializeError> {
+        let n = self.usize()?;
+        let rest = self.bytes.len() - self.pos;
+        if n.saturating_mul(min_elem_size) > rest {
+            return Err(SerializeError::Truncated);
+        }
+        Ok(n)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SerializeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SerializeError::InvalidData("invalid bool")),
+        }
+    }
+
+    pub fn char(&mut self) -> Result<char, SerializeError> {
+        char::from_u32(self.u32()?).ok_or(SerializeError::InvalidData("invalid char"))
+    }
+}
+
+/// 命令列をpayloadに変換
+pub(crate) fn write_program(insts: &[Instruction]) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.usize(insts.len());
+    for inst in insts {
+        match inst {
+            Instruction::Char(c) => {
+                w.u8(0);
+                w.char(*c);
+            }
+            Instruction::Match => w.u8(1),
+            Instruction::Jump(addr) => {
+                w.u8(2);
+                w.usize(*addr);
+            }
+            Instruction::Split(addr1, addr2) => {
+                w.u8(3);
+                w.usize(*addr1);
+                w.usize(*addr2);
+            }
+            Instruction::Class(class) => {
+                w.u8(4);
+                w.bool(class.negated);
+                w.usize(class.ranges.len());
+                for (lo, hi) in &class.ranges {
+                    w.char(*lo);
+                    w.char(*hi);
+                }
+            }
+            Instruction::AssertStart => w.u8(5),
+            Instruction::AssertEnd => w.u8(6),
+        }
+    }
+    w.bytes
+}
+
+/// payloadから命令列を復元し、評価器が範囲外を参照しないことを検査
+pub(crate) fn read_program(payload: &[u8]) -> Result<Vec<Instruction>, SerializeError> {
+    let mut r = Reader::new(payload);
+    let n = r.count(1)?;
+    let mut insts = Vec::with_capacity(n);
+    for _ in 0..n {
+        let inst = match r.u8()? {
+            0 => Instruction::Char(r.char()?),
+            1 => Instruction::Match,
+            2 => Instruction::Jump(r.usize()?),
+            3 => Instruction::Split(r.usize()?, r.usize()?),
+            4 => {
+                let negated = r.bool()?;
+                let count = r.count(8)?;
+                let mut ranges = Vec::with_capacity(count);
+                for _ in 0..count {
+                    ranges.push((r.char()?, r.char()?));
+                }
+                Instruction::Class(CharClass { ranges, negated })
+            }
+            5 => Instruction::AssertStart,
+            6 => Instruction::AssertEnd,
+            _ => return Err(SerializeError::InvalidData("unknown instruction")),
+        };
+        insts.push(inst);
+    }
+    if !r.is_empty() {
+        return Err(SerializeError::InvalidData("trailing bytes"));
+    }
+
+    // 分岐先は命令列の範囲内であり、最後の命令は次の命令へ進まないこと
+    for inst in &insts {
+        match inst {
+            Instruction::Jump(addr) if *addr >= n => {
+                return Err(SerializeError::InvalidData("jump out of range"))
+            }
+            Instruction::Split(addr1, addr2) if *addr1 >= n || *addr2 >= n => {
+                return Err(SerializeError::InvalidData("split out of range"))
+            }
+            _ => (),
+        }
+    }
+    match insts.last() {
+        Some(Instruction::Match | Instruction::Jump(_) | Instruction::Split(_, _)) => Ok(insts),
+        _ => Err(SerializeError::InvalidData("program must end with match")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SerializeError, FORMAT_VERSION};
+    use crate::engine::{dfa::Dfa, Regex};
+
+    #[test]
+    fn test_program_round_trip() {
+        let regex = Regex::new("^(ab|[^c-e]x)*.?$").unwrap();
+        let bytes = regex.to_bytes();
+        let restored = Regex::from_bytes(&bytes).unwrap();
+        assert_eq!(regex.code(), restored.code());
+        assert!(restored.is_match("abzx").unwrap());
+    }
+
+    #[test]
+    fn test_dfa_round_trip() {
+        let regex = Regex::new("a[0-9]+|b$").unwrap();
+        let dfa = Dfa::new(regex.code()).unwrap();
+        let restored = Dfa::from_bytes(&dfa.to_bytes()).unwrap();
+        assert_eq!(dfa, restored);
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let regex = Regex::new("abc").unwrap();
+        let bytes = regex.to_bytes();
+
+        // 種類が異なる
+        assert_eq!(
+            Some(SerializeError::WrongKind(0)),
+            Dfa::from_bytes(&bytes).err()
+        );
+
+        // バージョンが異なる
+        let mut other_version = bytes.clone();
+        other_version[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Some(&SerializeError::UnsupportedVersion(FORMAT_VERSION + 1)),
+            Regex::from_bytes(&other_version)
+                .unwrap_err()
+                .downcast_ref::<SerializeError>()
+        );
+
+        // 途中で終わっている
+        assert_eq!(
+            Some(&SerializeError::Truncated),
+            Regex::from_bytes(&bytes[..bytes.len() - 1])
+                .unwrap_err()
+                .downcast_ref::<SerializeError>()
+        );
+
+        // 1バイトでも壊れていれば検出する
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0x01;
+            assert!(Regex::from_bytes(&corrupted).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_program() {
+        use super::{read_program, write_program};
+        use crate::engine::Instruction;
+
+        let payload = write_program(&[Instruction::Jump(5), Instruction::Match]);
+        assert_eq!(
+            Err(SerializeError::InvalidData("jump out of range")),
+            read_program(&payload)
+        );
+        let payload = write_program(&[Instruction::Char('a')]);
+        assert_eq!(
+            Err(SerializeError::InvalidData("program must end with match")),
+            read_program(&payload)
+        );
+    }
+}
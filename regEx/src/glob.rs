@@ -0,0 +1,286 @@
+//! シェル形式のglobをパースし、正規表現と同じ抽象構文木（AST）に変換
+//!
+//! 対応する構文は次のとおり。
+//!
+//! - `*`：`/`以外の0文字以上
+//! - `**`：パスの区切りをまたいだ0個以上のディレクトリ（`src/**/mod.rs`など）
+//! - `?`：`/`以外の1文字
+//! - `[ch]`、`[a-z]`、`[!ch]`（`[^ch]`）：文字クラス
+//! - `{a,b}`：いずれかの候補（入れ子も可）
+//! - `\x`：xを通常の文字として扱う
+use crate::{
+    engine::{
+        parser::{CharClass, AST},
+        Regex,
+    },
+    helper::DynError,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    path::Path,
+};
+
+/// globのパースエラーを表現するための型
+#[derive(Debug, PartialEq, Eq)]
+pub enum GlobError {
+    UnclosedClass(usize),     // 閉じ角括弧なし
+    UnclosedBrace(usize),     // 閉じ波括弧なし
+    InvalidRange(usize),      // 開始が終了より大きい範囲
+    InvalidRightBrace(usize), // 開き波括弧なし
+    TrailingEscape,           // 末尾にエスケープ文字
+}
+
+impl Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobError::UnclosedClass(pos) => write!(f, "Unclosed '[' at position {}", pos),
+            GlobError::UnclosedBrace(pos) => write!(f, "Unclosed '{{' at position {}", pos),
+            GlobError::InvalidRange(pos) => write!(f, "Invalid range at position {}", pos),
+            GlobError::InvalidRightBrace(pos) => {
+                write!(f, "Invalid right brace at position {}", pos)
+            }
+            GlobError::TrailingEscape => write!(f, "Trailing escape character"),
+        }
+    }
+}
+
+impl Error for GlobError {}
+
+/// `/`以外の1文字
+fn not_separator() -> AST {
+    AST::Class(CharClass {
+        ranges: vec![('/', '/')],
+        negated: true,
+    })
+}
+
+/// globのパーサ
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// 現在位置からglobをパースし、`,`か`}`、または末尾で止まる
+    ///
+    /// in_braceがfalseの場合、`,`と`}`は通常の文字として扱う。
+    fn parse_seq(&mut self, in_brace: bool) -> Result<AST, GlobError> {
+        let mut seq = Vec::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            match c {
+                ',' | '}' if in_brace => break,
+                '}' => return Err(GlobError::InvalidRightBrace(self.pos)),
+                '*' => seq.push(self.parse_star()),
+                '?' => {
+                    self.pos += 1;
+                    seq.push(not_separator());
+                }
+                '[' => seq.push(self.parse_class()?),
+                '{' => seq.push(self.parse_brace()?),
+                '\\' => {
+                    let escaped = self
+                        .chars
+                        .get(self.pos + 1)
+                        .ok_or(GlobError::TrailingEscape)?;
+                    seq.push(AST::Char(*escaped));
+                    self.pos += 2;
+                }
+                _ => {
+                    seq.push(AST::Char(c));
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(AST::Seq(seq))
+    }
+
+    /// `*`と`**`をパース
+    fn parse_star(&mut self) -> AST {
+        let start = self.pos;
+        while self.chars.get(self.pos) == Some(&'*') {
+            self.pos += 1;
+        }
+        let after_separator = start == 0 || self.chars[start - 1] == '/';
+        let next = self.chars.get(self.pos);
+        let any = || AST::Star(Box::new(AST::Class(CharClass::any())));
+
+        if self.pos - start < 2 || !after_separator {
+            // パス要素の一部である`*`（`**`も`*`と同じ扱い）
+            AST::Star(Box::new(not_separator()))
+        } else if next == Some(&'/') {
+            // `**/`：0個以上のディレクトリ
+            self.pos += 1;
+            AST::Question(Box::new(AST::Seq(vec![any(), AST::Char('/')])))
+        } else if next.is_none() {
+            // 末尾の`**`：以下の全て
+            any()
+        } else {
+            AST::Star(Box::new(not_separator()))
+        }
+    }
+
+    /// `[...]`をパース
+    fn parse_class(&mut self) -> Result<AST, GlobError> {
+        let open = self.pos;
+        self.pos += 1;
+        let negated = matches!(self.chars.get(self.pos), Some('!' | '^'));
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = *self
+                .chars
+                .get(self.pos)
+                .ok_or(GlobError::UnclosedClass(open))?;
+            // 先頭の`]`は通常の文字
+            if c == ']' && !first {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+
+            if self.chars.get(self.pos + 1) == Some(&'-')
+                && self.chars.get(self.pos + 2).is_some_and(|c| *c != ']')
+            {
+                let hi = self.chars[self.pos + 2];
+                if c > hi {
+                    return Err(GlobError::InvalidRange(self.pos));
+                }
+                ranges.push((c, hi));
+                self.pos += 3;
+            } else {
+                ranges.push((c, c));
+                self.pos += 1;
+            }
+        }
+        Ok(AST::Class(CharClass { ranges, negated }))
+    }
+
+    /// `{a,b,...}`をパース
+    fn parse_brace(&mut self) -> Result<AST, GlobError> {
+        let open = self.pos;
+        self.pos += 1;
+        let mut alternatives = Vec::new();
+        loop {
+            alternatives.push(self.parse_seq(true)?);
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(GlobError::UnclosedBrace(open)),
+            }
+        }
+
+        // a,b,cをAST::Or(a, AST::Or(b, c))に変換
+        let mut ast = alternatives.pop().unwrap();
+        while let Some(alt) = alternatives.pop() {
+            ast = AST::Or(Box::new(alt), Box::new(ast));
+        }
+        Ok(ast)
+    }
+}
+
+/// globをパースし、文字列全体にマッチするASTに変換
+pub fn parse(glob: &str) -> Result<AST, GlobError> {
+    let mut parser = Parser {
+        chars: glob.chars().collect(),
+        pos: 0,
+    };
+    let body = parser.parse_seq(false)?;
+    Ok(AST::Seq(vec![AST::Start, body, AST::End]))
+}
+
+/// globによるパスの照合器
+///
+/// `/`を含まないglob（`*.rs`など）はファイル名と、含むglobはパス全体と照合する。
+#[derive(Debug, Clone)]
+pub struct Glob {
+    regex: Regex,
+    match_name_only: bool,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Self, DynError> {
+        let ast = parse(glob)?;
+        Ok(Self {
+            regex: Regex::from_ast(&ast)?,
+            match_name_only: !glob.contains('/'),
+        })
+    }
+
+    /// 文字列全体がglobにマッチするか判定
+    pub fn is_match(&self, text: &str) -> Result<bool, DynError> {
+        self.regex.is_match(text)
+    }
+
+    /// パスがglobにマッチするか判定
+    ///
+    /// 区切り文字は`/`にそろえ、先頭の`./`は取り除いてから照合する。
+    pub fn is_match_path(&self, path: &Path) -> Result<bool, DynError> {
+        if self.match_name_only {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            return self.is_match(&name);
+        }
+        let path = path.to_string_lossy().replace('\\', "/");
+        let path = path.trim_start_matches("./");
+        self.is_match(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Glob, GlobError};
+    use std::path::Path;
+
+    fn is_match(glob: &str, path: &str) -> bool {
+        Glob::new(glob)
+            .unwrap()
+            .is_match_path(Path::new(path))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(is_match("*.rs", "src/main.rs"));
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(!is_match("*.rs", "main.rsx"));
+        assert!(is_match("file?.[ch]", "file1.c"));
+        assert!(is_match("file?.[ch]", "file2.h"));
+        assert!(!is_match("file?.[ch]", "file10.c"));
+        assert!(is_match("[!a-c]*", "dog"));
+        assert!(!is_match("[!a-c]*", "cat"));
+        assert!(is_match("*.{rs,toml}", "Cargo.toml"));
+        assert!(is_match("{a,b{c,d}}.txt", "bd.txt"));
+        assert!(!is_match("{a,b{c,d}}.txt", "b.txt"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("\\*", "*"));
+        assert!(!is_match("\\*", "a"));
+    }
+
+    #[test]
+    fn test_glob_path() {
+        assert!(is_match("src/**/mod.rs", "src/mod.rs"));
+        assert!(is_match("src/**/mod.rs", "./src/engine/mod.rs"));
+        assert!(is_match("src/**/mod.rs", "src/a/b/mod.rs"));
+        assert!(!is_match("src/**/mod.rs", "lib/a/mod.rs"));
+        assert!(is_match("src/**", "src/a/b.rs"));
+        assert!(is_match("src/*.rs", "src/main.rs"));
+        assert!(!is_match("src/*.rs", "src/engine/parser.rs"));
+        assert!(is_match("**/*.rs", "a/b/c.rs"));
+    }
+
+    #[test]
+    fn test_glob_error() {
+        assert_eq!(Err(GlobError::UnclosedClass(0)), parse("[ab"));
+        assert_eq!(Err(GlobError::UnclosedBrace(2)), parse("a.{b,c"));
+        assert_eq!(Err(GlobError::InvalidRange(1)), parse("[z-a]"));
+        assert_eq!(Err(GlobError::InvalidRightBrace(1)), parse("a}"));
+        assert_eq!(Err(GlobError::TrailingEscape), parse("a\\"));
+    }
+}
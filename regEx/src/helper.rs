@@ -0,0 +1,29 @@
+//! 各モジュールで共通に利用するヘルパー
+
+/// 任意のエラーを返すための型
+pub type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// オーバーフローを検知する加算
+pub trait SafeAdd: Sized {
+    fn safe_add(&self, n: &Self) -> Option<Self>;
+}
+
+impl SafeAdd for usize {
+    fn safe_add(&self, n: &Self) -> Option<Self> {
+        self.checked_add(*n)
+    }
+}
+
+/// dstにsrcを加算し、オーバーフローした場合はf()の返すエラーを返す
+pub fn safe_add<T, F, E>(dst: &mut T, src: &T, f: F) -> Result<(), E>
+where
+    T: SafeAdd,
+    F: Fn() -> E,
+{
+    if let Some(n) = dst.safe_add(src) {
+        *dst = n;
+        Ok(())
+    } else {
+        Err(f())
+    }
+}
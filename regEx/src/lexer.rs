@@ -0,0 +1,169 @@
+//! 名前付きの正規表現の規則の並びから字句解析器を生成
+//!
+//! 各位置で全ての規則を試し、最も長くマッチした規則を採用する（最長一致）。
+//! 長さが同じ場合は先に追加した規則を優先する。
+use crate::{engine::Regex, helper::DynError};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 字句解析のエラーを表現するための型
+#[derive(Debug, PartialEq, Eq)]
+pub enum LexError {
+    Unmatched(usize, char), // どの規則にもマッチしない文字（位置、文字）
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::Unmatched(pos, c) => {
+                write!(f, "Unexpected character at position {}: '{}'", pos, c)
+            }
+        }
+    }
+}
+
+impl Error for LexError {}
+
+/// 字句解析器が生成するトークン
+#[derive(Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: &'a str, // マッチした規則の名前
+    pub text: String,  // マッチした文字列
+    pub pos: usize,    // 入力中の位置（文字単位）
+}
+
+/// 字句解析の規則
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    regex: Regex,
+    skip: bool, // マッチした文字列をトークンにせず読み飛ばす
+}
+
+/// 規則の並びから生成した字句解析器
+#[derive(Debug, Default)]
+pub struct Lexer {
+    rules: Vec<Rule>,
+}
+
+impl Lexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// トークンを生成する規則を追加
+    pub fn add_rule(&mut self, name: &str, pattern: &str) -> Result<(), DynError> {
+        self.push_rule(name, pattern, false)
+    }
+
+    /// 空白など、読み飛ばす規則を追加
+    pub fn add_skip_rule(&mut self, name: &str, pattern: &str) -> Result<(), DynError> {
+        self.push_rule(name, pattern, true)
+    }
+
+    fn push_rule(&mut self, name: &str, pattern: &str, skip: bool) -> Result<(), DynError> {
+        self.rules.push(Rule {
+            name: name.to_string(),
+            regex: Regex::new(pattern)?,
+            skip,
+        });
+        Ok(())
+    }
+
+    /// posから始まる最長一致の規則と、その終了位置を返す
+    ///
+    /// 空文字列へのマッチは、入力が進まなくなるためマッチとみなさない。
+    fn longest_match(
+        &self,
+        chars: &[char],
+        pos: usize,
+    ) -> Result<Option<(&Rule, usize)>, DynError> {
+        let mut best: Option<(&Rule, usize)> = None;
+        for rule in &self.rules {
+            if let Some(end) = rule.regex.longest_match_at(chars, pos)? {
+                if end > pos && best.is_none_or(|(_, best_end)| end > best_end) {
+                    best = Some((rule, end));
+                }
+            }
+        }
+        Ok(best)
+    }
+
+    /// 入力をトークン列に分割
+    pub fn tokenize(&self, input: &str) -> Result<Vec<Token<'_>>, DynError> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        while pos < chars.len() {
+            let Some((rule, end)) = self.longest_match(&chars, pos)? else {
+                return Err(Box::new(LexError::Unmatched(pos, chars[pos])));
+            };
+            if !rule.skip {
+                tokens.push(Token {
+                    kind: &rule.name,
+                    text: chars[pos..end].iter().collect(),
+                    pos,
+                });
+            }
+            pos = end;
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LexError, Lexer};
+
+    fn calc_lexer() -> Lexer {
+        let mut lexer = Lexer::new();
+        lexer.add_rule("mem", "mem").unwrap();
+        lexer.add_rule("ident", "[a-z][a-z0-9]*").unwrap();
+        lexer.add_rule("number", "[0-9]+(\\.[0-9]+)?").unwrap();
+        lexer.add_rule("op", "[-+*/]|\\*\\*").unwrap();
+        lexer.add_rule("lparen", "\\(").unwrap();
+        lexer.add_rule("rparen", "\\)").unwrap();
+        lexer.add_skip_rule("space", "[ \t]+").unwrap();
+        lexer
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let lexer = calc_lexer();
+        let tokens = lexer.tokenize("1+2.5 **(x1- mem)").unwrap();
+        let kinds: Vec<(&str, &str, usize)> = tokens
+            .iter()
+            .map(|t| (t.kind, t.text.as_str(), t.pos))
+            .collect();
+        assert_eq!(
+            vec![
+                ("number", "1", 0),
+                ("op", "+", 1),
+                ("number", "2.5", 2),
+                ("op", "**", 6),
+                ("lparen", "(", 8),
+                ("ident", "x1", 9),
+                ("op", "-", 11),
+                ("mem", "mem", 13),
+                ("rparen", ")", 16),
+            ],
+            kinds
+        );
+
+        // 長さが同じ場合は先の規則、長い場合は長い方が優先される
+        let tokens = lexer.tokenize("memory").unwrap();
+        assert_eq!("ident", tokens[0].kind);
+    }
+
+    #[test]
+    fn test_unmatched() {
+        let lexer = calc_lexer();
+        let err = lexer.tokenize("1 + #").unwrap_err();
+        assert_eq!(
+            Some(&LexError::Unmatched(4, '#')),
+            err.downcast_ref::<LexError>()
+        );
+    }
+}
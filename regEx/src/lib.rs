@@ -0,0 +1,5 @@
+pub mod engine;
+pub mod glob;
+pub mod helper;
+pub mod lexer;
+pub mod search;
@@ -1,3 +1,86 @@
-fn main() {
-    println!("Hello, world!");
+use clap::Parser;
+use reg_ex::{
+    engine::Regex,
+    glob::Glob,
+    search::{search, SearchOptions},
+};
+use std::{path::PathBuf, process::ExitCode};
+
+/// ディレクトリを再帰的に検索し、正規表現にマッチする行を表示する
+#[derive(Parser)]
+#[clap(version = "1.0")]
+struct App {
+    /// 正規表現
+    pattern: String,
+    /// 検索するファイルまたはディレクトリ
+    #[clap(default_value = ".")]
+    paths: Vec<PathBuf>,
+    /// 隠しファイルも検索する
+    #[clap(long)]
+    hidden: bool,
+    /// .gitignore、.ignoreを無視する
+    #[clap(long)]
+    no_ignore: bool,
+    /// 検索するファイルをglobで絞り込む（複数指定可）
+    #[clap(short, long)]
+    glob: Vec<String>,
+    /// 検索に使うスレッド数（0の場合はCPU数）
+    #[clap(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+}
+
+fn main() -> ExitCode {
+    let app = App::parse();
+    let regex = match Regex::new(&app.pattern) {
+        Ok(regex) => regex,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let globs = match app.glob.iter().map(|g| Glob::new(g)).collect() {
+        Ok(globs) => globs,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let options = SearchOptions {
+        hidden: app.hidden,
+        no_ignore: app.no_ignore,
+        threads: app.threads,
+        globs,
+    };
+    let results = match search(&regex, &app.paths, &options) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    // grepと同じく、マッチあり:0、マッチなし:1、エラー:2
+    let mut found = false;
+    let mut failed = false;
+    for result in results {
+        match result {
+            Ok(file) => {
+                found = true;
+                for m in file.lines {
+                    println!("{}:{}:{}", file.path.display(), m.line_number, m.line);
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::from(2)
+    } else if found {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
 }
@@ -0,0 +1,244 @@
+//! ディレクトリを再帰的にたどり、正規表現にマッチする行を並列に検索
+use crate::{engine::Regex, glob::Glob, helper::DynError};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// バイナリ判定のために先頭から調べるバイト数
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// 検索の設定
+#[derive(Debug, Default, Clone)]
+pub struct SearchOptions {
+    pub hidden: bool,     // 隠しファイルも検索する
+    pub no_ignore: bool,  // .gitignore、.ignoreを無視する
+    pub threads: usize,   // スレッド数（0の場合は自動）
+    pub globs: Vec<Glob>, // いずれかにマッチするファイルのみ検索する（空の場合は全て）
+}
+
+/// マッチした行
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineMatch {
+    pub line_number: usize, // 1始まりの行番号
+    pub line: String,
+}
+
+/// ファイルごとの検索結果
+#[derive(Debug)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub lines: Vec<LineMatch>,
+}
+
+/// 検索中のエラーを表現するための型
+#[derive(Debug)]
+pub enum SearchError {
+    Walk(ignore::Error),     // ディレクトリの走査に失敗
+    Io(PathBuf, io::Error),  // ファイルの読み込みに失敗
+    Eval(PathBuf, DynError), // マッチングに失敗
+}
+
+impl Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::Walk(e) => write!(f, "{}", e),
+            SearchError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SearchError::Eval(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for SearchError {}
+
+/// pathsを再帰的にたどり、検索対象のファイルを名前順に列挙
+///
+/// .gitignore、.ignore、隠しファイルの規則とglobによる絞り込みはoptionsに従って適用する。
+pub fn collect_files(
+    paths: &[PathBuf],
+    options: &SearchOptions,
+) -> Vec<Result<PathBuf, SearchError>> {
+    let mut files = Vec::new();
+    for root in paths {
+        let walker = WalkBuilder::new(root)
+            .hidden(!options.hidden)
+            .ignore(!options.no_ignore)
+            .git_ignore(!options.no_ignore)
+            .git_global(!options.no_ignore)
+            .git_exclude(!options.no_ignore)
+            .parents(!options.no_ignore)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        for entry in walker {
+            match entry {
+                Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => {
+                    let path = entry.into_path();
+                    match matches_globs(&options.globs, root, &path) {
+                        Ok(true) => files.push(Ok(path)),
+                        Ok(false) => (),
+                        Err(e) => files.push(Err(SearchError::Eval(path, e))),
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => files.push(Err(SearchError::Walk(e))),
+            }
+        }
+    }
+    files
+}
+
+/// globが指定されていなければ全てのパスを、指定されていればいずれかにマッチするパスを対象とする
+///
+/// globは走査を始めたディレクトリからの相対パスと照合する。
+fn matches_globs(globs: &[Glob], root: &Path, path: &Path) -> Result<bool, DynError> {
+    if globs.is_empty() {
+        return Ok(true);
+    }
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => path,
+    };
+    for glob in globs {
+        if glob.is_match_path(relative)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 先頭にNULバイトを含むファイルをバイナリとみなす
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// 1つのファイルを検索し、マッチした行を返す
+///
+/// バイナリファイルは検索せず、空の結果を返す。
+pub fn search_file(regex: &Regex, path: &Path) -> Result<Vec<LineMatch>, SearchError> {
+    let bytes = fs::read(path).map_err(|e| SearchError::Io(path.to_path_buf(), e))?;
+    if is_binary(&bytes) {
+        return Ok(Vec::new());
+    }
+
+    let mut lines = Vec::new();
+    for (i, line) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+        let is_match = regex
+            .is_match(line)
+            .map_err(|e| SearchError::Eval(path.to_path_buf(), e))?;
+        if is_match {
+            lines.push(LineMatch {
+                line_number: i + 1,
+                line: line.to_string(),
+            });
+        }
+    }
+    Ok(lines)
+}
+
+/// pathsの下にあるファイルをスレッドプールで並列に検索
+///
+/// 結果は並列度によらず、ファイルの走査順に並ぶ。マッチしなかったファイルは含まない。
+pub fn search(
+    regex: &Regex,
+    paths: &[PathBuf],
+    options: &SearchOptions,
+) -> Result<Vec<Result<FileMatches, SearchError>>, DynError> {
+    let files = collect_files(paths, options);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()?;
+
+    let results: Vec<_> = pool.install(|| {
+        files
+            .into_par_iter()
+            .map(|file| {
+                let path = file?;
+                let lines = search_file(regex, &path)?;
+                Ok(FileMatches { path, lines })
+            })
+            .collect()
+    });
+
+    Ok(results
+        .into_iter()
+        .filter(|r| !matches!(r, Ok(m) if m.lines.is_empty()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{search, SearchOptions};
+    use crate::{engine::Regex, glob::Glob};
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+        fs::write(root.join("b.txt"), "abc\nxyz\nabbc\n").unwrap();
+        fs::write(root.join("sub/a.txt"), "none\nabc\n").unwrap();
+        fs::write(root.join("ignored.txt"), "abc\n").unwrap();
+        fs::write(root.join(".hidden"), "abc\n").unwrap();
+        fs::write(root.join("bin.dat"), b"abc\0\n").unwrap();
+
+        let regex = Regex::new("ab+c").unwrap();
+        let paths = vec![root.to_path_buf()];
+        for threads in [1, 4] {
+            let options = SearchOptions {
+                threads,
+                ..Default::default()
+            };
+            let found: Vec<(PathBuf, Vec<usize>)> = search(&regex, &paths, &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| {
+                    let m = r.unwrap();
+                    (m.path, m.lines.iter().map(|l| l.line_number).collect())
+                })
+                .collect();
+            assert_eq!(
+                vec![
+                    (root.join("b.txt"), vec![1, 3]),
+                    (root.join("sub/a.txt"), vec![2]),
+                ],
+                found
+            );
+        }
+
+        // 除外設定と隠しファイルを無視する
+        let options = SearchOptions {
+            hidden: true,
+            no_ignore: true,
+            threads: 2,
+            ..Default::default()
+        };
+        let found: Vec<PathBuf> = search(&regex, &paths, &options)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.unwrap().path)
+            .collect();
+        assert!(found.contains(&root.join("ignored.txt")));
+        assert!(found.contains(&root.join(".hidden")));
+        assert!(!found.contains(&root.join("bin.dat")));
+
+        // globで絞り込む
+        let options = SearchOptions {
+            globs: vec![Glob::new("sub/*.txt").unwrap()],
+            ..Default::default()
+        };
+        let found: Vec<PathBuf> = search(&regex, &[PathBuf::from(root)], &options)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.unwrap().path)
+            .collect();
+        assert_eq!(vec![root.join("sub/a.txt")], found);
+    }
+}
@@ -0,0 +1,137 @@
+//! 同じ命令列に対して、バックトラック法、Pike VM、DFAが同じマッチを返すことを確認する
+//!
+//! ランダムに生成したパターンと入力で比較し、不一致があればproptestが最小の例まで縮小する。
+//! 縮小後の例はtests/differential.proptest-regressionsに保存され、以降の実行で最初に再検証される。
+use proptest::prelude::*;
+use reg_ex::engine::{
+    parser::{CharClass, AST},
+    Engine, Regex,
+};
+
+/// パターンに使う文字（特殊文字を含む）
+const ALPHABET: &[char] = &['a', 'b', 'c', '*', '-', '^'];
+
+/// ランダムな文字クラス
+fn arb_class() -> impl Strategy<Value = CharClass> {
+    let range = (
+        prop::sample::select(ALPHABET),
+        prop::sample::select(ALPHABET),
+    )
+        .prop_map(|(a, b)| if a <= b { (a, b) } else { (b, a) });
+    (prop::collection::vec(range, 0..3), any::<bool>())
+        .prop_map(|(ranges, negated)| CharClass { ranges, negated })
+}
+
+/// ランダムなAST
+fn arb_ast() -> impl Strategy<Value = AST> {
+    let leaf = prop_oneof![
+        4 => prop::sample::select(ALPHABET).prop_map(AST::Char),
+        1 => arb_class().prop_map(AST::Class),
+        1 => Just(AST::Start),
+        1 => Just(AST::End),
+    ];
+    leaf.prop_recursive(4, 24, 4, |inner| {
+        prop_oneof![
+            inner.clone().prop_map(|e| AST::Plus(Box::new(e))),
+            inner.clone().prop_map(|e| AST::Star(Box::new(e))),
+            inner.clone().prop_map(|e| AST::Question(Box::new(e))),
+            (inner.clone(), inner.clone()).prop_map(|(e1, e2)| AST::Or(Box::new(e1), Box::new(e2))),
+            prop::collection::vec(inner, 0..4).prop_map(AST::Seq),
+        ]
+    })
+}
+
+/// ASTを正規表現の文字列に戻す（正規表現の構文で書けない場合はNone）
+fn to_pattern(ast: &AST) -> Option<String> {
+    // 1文字以外は括弧で囲む
+    fn group(ast: &AST) -> Option<String> {
+        match ast {
+            AST::Char(_) => to_pattern(ast),
+            _ => Some(format!("({})", to_pattern(ast)?)),
+        }
+    }
+    let pattern = match ast {
+        AST::Char(c) if "\\+*?|()[].^$".contains(*c) => format!("\\{}", c),
+        AST::Char(c) => c.to_string(),
+        AST::Plus(e) => format!("{}+", group(e)?),
+        AST::Star(e) => format!("{}*", group(e)?),
+        AST::Question(e) => format!("{}?", group(e)?),
+        AST::Or(e1, e2) => format!("{}|{}", group(e1)?, group(e2)?),
+        AST::Seq(v) if v.is_empty() => return None,
+        AST::Seq(v) => v.iter().map(group).collect::<Option<_>>()?,
+        AST::Class(class) if class.ranges.is_empty() => return None,
+        AST::Class(class) => {
+            let escape = |c: char| {
+                if "\\]^-".contains(c) {
+                    format!("\\{}", c)
+                } else {
+                    c.to_string()
+                }
+            };
+            let mut pattern = String::from(if class.negated { "[^" } else { "[" });
+            for (lo, hi) in &class.ranges {
+                pattern += &escape(*lo);
+                if lo != hi {
+                    pattern += "-";
+                    pattern += &escape(*hi);
+                }
+            }
+            pattern + "]"
+        }
+        AST::Start => "^".to_string(),
+        AST::End => "$".to_string(),
+    };
+    Some(pattern)
+}
+
+/// 入力文字列（パターンに現れない文字を含む）
+fn arb_line() -> impl Strategy<Value = String> {
+    "[abc*^\\-d]{0,12}"
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn engines_agree(ast in arb_ast(), line in arb_line()) {
+        let regex = Regex::from_ast(&ast).unwrap();
+        let expected = regex.find_with(Engine::Backtrack, &line).unwrap();
+        for engine in [Engine::PikeVm, Engine::Dfa] {
+            let actual = regex.find_with(engine, &line).unwrap();
+            prop_assert_eq!(
+                &expected,
+                &actual,
+                "ast: {:?}, line: {:?}, engine: {:?}",
+                ast,
+                line,
+                engine
+            );
+        }
+    }
+
+    #[test]
+    fn parsed_pattern_agrees(ast in arb_ast(), line in arb_line()) {
+        // 正規表現の文字列を経由しても同じマッチになる
+        let Some(pattern) = to_pattern(&ast) else {
+            return Ok(());
+        };
+        let expected = Regex::from_ast(&ast).unwrap().find(&line).unwrap();
+        let actual = Regex::new(&pattern).unwrap().find(&line).unwrap();
+        prop_assert_eq!(expected, actual, "pattern: {:?}, line: {:?}", pattern, line);
+    }
+}
+
+#[test]
+fn engines_agree_on_empty_loops() {
+    for (pattern, line) in [
+        ("(a*)*b", "aaab"),
+        ("(a?)+", "aa"),
+        ("(a|b*)*c", "abbc"),
+        ("((a*)?)*", ""),
+    ] {
+        let regex = Regex::new(pattern).unwrap();
+        let expected = regex.find_with(Engine::Backtrack, line).unwrap();
+        assert_eq!(expected, regex.find_with(Engine::PikeVm, line).unwrap());
+        assert_eq!(expected, regex.find_with(Engine::Dfa, line).unwrap());
+    }
+}